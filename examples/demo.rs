@@ -13,21 +13,12 @@ pub fn point_cloud() -> LumpedObject {
         .geometries(vec![Geometry::new(GeometryType::Buffer {
             data: Box::new(BufferGeometryData {
                 attributes: BufferGeometryAttributes {
-                    position: BufferGeometryAttribute {
-                        item_size: 3,
-                        array: points,
-                        attribute_type: "Float32Array".to_string(),
-                        normalized: false,
-                    },
-                    color: BufferGeometryAttribute {
-                        item_size: 3,
-                        array: colors,
-                        attribute_type: "Float32Array".to_string(),
-                        normalized: false,
-                    },
+                    position: BufferGeometryAttribute::new(points),
+                    color: BufferGeometryAttribute::new(colors),
                     normal: None,
                     uv: None,
                 },
+                index: None,
             }),
         })])
         .material(
@@ -46,13 +37,13 @@ pub fn point_cloud() -> LumpedObject {
         .build()
 }
 
-fn valkyrie_head() -> LumpedObject {
-    LumpedObject::builder()
-        .image(Image::new("examples/data/HeadTextureMultisense.png"))
+fn valkyrie_head() -> Result<LumpedObject, Box<dyn Error>> {
+    Ok(LumpedObject::builder()
+        .image(Image::new("examples/data/HeadTextureMultisense.png")?)
         .texture(Texture::new(TextureType::new_image()))
-        .geometries(vec![Geometry::new(
-            utils::load_mesh("examples/data/head_multisense.obj").expect("Failed to load mesh"),
-        )])
+        .geometries(vec![Geometry::new(utils::load_mesh(
+            "examples/data/head_multisense.obj",
+        )?)])
         .object(Object::new(
             Isometry3::from_parts(
                 nalgebra::Translation3::new(0.0, 0.0, 0.0),
@@ -60,14 +51,14 @@ fn valkyrie_head() -> LumpedObject {
             ),
             ObjectType::Mesh,
         ))
-        .build()
+        .build())
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
-    let meshcat = Meshcat::new("tcp://127.0.0.1:6000");
+    let meshcat = Meshcat::new("tcp://127.0.0.1:6000")?;
 
-    meshcat.set_object("/head_1", valkyrie_head())?;
-    meshcat.set_object("/head_1/head", valkyrie_head())?;
+    meshcat.set_object("/head_1", valkyrie_head()?)?;
+    meshcat.set_object("/head_1/head", valkyrie_head()?)?;
     meshcat.set_transform(
         "/head_1/head",
         Isometry3::from_parts(
@@ -120,6 +111,7 @@ fn main() -> Result<(), Box<dyn Error>> {
                 tube: 0.2,
                 radial_segments: 12,
                 tubular_segments: 48,
+                arc: 2.0 * std::f64::consts::PI,
             })])
             .object(Object::new(
                 Isometry3::from_parts(
@@ -303,6 +295,10 @@ fn main() -> Result<(), Box<dyn Error>> {
                 radius: 0.5,
                 width_segments: 12,
                 height_segments: 12,
+                phi_start: 0.0,
+                phi_length: 2.0 * std::f64::consts::PI,
+                theta_start: 0.0,
+                theta_length: std::f64::consts::PI,
             })])
             .object(Object::new(
                 Isometry3::from_parts(
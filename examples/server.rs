@@ -0,0 +1,12 @@
+use std::error::Error;
+
+use meshcat::server::MeshcatServer;
+use meshcat::types::DEFAULT_ZMQ_URL;
+
+// A meshcat server without meshcat-python, e.g. for the demo
+// cargo run --example server --features server
+fn main() -> Result<(), Box<dyn Error>> {
+    let server = MeshcatServer::new(DEFAULT_ZMQ_URL, "127.0.0.1:7000")?;
+    println!("Open the viewer at {}", server.viewer_url()?);
+    server.run()
+}
@@ -0,0 +1,134 @@
+use std::error::Error;
+
+use arrow_array::{Array, Float32Array, Float64Array, RecordBatch, UInt8Array};
+use nalgebra::Matrix3xX;
+
+use super::types::LumpedObject;
+
+// Points without color columns are drawn in gray
+const DEFAULT_COLOR: f64 = 0.5;
+
+// Float columns are used as is, u8 columns are mapped from [0, 255] to [0, 1] (for colors)
+fn column_values(batch: &RecordBatch, name: &str) -> Result<Vec<f64>, Box<dyn Error>> {
+    let column = batch
+        .column_by_name(name)
+        .ok_or_else(|| format!("Missing column '{}'", name))?;
+    if column.null_count() > 0 {
+        return Err(format!("Column '{}' has null values", name).into());
+    }
+    let column = column.as_any();
+    if let Some(values) = column.downcast_ref::<Float64Array>() {
+        Ok(values.values().to_vec())
+    } else if let Some(values) = column.downcast_ref::<Float32Array>() {
+        Ok(values.values().iter().map(|value| *value as f64).collect())
+    } else if let Some(values) = column.downcast_ref::<UInt8Array>() {
+        Ok(values
+            .values()
+            .iter()
+            .map(|value| *value as f64 / 255.0)
+            .collect())
+    } else {
+        Err(format!("Column '{}' isn't a f64, f32 or u8 column", name).into())
+    }
+}
+
+fn columns_to_matrix(
+    batch: &RecordBatch,
+    names: [&str; 3],
+) -> Result<Matrix3xX<f64>, Box<dyn Error>> {
+    let columns = names
+        .iter()
+        .map(|name| column_values(batch, name))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(Matrix3xX::from_fn(batch.num_rows(), |row, column| {
+        columns[row][column]
+    }))
+}
+
+// Builds a point cloud (See utils::point_cloud) from a record batch with x/y/z columns and
+// optional r/g/b columns, e.g. a Polars DataFrame exported to Arrow
+pub fn point_cloud(batch: &RecordBatch, size: f64) -> Result<LumpedObject, Box<dyn Error>> {
+    let points = columns_to_matrix(batch, ["x", "y", "z"])?;
+    let colors = if batch.column_by_name("r").is_some() {
+        columns_to_matrix(batch, ["r", "g", "b"])?
+    } else {
+        Matrix3xX::from_element(batch.num_rows(), DEFAULT_COLOR)
+    };
+    Ok(crate::utils::point_cloud(points, colors, size))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use arrow_array::ArrayRef;
+    use nalgebra::Vector3;
+
+    use super::*;
+    use crate::types::GeometryType;
+
+    fn attributes(batch: &RecordBatch) -> (Matrix3xX<f64>, Matrix3xX<f64>) {
+        let lumped_object = point_cloud(batch, 0.01).unwrap();
+        let GeometryType::Buffer { data } = &lumped_object.geometries[0].geometry else {
+            panic!("Expected a buffer geometry");
+        };
+        (
+            data.attributes.position.to_matrix(),
+            data.attributes.color.to_matrix(),
+        )
+    }
+
+    #[test]
+    fn test_point_cloud() {
+        let batch = RecordBatch::try_from_iter([
+            (
+                "x",
+                Arc::new(Float64Array::from(vec![1.0, 4.0])) as ArrayRef,
+            ),
+            (
+                "y",
+                Arc::new(Float32Array::from(vec![2.0, 5.0])) as ArrayRef,
+            ),
+            (
+                "z",
+                Arc::new(Float64Array::from(vec![3.0, 6.0])) as ArrayRef,
+            ),
+        ])
+        .unwrap();
+        let (points, colors) = attributes(&batch);
+        assert_eq!(points.column(1), Vector3::new(4.0, 5.0, 6.0));
+        assert_eq!(colors.column(0), Vector3::repeat(DEFAULT_COLOR));
+
+        let batch = RecordBatch::try_from_iter([
+            ("x", Arc::new(Float64Array::from(vec![1.0])) as ArrayRef),
+            ("y", Arc::new(Float64Array::from(vec![2.0])) as ArrayRef),
+            ("z", Arc::new(Float64Array::from(vec![3.0])) as ArrayRef),
+            ("r", Arc::new(UInt8Array::from(vec![255])) as ArrayRef),
+            ("g", Arc::new(UInt8Array::from(vec![0])) as ArrayRef),
+            ("b", Arc::new(Float32Array::from(vec![0.5])) as ArrayRef),
+        ])
+        .unwrap();
+        let (_, colors) = attributes(&batch);
+        assert_eq!(colors.column(0), Vector3::new(1.0, 0.0, 0.5));
+    }
+
+    #[test]
+    fn test_invalid_columns() {
+        let batch = RecordBatch::try_from_iter([
+            ("x", Arc::new(Float64Array::from(vec![1.0])) as ArrayRef),
+            (
+                "y",
+                Arc::new(Float64Array::from(vec![Some(2.0)])) as ArrayRef,
+            ),
+        ])
+        .unwrap();
+        assert!(point_cloud(&batch, 0.01).is_err());
+        let batch = RecordBatch::try_from_iter([
+            ("x", Arc::new(Float64Array::from(vec![1.0])) as ArrayRef),
+            ("y", Arc::new(Float64Array::from(vec![None])) as ArrayRef),
+            ("z", Arc::new(Float64Array::from(vec![1.0])) as ArrayRef),
+        ])
+        .unwrap();
+        assert!(point_cloud(&batch, 0.01).is_err());
+    }
+}
@@ -0,0 +1,186 @@
+use std::borrow::Borrow;
+
+use log::info;
+use nalgebra::Isometry3;
+use serde::Serialize;
+use tmq::request_reply::RequestSender;
+use tokio::sync::Mutex;
+
+use crate::transport::Encoding;
+use crate::types::*;
+
+// Like Meshcat but the requests are awaited instead of blocking the thread until the server
+// replied, e.g. in a tokio control loop. The requests are sent one at a time (like Meshcat's
+// REQ socket), the socket of a request cancelled while waiting for its reply is replaced by a new
+// one (the cancelled request may or may not have reached the server)
+pub struct AsyncMeshcat {
+    context: tmq::Context,
+    endpoint: String,
+    // Taken while a request waits for its reply
+    socket: Mutex<Option<RequestSender>>,
+}
+
+impl AsyncMeshcat {
+    // Connects in the background, e.g. AsyncMeshcat::new(DEFAULT_ZMQ_URL) from a tokio runtime
+    pub fn new(endpoint: &str) -> Result<Self, crate::Error> {
+        let context = tmq::Context::new();
+        let socket = tmq::request(&context).connect(endpoint)?;
+        Ok(Self {
+            context,
+            endpoint: endpoint.to_string(),
+            socket: Mutex::new(Some(socket)),
+        })
+    }
+
+    async fn request(&self, frames: Vec<&[u8]>) -> Result<String, crate::Error> {
+        let mut guard = self.socket.lock().await;
+        let socket = match guard.take() {
+            Some(socket) => socket,
+            // The REQ socket of a cancelled (or failed) request still waits for its reply
+            None => tmq::request(&self.context).connect(&self.endpoint)?,
+        };
+        let request_type = String::from_utf8_lossy(frames[0]).to_string();
+        let (reply, socket) = socket.send(frames.into()).await?.recv().await?;
+        *guard = Some(socket);
+        let reply = reply
+            .iter()
+            .next()
+            .and_then(|message| message.as_str())
+            .ok_or(crate::Error::InvalidReply(request_type))?;
+        Ok(reply.to_string())
+    }
+
+    async fn send(
+        &self,
+        request_type: &str,
+        path: &str,
+        data: &impl Serialize,
+    ) -> Result<(), crate::Error> {
+        let data = Encoding::MessagePack
+            .to_vec(data)
+            .map_err(|err| crate::Error::Serialization(err.to_string().into()))?;
+        let reply = self
+            .request(vec![request_type.as_bytes(), path.as_bytes(), &data])
+            .await?;
+        info!("Received reply {}", reply);
+        Ok(())
+    }
+
+    // The url of the viewer served by the meshcat server
+    pub async fn url(&self) -> Result<String, crate::Error> {
+        self.request(vec![b"url"]).await
+    }
+
+    pub async fn set_object(
+        &self,
+        path: &str,
+        object: impl Borrow<LumpedObject>,
+    ) -> Result<(), crate::Error> {
+        let data = SetObjectData {
+            object: object.borrow(),
+            path: path.to_string(),
+            request_type: "set_object".to_string(),
+        };
+        self.send(&data.request_type, path, &data).await
+    }
+
+    pub async fn set_transform(
+        &self,
+        path: &str,
+        matrix: Isometry3<f64>,
+    ) -> Result<(), crate::Error> {
+        let data = SetTransformData::new(matrix, path);
+        self.send("set_transform", path, &data).await
+    }
+
+    pub async fn set_property(&self, path: &str, value: PropertyType) -> Result<(), crate::Error> {
+        let data = SetPropertyData::new(path, value);
+        self.send(data.request_type, path, &data).await
+    }
+
+    pub async fn delete(&self, path: &str) -> Result<(), crate::Error> {
+        let data = DeleteData {
+            path,
+            request_type: "delete",
+        };
+        self.send(data.request_type, path, &data).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_async_meshcat() {
+        let server = zmq::Context::new().socket(zmq::REP).unwrap();
+        server.bind("tcp://127.0.0.1:*").unwrap();
+        let endpoint = server.get_last_endpoint().unwrap().unwrap();
+        let requests = std::thread::spawn(move || {
+            let mut requests = Vec::new();
+            for _ in 0..3 {
+                let frames = server.recv_multipart(0).unwrap();
+                let reply = if frames[0] == b"url" {
+                    "http://viewer"
+                } else {
+                    "ok"
+                };
+                server.send(reply, 0).unwrap();
+                requests.push(String::from_utf8(frames[0].clone()).unwrap());
+            }
+            requests
+        });
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let meshcat = AsyncMeshcat::new(&endpoint).unwrap();
+            // Can be awaited in spawned tasks
+            fn assert_send(_: &impl Send) {}
+            assert_send(&meshcat.set_object("/box", crate::utils::triad(Isometry3::identity())));
+            assert_eq!(meshcat.url().await.unwrap(), "http://viewer");
+            meshcat
+                .set_transform("/box", Isometry3::translation(1.0, 0.0, 0.0))
+                .await
+                .unwrap();
+            meshcat.delete("/box").await.unwrap();
+        });
+        assert_eq!(requests.join().unwrap(), ["url", "set_transform", "delete"]);
+    }
+
+    #[test]
+    fn test_cancelled_request() {
+        let server = zmq::Context::new().socket(zmq::REP).unwrap();
+        server.bind("tcp://127.0.0.1:*").unwrap();
+        let endpoint = server.get_last_endpoint().unwrap().unwrap();
+        let (cancelled, wait_cancelled) = std::sync::mpsc::channel();
+        let requests = std::thread::spawn(move || {
+            let mut requests = Vec::new();
+            for _ in 0..2 {
+                let frames = server.recv_multipart(0).unwrap();
+                if requests.is_empty() {
+                    wait_cancelled.recv().unwrap();
+                }
+                server.send("ok", 0).unwrap();
+                requests.push(String::from_utf8(frames[0].clone()).unwrap());
+            }
+            requests
+        });
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let meshcat = AsyncMeshcat::new(&endpoint).unwrap();
+            let transform = meshcat.set_transform("/box", Isometry3::identity());
+            let timeout = std::time::Duration::from_millis(50);
+            assert!(tokio::time::timeout(timeout, transform).await.is_err());
+            cancelled.send(()).unwrap();
+            meshcat.delete("/box").await.unwrap();
+        });
+        assert_eq!(requests.join().unwrap(), ["set_transform", "delete"]);
+    }
+}
@@ -0,0 +1,170 @@
+use std::error::Error;
+use std::time::{Duration, Instant};
+
+use nalgebra::{Isometry3, Matrix3xX};
+use typed_builder::TypedBuilder;
+
+use crate::types::*;
+
+#[derive(Clone, Debug, TypedBuilder)]
+pub struct BenchConfig {
+    #[builder(default = 1000)]
+    pub transforms: usize,
+    #[builder(default = 100)]
+    pub objects: usize,
+    // The objects are point clouds of this many points
+    #[builder(default = 10_000)]
+    pub points: usize,
+    #[builder(default = "/meshcat_bench".to_string(), setter(into))]
+    pub path: String,
+}
+
+impl Default for BenchConfig {
+    fn default() -> Self {
+        BenchConfig::builder().build()
+    }
+}
+
+// The latencies of the requests of one kind (the time until the server replied)
+#[derive(Clone, Debug, PartialEq)]
+pub struct BenchStats {
+    pub count: usize,
+    // Requests per second
+    pub rate: f64,
+    // Encoded bytes per request
+    pub payload: usize,
+    // Bytes per second
+    pub bandwidth: f64,
+    pub p50: Duration,
+    pub p90: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+}
+
+impl BenchStats {
+    fn new(mut latencies: Vec<Duration>, payload: usize) -> Self {
+        latencies.sort();
+        let total = latencies.iter().sum::<Duration>().as_secs_f64();
+        let percentile = |p: f64| {
+            let index = ((p * latencies.len() as f64).ceil() as usize).max(1) - 1;
+            latencies.get(index).copied().unwrap_or_default()
+        };
+        let rate = if total > 0.0 {
+            latencies.len() as f64 / total
+        } else {
+            0.0
+        };
+        BenchStats {
+            count: latencies.len(),
+            rate,
+            payload,
+            bandwidth: rate * payload as f64,
+            p50: percentile(0.5),
+            p90: percentile(0.9),
+            p99: percentile(0.99),
+            max: latencies.last().copied().unwrap_or_default(),
+        }
+    }
+}
+
+impl std::fmt::Display for BenchStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} requests, {:.0}/s, {} bytes each ({:.2} MB/s), p50 {:?} p90 {:?} p99 {:?} max {:?}",
+            self.count,
+            self.rate,
+            self.payload,
+            self.bandwidth / 1e6,
+            self.p50,
+            self.p90,
+            self.p99,
+            self.max
+        )
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct BenchReport {
+    pub set_transform: BenchStats,
+    pub set_object: BenchStats,
+}
+
+impl std::fmt::Display for BenchReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "set_transform: {}", self.set_transform)?;
+        write!(f, "set_object: {}", self.set_object)
+    }
+}
+
+fn time(request: impl FnOnce() -> Result<(), Box<dyn Error>>) -> Result<Duration, Box<dyn Error>> {
+    let start = Instant::now();
+    request()?;
+    Ok(start.elapsed())
+}
+
+// Measures how fast the transforms and objects can be sent to a live server (or through any
+// other transport), the objects are published under config.path which is deleted at the end
+pub fn run(meshcat: &Meshcat, config: &BenchConfig) -> Result<BenchReport, Box<dyn Error>> {
+    let path = format!("{}/object", config.path);
+    let transform_latencies = (0..config.transforms)
+        .map(|i| {
+            let pose = Isometry3::translation(i as f64 / config.transforms as f64, 0.0, 0.0);
+            time(|| meshcat.set_transform(&path, pose))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let transform_payload = meshcat
+        .encoding()
+        .to_vec(&SetTransformData::new(Isometry3::identity(), &path))?
+        .len();
+
+    let object = crate::utils::point_cloud(
+        Matrix3xX::from_fn(config.points, |row, column| (row + column) as f64),
+        Matrix3xX::from_element(config.points, 0.5),
+        0.01,
+    );
+    let object_latencies = (0..config.objects)
+        .map(|_| time(|| meshcat.set_object(&path, &object)))
+        .collect::<Result<Vec<_>, _>>()?;
+    let object_payload = meshcat
+        .encoding()
+        .to_vec(&SetObjectData {
+            object: &object,
+            path: path.clone(),
+            request_type: "set_object".to_string(),
+        })?
+        .len();
+    meshcat.delete(&config.path)?;
+    Ok(BenchReport {
+        set_transform: BenchStats::new(transform_latencies, transform_payload),
+        set_object: BenchStats::new(object_latencies, object_payload),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::Recorder;
+
+    #[test]
+    fn test_bench() {
+        let latencies = (1..=100).map(Duration::from_millis).collect();
+        let stats = BenchStats::new(latencies, 10);
+        assert_eq!(stats.p50, Duration::from_millis(50));
+        assert_eq!(stats.p99, Duration::from_millis(99));
+        assert_eq!(stats.max, Duration::from_millis(100));
+        assert!((stats.rate - 100.0 / 5.05).abs() < 1e-9);
+        assert!((stats.bandwidth - stats.rate * 10.0).abs() < 1e-9);
+
+        let meshcat = Meshcat::with_transport(Recorder::default());
+        let config = BenchConfig::builder()
+            .transforms(10)
+            .objects(2)
+            .points(10)
+            .build();
+        let report = run(&meshcat, &config).unwrap();
+        assert_eq!(report.set_transform.count, 10);
+        assert_eq!(report.set_object.count, 2);
+        assert!(report.set_object.payload > report.set_transform.payload);
+    }
+}
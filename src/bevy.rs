@@ -0,0 +1,292 @@
+use std::collections::HashMap;
+use std::error::Error;
+
+use bevy_app::{App, Plugin, PostUpdate, Startup};
+use bevy_asset::{Assets, Handle};
+use bevy_ecs::prelude::*;
+use bevy_mesh::{Mesh, PrimitiveTopology, VertexAttributeValues};
+use bevy_transform::components::GlobalTransform;
+use bevy_transform::TransformSystems;
+use log::warn;
+use nalgebra::{Isometry3, Matrix3xX, Quaternion, Translation3, UnitQuaternion, Vector3};
+
+use crate::types::*;
+
+// Mirrors the entity to {root}/{path} in the viewer, the mesh is re-sent when the component
+// changes and the transform when the GlobalTransform changes (its scale is ignored)
+#[derive(Component, Clone, Debug)]
+pub struct MeshcatSync {
+    pub path: String,
+    pub mesh: Handle<Mesh>,
+    pub color: u32,
+}
+
+// Publishes the entities with a MeshcatSync component every frame. Insert a Meshcat as a
+// non-send resource before adding the plugin to use another endpoint or transport
+pub struct MeshcatPlugin {
+    // Bevy is y up, the root is rotated so the scene is z up in the viewer
+    pub root: String,
+}
+
+impl Default for MeshcatPlugin {
+    fn default() -> Self {
+        Self {
+            root: "/bevy".to_string(),
+        }
+    }
+}
+
+#[derive(Resource)]
+struct Root(String);
+
+impl Plugin for MeshcatPlugin {
+    fn build(&self, app: &mut App) {
+        if !app.world().contains_non_send::<Meshcat>() {
+            let meshcat = Meshcat::new(DEFAULT_ZMQ_URL).unwrap_or_else(|err| {
+                panic!("Failed to connect to '{}': {}", DEFAULT_ZMQ_URL, err)
+            });
+            app.insert_non_send(meshcat);
+        }
+        app.insert_resource(Root(self.root.clone()))
+            .add_systems(Startup, publish_root)
+            .add_systems(
+                PostUpdate,
+                (publish_meshes, publish_transforms)
+                    .chain()
+                    .after(TransformSystems::Propagate),
+            );
+    }
+}
+
+fn publish_root(meshcat: NonSend<Meshcat>, root: Res<Root>) {
+    let y_up = Isometry3::rotation(Vector3::x() * std::f64::consts::FRAC_PI_2);
+    if let Err(err) = meshcat.set_transform(&root.0, y_up) {
+        warn!("Failed to publish '{}': {}", root.0, err);
+    }
+}
+
+fn publish_meshes(
+    meshcat: NonSend<Meshcat>,
+    root: Res<Root>,
+    meshes: Res<Assets<Mesh>>,
+    query: Query<(Entity, Ref<MeshcatSync>, Option<&GlobalTransform>)>,
+    mut removed: RemovedComponents<MeshcatSync>,
+    // The paths the entities were published to
+    mut published: Local<HashMap<Entity, String>>,
+) {
+    for entity in removed.read() {
+        if let Some(path) = published.remove(&entity) {
+            if let Err(err) = meshcat.delete(&path) {
+                warn!("Failed to delete '{}': {}", path, err);
+            }
+        }
+    }
+    for (entity, sync, transform) in &query {
+        if !sync.is_changed() && published.contains_key(&entity) {
+            continue;
+        }
+        // Not loaded yet, tried again next frame
+        let Some(mesh) = meshes.get(&sync.mesh) else {
+            continue;
+        };
+        let path = format!("{}/{}", root.0, sync.path.trim_start_matches('/'));
+        let result =
+            lumped_object(mesh, sync.color).and_then(|object| meshcat.set_object(&path, object));
+        if let Err(err) = result {
+            warn!("Failed to publish '{}': {}", path, err);
+        }
+        match published.insert(entity, path.clone()) {
+            Some(previous) if previous != path => {
+                if let Err(err) = meshcat.delete(&previous) {
+                    warn!("Failed to delete '{}': {}", previous, err);
+                }
+                // The transform is only re-sent when it changes (See publish_transforms)
+                if let Some(transform) = transform {
+                    if let Err(err) = meshcat.set_transform(&path, isometry(transform)) {
+                        warn!("Failed to publish the transform of '{}': {}", path, err);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn publish_transforms(
+    meshcat: NonSend<Meshcat>,
+    root: Res<Root>,
+    query: Query<(&MeshcatSync, &GlobalTransform), Changed<GlobalTransform>>,
+) {
+    for (sync, transform) in &query {
+        let path = format!("{}/{}", root.0, sync.path.trim_start_matches('/'));
+        if let Err(err) = meshcat.set_transform(&path, isometry(transform)) {
+            warn!("Failed to publish the transform of '{}': {}", path, err);
+        }
+    }
+}
+
+fn isometry(transform: &GlobalTransform) -> Isometry3<f64> {
+    let (_, rotation, translation) = transform.to_scale_rotation_translation();
+    Isometry3::from_parts(
+        Translation3::new(
+            translation.x as f64,
+            translation.y as f64,
+            translation.z as f64,
+        ),
+        UnitQuaternion::from_quaternion(Quaternion::new(
+            rotation.w as f64,
+            rotation.x as f64,
+            rotation.y as f64,
+            rotation.z as f64,
+        )),
+    )
+}
+
+fn float3(mesh: &Mesh, attribute: bevy_mesh::MeshVertexAttribute) -> Option<&[[f32; 3]]> {
+    mesh.try_attribute_option(attribute)
+        .ok()
+        .flatten()
+        .and_then(VertexAttributeValues::as_float3)
+}
+
+// The triangles of the mesh as a (non indexed) buffer geometry
+fn lumped_object(mesh: &Mesh, color: u32) -> Result<LumpedObject, Box<dyn Error>> {
+    if mesh.primitive_topology() != PrimitiveTopology::TriangleList {
+        return Err(format!(
+            "Unsupported primitive topology {:?}, only triangle lists are supported",
+            mesh.primitive_topology()
+        )
+        .into());
+    }
+    let positions = float3(mesh, Mesh::ATTRIBUTE_POSITION)
+        .ok_or("The mesh has no positions (or was only kept in the render world)")?;
+    let normals = float3(mesh, Mesh::ATTRIBUTE_NORMAL);
+    let indices = match mesh.try_indices_option()? {
+        Some(indices) => indices.iter().collect::<Vec<_>>(),
+        None => (0..positions.len()).collect(),
+    };
+    let to_matrix = |values: &[[f32; 3]]| -> Result<Matrix3xX<f64>, Box<dyn Error>> {
+        let columns = indices
+            .iter()
+            .map(|&index| {
+                values
+                    .get(index)
+                    .ok_or_else(|| format!("Index {} is out of bounds", index))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        // from_columns panics on an empty mesh
+        Ok(Matrix3xX::from_iterator(
+            columns.len(),
+            columns.iter().flat_map(|column| column.map(f64::from)),
+        ))
+    };
+    let attribute = BufferGeometryAttribute::new;
+    let position = to_matrix(positions)?;
+    let normal = normals.map(to_matrix).transpose()?;
+    let geometry = GeometryType::Buffer {
+        data: Box::new(BufferGeometryData {
+            attributes: BufferGeometryAttributes {
+                // The material color is used, not the vertex colors
+                color: attribute(Matrix3xX::from_element(position.ncols(), 1.0)),
+                position: attribute(position),
+                normal: normal.map(attribute),
+                uv: None,
+            },
+            index: None,
+        }),
+    };
+    Ok(LumpedObject::builder()
+        .geometries(vec![Geometry::new(geometry)])
+        .material(
+            Material::builder()
+                .material_type(MaterialType::MeshLambert)
+                .color(color)
+                .build(),
+        )
+        .build())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy_asset::RenderAssetUsages;
+    use bevy_mesh::Indices;
+
+    #[test]
+    fn test_lumped_object() {
+        let mesh = Mesh::new(PrimitiveTopology::TriangleList, RenderAssetUsages::all())
+            .with_inserted_attribute(
+                Mesh::ATTRIBUTE_POSITION,
+                vec![
+                    [0.0, 0.0, 0.0],
+                    [1.0, 0.0, 0.0],
+                    [0.0, 1.0, 0.0],
+                    [1.0, 1.0, 0.0],
+                ],
+            )
+            .with_inserted_indices(Indices::U16(vec![0, 1, 2, 2, 1, 3]));
+        let object = lumped_object(&mesh, 0xff0000).unwrap();
+        let GeometryType::Buffer { data } = &object.geometries[0].geometry else {
+            panic!("Expected a buffer geometry");
+        };
+        let position = &data.attributes.position.to_matrix();
+        assert_eq!(position.ncols(), 6);
+        assert_eq!(position.column(5), Vector3::new(1.0, 1.0, 0.0));
+        assert!(data.attributes.normal.is_none());
+        assert_eq!(object.material.color, Some(0xff0000));
+
+        let empty = Mesh::new(PrimitiveTopology::TriangleList, RenderAssetUsages::all())
+            .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, Vec::<[f32; 3]>::new());
+        let object = lumped_object(&empty, 0xff0000).unwrap();
+        let GeometryType::Buffer { data } = &object.geometries[0].geometry else {
+            panic!("Expected a buffer geometry");
+        };
+        assert_eq!(data.attributes.position.to_matrix().ncols(), 0);
+
+        let lines = Mesh::new(PrimitiveTopology::LineList, RenderAssetUsages::all());
+        assert!(lumped_object(&lines, 0xff0000).is_err());
+    }
+
+    #[test]
+    fn test_moved_path() {
+        let recorder = crate::transport::Recorder::default();
+        let mut app = App::new();
+        app.insert_non_send(Meshcat::with_transport(recorder.clone()))
+            .init_resource::<Assets<Mesh>>()
+            .add_plugins(MeshcatPlugin::default());
+        let mesh = Mesh::new(PrimitiveTopology::TriangleList, RenderAssetUsages::all())
+            .with_inserted_attribute(
+                Mesh::ATTRIBUTE_POSITION,
+                vec![[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]],
+            );
+        let mesh = app.world_mut().resource_mut::<Assets<Mesh>>().add(mesh);
+        let sync = MeshcatSync {
+            path: "/a".to_string(),
+            mesh,
+            color: 0xffffff,
+        };
+        let entity = app
+            .world_mut()
+            .spawn((sync, GlobalTransform::default()))
+            .id();
+        app.update();
+        recorder.take();
+
+        app.world_mut().get_mut::<MeshcatSync>(entity).unwrap().path = "/b".to_string();
+        app.update();
+        let requests = recorder
+            .take()
+            .into_iter()
+            .map(|(request_type, path, _)| (request_type, path))
+            .collect::<Vec<_>>();
+        let request = |request_type: &str, path: &str| (request_type.to_string(), path.to_string());
+        assert_eq!(
+            requests,
+            [
+                request("set_object", "/bevy/b"),
+                request("delete", "/bevy/a"),
+                request("set_transform", "/bevy/b"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,57 @@
+// The errors of the client's functions that connect or read files (See lib.rs)
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Unable to read '{path}': {source}")]
+    Io {
+        path: String,
+        source: std::io::Error,
+    },
+    #[cfg(feature = "zmq")]
+    #[error("ZMQ error: {0}")]
+    Zmq(#[from] zmq::Error),
+    // Connecting a transport other than zmq (e.g. a WebSocket on wasm32)
+    #[error("Failed to connect to '{endpoint}': {message}")]
+    Connection { endpoint: String, message: String },
+    #[error("Failed to serialize: {0}")]
+    Serialization(#[source] Box<dyn std::error::Error + Send + Sync>),
+    // A file whose extension isn't supported (or that has none)
+    #[error("Unsupported format of '{0}'")]
+    UnsupportedFormat(String),
+    // The frames per second of an ImageSequence, it has to be finite and positive
+    #[error("Invalid frame rate {0}")]
+    InvalidFrameRate(f64),
+    // A package:// uri of a URDF whose package isn't in the package paths
+    #[error(
+        "Package '{package}' of '{uri}' not found in {package_paths:?} (See ROS_PACKAGE_PATH)"
+    )]
+    PackageNotFound {
+        package: String,
+        uri: String,
+        package_paths: Vec<std::path::PathBuf>,
+    },
+    // A geometry that meshcat can't draw as a single geometry
+    #[error("Unsupported geometry: {0}")]
+    UnsupportedGeometry(String),
+    // The errors of AsyncMeshcat's socket
+    #[cfg(feature = "tokio")]
+    #[error(transparent)]
+    Tmq(#[from] tmq::TmqError),
+    // A reply of the meshcat server that isn't valid utf-8
+    #[error("The reply to '{0}' isn't valid utf-8")]
+    InvalidReply(String),
+    // An argument that the function can't accept, e.g. a window of 0 requests
+    #[error("Invalid argument: {0}")]
+    InvalidArgument(String),
+}
+
+impl From<rmp_serde::encode::Error> for Error {
+    fn from(err: rmp_serde::encode::Error) -> Self {
+        Error::Serialization(Box::new(err))
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Self {
+        Error::Serialization(Box::new(err))
+    }
+}
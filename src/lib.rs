@@ -1,2 +1,38 @@
+#[cfg(feature = "arrow")]
+pub mod arrow;
+#[cfg(feature = "tokio")]
+pub mod async_client;
+pub mod bench;
+#[cfg(feature = "bevy")]
+pub mod bevy;
+mod error;
+#[cfg(feature = "ndarray")]
+pub mod ndarray;
+#[cfg(feature = "rayon")]
+mod parallel;
+pub mod path;
+#[cfg(feature = "python")]
+mod python;
+pub mod scenario;
+pub mod scene;
+#[cfg(feature = "server")]
+pub mod server;
+pub mod shapes;
+pub mod shared;
+pub mod stream;
+pub mod transport;
+pub mod tree;
 pub mod types;
+pub mod urdf;
 pub mod utils;
+#[cfg(feature = "watch")]
+pub mod watch;
+
+// Error is returned by the client's functions that connect or read files (Meshcat::new and
+// try_new, the transports, Image::new, ImageSequence::new, utils::load_mesh and mesh_file) so the
+// cause can be matched on. The other functions, whose errors are invalid arguments or failed
+// requests, return a Box<dyn Error> with a message, which an Error converts into
+pub use error::Error;
+
+// Re-exported for the meshcat! macro and so users get the same nalgebra version
+pub use nalgebra;
@@ -0,0 +1,74 @@
+use std::error::Error;
+
+use ::ndarray::ArrayView2;
+use nalgebra::Matrix3xX;
+
+use super::types::{BufferGeometryAttribute, LumpedObject};
+
+// Converts an N x 3 array (one point per row) to the 3 x N matrix used by the buffer attributes
+pub fn to_matrix<T: Copy + Into<f64>>(
+    array: ArrayView2<T>,
+) -> Result<Matrix3xX<f64>, Box<dyn Error>> {
+    if array.ncols() != 3 {
+        return Err(format!("Expected an N x 3 array, got {:?}", array.shape()).into());
+    }
+    Ok(Matrix3xX::from_fn(array.nrows(), |row, column| {
+        array[(column, row)].into()
+    }))
+}
+
+pub fn buffer_attribute<T: Copy + Into<f64>>(
+    array: ArrayView2<T>,
+) -> Result<BufferGeometryAttribute, Box<dyn Error>> {
+    Ok(BufferGeometryAttribute::new(to_matrix(array)?))
+}
+
+// See utils::point_cloud
+pub fn point_cloud<T: Copy + Into<f64>>(
+    points: ArrayView2<T>,
+    colors: ArrayView2<T>,
+    size: f64,
+) -> Result<LumpedObject, Box<dyn Error>> {
+    if points.nrows() != colors.nrows() {
+        return Err(format!(
+            "Got {} points but {} colors",
+            points.nrows(),
+            colors.nrows()
+        )
+        .into());
+    }
+    Ok(crate::utils::point_cloud(
+        to_matrix(points)?,
+        to_matrix(colors)?,
+        size,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use ::ndarray::{array, Array2};
+    use nalgebra::Vector3;
+
+    use super::*;
+
+    #[test]
+    fn test_to_matrix() {
+        let points = array![[1.0_f32, 2.0, 3.0], [4.0, 5.0, 6.0]];
+        let matrix = to_matrix(points.view()).unwrap();
+        assert_eq!(matrix.ncols(), 2);
+        assert_eq!(matrix.column(1), Vector3::new(4.0, 5.0, 6.0));
+        // Non-contiguous views work too
+        let matrix = to_matrix(points.t().t()).unwrap();
+        assert_eq!(matrix.column(0), Vector3::new(1.0, 2.0, 3.0));
+        assert!(to_matrix(points.t()).is_err());
+    }
+
+    #[test]
+    fn test_point_cloud() {
+        let points = Array2::<f64>::zeros((10, 3));
+        let colors = Array2::<f64>::ones((10, 3));
+        assert!(point_cloud(points.view(), colors.view(), 0.01).is_ok());
+        let colors = Array2::<f64>::ones((9, 3));
+        assert!(point_cloud(points.view(), colors.view(), 0.01).is_err());
+    }
+}
@@ -0,0 +1,134 @@
+use std::cell::RefCell;
+use std::error::Error;
+
+use rayon::prelude::*;
+use serde::Serialize;
+
+use crate::types::AttributeArray;
+
+// The buffer attributes with at least this many values are encoded in parallel
+pub(crate) const MIN_PARALLEL_VALUES: usize = 1 << 18;
+// The number of values encoded by each task
+const CHUNK_VALUES: usize = 1 << 16;
+
+// The arrays encoded while serializing a request, each one is serialized as a placeholder string
+// (the prefix and its index) which is replaced by the encoded array afterwards
+const PLACEHOLDER_PREFIX: &str = "\0meshcat-deferred-array-";
+
+thread_local! {
+    static DEFERRED_ARRAYS: RefCell<Option<Vec<Vec<u8>>>> = const { RefCell::new(None) };
+}
+
+fn placeholder(index: usize) -> String {
+    format!("{}{}", PLACEHOLDER_PREFIX, index)
+}
+
+// Like rmp_serde::encode::write_named but the large buffer attributes are encoded on the rayon
+// pool, then stitched in the stream (See Encoding::object_to_vec)
+pub(crate) fn write_named(buf: &mut Vec<u8>, data: &impl Serialize) -> Result<(), Box<dyn Error>> {
+    DEFERRED_ARRAYS.with_borrow_mut(|deferred| *deferred = Some(Vec::new()));
+    let start = buf.len();
+    let result = rmp_serde::encode::write_named(&mut *buf, data);
+    let arrays = DEFERRED_ARRAYS
+        .with_borrow_mut(Option::take)
+        .unwrap_or_default();
+    result?;
+    if arrays.is_empty() {
+        return Ok(());
+    }
+    // The placeholders are in the stream in the order the arrays were deferred
+    let encoded = buf.split_off(start);
+    let mut rest = encoded.as_slice();
+    for (index, array) in arrays.iter().enumerate() {
+        let mut placeholder_bytes = Vec::new();
+        rmp::encode::write_str(&mut placeholder_bytes, &placeholder(index))?;
+        let start = rest
+            .windows(placeholder_bytes.len())
+            .position(|window| window == placeholder_bytes)
+            .ok_or("A deferred array is missing from the encoded data")?;
+        buf.extend_from_slice(&rest[..start]);
+        buf.extend_from_slice(array);
+        rest = &rest[start + placeholder_bytes.len()..];
+    }
+    buf.extend_from_slice(rest);
+    Ok(())
+}
+
+// Encodes the little endian bytes of the array in parallel and returns the placeholder to
+// serialize instead of its msgpack extension, None when the array is small or not serialized by
+// write_named
+pub(crate) fn defer_array(array: &AttributeArray) -> Result<Option<String>, Box<dyn Error>> {
+    if array.len() < MIN_PARALLEL_VALUES
+        || DEFERRED_ARRAYS.with_borrow(|deferred| deferred.is_none())
+    {
+        return Ok(None);
+    }
+    let value_size = array.value_size();
+    let chunks = (0..array.len())
+        .step_by(CHUNK_VALUES)
+        .collect::<Vec<_>>()
+        .into_par_iter()
+        .map(|start| {
+            let end = (start + CHUNK_VALUES).min(array.len());
+            let mut buf = Vec::with_capacity((end - start) * value_size);
+            array.extend_le_bytes(start..end, &mut buf);
+            buf
+        })
+        .collect::<Vec<_>>();
+    let len = array.len() * value_size;
+    let mut encoded = Vec::with_capacity(len + 6);
+    rmp::encode::write_ext_meta(&mut encoded, len as u32, array.array_type().ext_type())?;
+    for chunk in chunks {
+        encoded.extend_from_slice(&chunk);
+    }
+    Ok(DEFERRED_ARRAYS.with_borrow_mut(|deferred| {
+        deferred.as_mut().map(|deferred| {
+            deferred.push(encoded);
+            placeholder(deferred.len() - 1)
+        })
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::*;
+    use nalgebra::Matrix3xX;
+
+    #[test]
+    fn test_parallel_encoding() {
+        let points = MIN_PARALLEL_VALUES / 3 + 1;
+        let position = Matrix3xX::from_fn(points, |row, column| (row * column) as f64 * 0.1);
+        let color = Matrix3xX::from_fn(points, |row, _| row as f64 / 2.0);
+        let geometry = GeometryType::Buffer {
+            data: Box::new(BufferGeometryData {
+                attributes: BufferGeometryAttributes {
+                    position: BufferGeometryAttribute::new(position.clone()),
+                    color: BufferGeometryAttribute::quantized(color, ArrayType::Uint8).unwrap(),
+                    normal: Some(BufferGeometryAttribute::with_type(
+                        position,
+                        ArrayType::Float64,
+                    )),
+                    uv: None,
+                },
+                index: None,
+            }),
+        };
+        let object = LumpedObject::builder()
+            .geometries(vec![Geometry::new(geometry)])
+            .material(Material::builder().build())
+            .build();
+        let mut buf = Vec::new();
+        write_named(&mut buf, &object).unwrap();
+        assert_eq!(buf, rmp_serde::encode::to_vec_named(&object).unwrap());
+        assert!(DEFERRED_ARRAYS.with_borrow(Option::is_none));
+
+        // Encoding doesn't draw from the seeded uuids
+        set_uuid_seed(Some(7));
+        let expected = crate::types::new_uuid();
+        set_uuid_seed(Some(7));
+        write_named(&mut Vec::new(), &object).unwrap();
+        assert_eq!(crate::types::new_uuid(), expected);
+        set_uuid_seed(None);
+    }
+}
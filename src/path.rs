@@ -0,0 +1,137 @@
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+// A meshcat path, / separated names starting with a /, e.g. /robot/arm/link3/visual. The
+// Meshcat functions take any AsRef<str> so a ScenePath, a &str or a String can be passed, the
+// strings are checked like ScenePath::new (without allocating) and rejected if invalid
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ScenePath(String);
+
+// The names can't be empty, "<object>" (the object of a path) is a name
+fn check_names(path: &str, names: &str) -> Result<(), Box<dyn Error>> {
+    if names.split('/').any(str::is_empty) {
+        return Err(format!("Invalid path '{}', it has an empty name", path).into());
+    }
+    Ok(())
+}
+
+// The path if it's a valid ScenePath
+pub(crate) fn checked(path: &str) -> Result<&str, Box<dyn Error>> {
+    let names = path
+        .strip_prefix('/')
+        .ok_or_else(|| format!("Invalid path '{}', it doesn't start with /", path))?;
+    if !names.is_empty() {
+        check_names(path, names)?;
+    }
+    Ok(path)
+}
+
+impl ScenePath {
+    // The scene, its path is /
+    pub fn root() -> Self {
+        ScenePath("/".to_string())
+    }
+
+    pub fn new(path: &str) -> Result<Self, Box<dyn Error>> {
+        Ok(ScenePath(checked(path)?.to_string()))
+    }
+
+    // Appends one name or a relative path, e.g. ScenePath::new("/robot")?.join("arm/link3")
+    pub fn join(&self, relative: &str) -> Result<Self, Box<dyn Error>> {
+        check_names(relative, relative)?;
+        Ok(ScenePath(match self.is_root() {
+            true => format!("/{}", relative),
+            false => format!("{}/{}", self.0, relative),
+        }))
+    }
+
+    // None for the root
+    pub fn parent(&self) -> Option<Self> {
+        let (parent, _) = self.0.rsplit_once('/')?;
+        match (self.is_root(), parent.is_empty()) {
+            (true, _) => None,
+            (false, true) => Some(Self::root()),
+            (false, false) => Some(ScenePath(parent.to_string())),
+        }
+    }
+
+    // The last name, None for the root
+    pub fn name(&self) -> Option<&str> {
+        self.0.rsplit('/').next().filter(|name| !name.is_empty())
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.0 == "/"
+    }
+
+    // Whether self is other or one of its descendants
+    pub fn starts_with(&self, other: &ScenePath) -> bool {
+        other.is_root()
+            || self
+                .0
+                .strip_prefix(&other.0)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for ScenePath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl FromStr for ScenePath {
+    type Err = Box<dyn Error>;
+
+    fn from_str(path: &str) -> Result<Self, Self::Err> {
+        ScenePath::new(path)
+    }
+}
+
+impl AsRef<str> for ScenePath {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<ScenePath> for String {
+    fn from(path: ScenePath) -> Self {
+        path.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scene_path() {
+        let robot = ScenePath::new("/robot").unwrap();
+        let link = robot.join("arm/link3").unwrap().join("visual").unwrap();
+        assert_eq!(link.to_string(), "/robot/arm/link3/visual");
+        assert_eq!(link.name(), Some("visual"));
+        assert_eq!(link.parent().unwrap().as_str(), "/robot/arm/link3");
+        assert!(link.starts_with(&robot));
+        assert!(!ScenePath::new("/robots").unwrap().starts_with(&robot));
+        assert_eq!(robot.parent(), Some(ScenePath::root()));
+        assert_eq!(ScenePath::root().parent(), None);
+        assert_eq!(ScenePath::root().join("a").unwrap().as_str(), "/a");
+        assert_eq!(
+            "/Cameras/default/rotated/<object>"
+                .parse::<ScenePath>()
+                .unwrap()
+                .name(),
+            Some("<object>")
+        );
+        for invalid in ["", "robot", "/robot/", "//robot", "/robot//arm"] {
+            assert!(ScenePath::new(invalid).is_err(), "{}", invalid);
+        }
+        assert!(robot.join("/arm").is_err());
+        assert!(robot.join("").is_err());
+    }
+}
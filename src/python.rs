@@ -0,0 +1,224 @@
+use std::error::Error;
+
+use nalgebra::{Isometry3, Matrix3xX, Translation3, UnitQuaternion, Vector3};
+use pyo3::exceptions::PyRuntimeError;
+use pyo3::prelude::*;
+
+use crate::scene::{ObjectDescription, ShapeDescription};
+use crate::types::*;
+
+fn to_py_err(err: Box<dyn Error>) -> PyErr {
+    PyRuntimeError::new_err(err.to_string())
+}
+
+fn pose(translation: [f64; 3], rpy: [f64; 3]) -> Isometry3<f64> {
+    Isometry3::from_parts(
+        Translation3::from(Vector3::from(translation)),
+        UnitQuaternion::from_euler_angles(rpy[0], rpy[1], rpy[2]),
+    )
+}
+
+#[pyclass(name = "LumpedObject", module = "meshcat", frozen)]
+pub struct PyLumpedObject(LumpedObject);
+
+// The primitives use the same conventions as the scene descriptions (See scene::ShapeDescription)
+fn shape(
+    shape: ShapeDescription,
+    color: Option<u32>,
+    opacity: Option<f64>,
+) -> PyResult<PyLumpedObject> {
+    let object = ObjectDescription {
+        shape,
+        color,
+        opacity,
+        pose: Isometry3::identity(),
+    };
+    Ok(PyLumpedObject(
+        object.to_lumped_object().map_err(to_py_err)?,
+    ))
+}
+
+#[pyfunction(name = "box")]
+#[pyo3(signature = (width, height, depth, color=None, opacity=None))]
+fn box_object(
+    width: f64,
+    height: f64,
+    depth: f64,
+    color: Option<u32>,
+    opacity: Option<f64>,
+) -> PyResult<PyLumpedObject> {
+    let size = Vector3::new(width, height, depth);
+    shape(ShapeDescription::Box { size }, color, opacity)
+}
+
+#[pyfunction]
+#[pyo3(signature = (radius, color=None, opacity=None))]
+fn sphere(radius: f64, color: Option<u32>, opacity: Option<f64>) -> PyResult<PyLumpedObject> {
+    shape(ShapeDescription::Sphere { radius }, color, opacity)
+}
+
+#[pyfunction]
+#[pyo3(signature = (radius, length, color=None, opacity=None))]
+fn cylinder(
+    radius: f64,
+    length: f64,
+    color: Option<u32>,
+    opacity: Option<f64>,
+) -> PyResult<PyLumpedObject> {
+    shape(
+        ShapeDescription::Cylinder { radius, length },
+        color,
+        opacity,
+    )
+}
+
+#[pyfunction]
+#[pyo3(signature = (filename, color=None, opacity=None))]
+fn mesh(filename: String, color: Option<u32>, opacity: Option<f64>) -> PyResult<PyLumpedObject> {
+    shape(ShapeDescription::Mesh { filename }, color, opacity)
+}
+
+#[pyfunction]
+#[pyo3(signature = (translation=[0.0; 3], rpy=[0.0; 3]))]
+fn triad(translation: [f64; 3], rpy: [f64; 3]) -> PyLumpedObject {
+    PyLumpedObject(crate::utils::triad(pose(translation, rpy)))
+}
+
+// Lists of [x, y, z] points and [r, g, b] colors in [0, 1]
+#[pyfunction]
+#[pyo3(signature = (points, colors, size=0.01))]
+fn point_cloud(
+    points: Vec<[f64; 3]>,
+    colors: Vec<[f64; 3]>,
+    size: f64,
+) -> PyResult<PyLumpedObject> {
+    if points.len() != colors.len() {
+        return Err(PyRuntimeError::new_err(format!(
+            "Got {} points but {} colors",
+            points.len(),
+            colors.len()
+        )));
+    }
+    let to_matrix = |columns: Vec<[f64; 3]>| {
+        Matrix3xX::from_iterator(columns.len(), columns.into_iter().flatten())
+    };
+    Ok(PyLumpedObject(crate::utils::point_cloud(
+        to_matrix(points),
+        to_matrix(colors),
+        size,
+    )))
+}
+
+// Meshcat isn't Send (See Meshcat::with_transport), it can only be used from the thread that
+// created it
+#[pyclass(name = "Meshcat", module = "meshcat", unsendable)]
+pub struct PyMeshcat(Meshcat);
+
+#[pymethods]
+impl PyMeshcat {
+    #[new]
+    #[pyo3(signature = (endpoint=DEFAULT_ZMQ_URL))]
+    fn new(endpoint: &str) -> PyResult<Self> {
+        let transport =
+            crate::transport::ZmqTransport::new(endpoint).map_err(|err| to_py_err(err.into()))?;
+        Ok(Self(Meshcat::with_transport(transport)))
+    }
+
+    fn url(&self) -> PyResult<String> {
+        self.0.url().map_err(to_py_err)
+    }
+
+    fn set_object(&self, path: &str, object: &PyLumpedObject) -> PyResult<()> {
+        self.0.set_object(path, &object.0).map_err(to_py_err)
+    }
+
+    #[pyo3(signature = (path, translation=[0.0; 3], rpy=[0.0; 3]))]
+    fn set_transform(&self, path: &str, translation: [f64; 3], rpy: [f64; 3]) -> PyResult<()> {
+        self.0
+            .set_transform(path, pose(translation, rpy))
+            .map_err(to_py_err)
+    }
+
+    fn set_visible(&self, path: &str, visible: bool) -> PyResult<()> {
+        self.0
+            .set_property(path, PropertyType::Visible(visible))
+            .map_err(to_py_err)
+    }
+
+    #[pyo3(signature = (path, text, size=1.0, font_size=48, background_color=None))]
+    fn set_label(
+        &self,
+        path: &str,
+        text: &str,
+        size: f64,
+        font_size: u32,
+        background_color: Option<u32>,
+    ) -> PyResult<()> {
+        let options = crate::utils::LabelOptions {
+            size,
+            font_size,
+            background_color,
+            ..Default::default()
+        };
+        self.0.set_label(path, text, &options).map_err(to_py_err)
+    }
+
+    fn load_scene(&self, path: &str) -> PyResult<()> {
+        self.0.load_scene(path).map_err(to_py_err)
+    }
+
+    fn delete(&self, path: &str) -> PyResult<()> {
+        self.0.delete(path).map_err(to_py_err)
+    }
+}
+
+// The `meshcat` Python module, e.g.
+// vis = meshcat.Meshcat()
+// vis.set_object("/box", meshcat.box(1.0, 1.0, 1.0, color=0xff0000))
+#[pymodule]
+fn meshcat(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<PyMeshcat>()?;
+    module.add_class::<PyLumpedObject>()?;
+    module.add_function(wrap_pyfunction!(box_object, module)?)?;
+    module.add_function(wrap_pyfunction!(sphere, module)?)?;
+    module.add_function(wrap_pyfunction!(cylinder, module)?)?;
+    module.add_function(wrap_pyfunction!(mesh, module)?)?;
+    module.add_function(wrap_pyfunction!(triad, module)?)?;
+    module.add_function(wrap_pyfunction!(point_cloud, module)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pyo3::types::PyDict;
+
+    #[test]
+    fn test_module() {
+        Python::initialize();
+        Python::attach(|py| {
+            let module = PyModule::new(py, "meshcat").unwrap();
+            meshcat(&module).unwrap();
+            let globals = PyDict::new(py);
+            globals.set_item("meshcat", &module).unwrap();
+            py.run(
+                c"
+box = meshcat.box(1.0, 2.0, 3.0, color=0xff0000)
+assert isinstance(box, meshcat.LumpedObject)
+assert isinstance(meshcat.triad(translation=[1.0, 0.0, 0.0]), meshcat.LumpedObject)
+try:
+    meshcat.point_cloud([[0.0, 0.0, 0.0]], [])
+    raise AssertionError('Expected an error')
+except RuntimeError as err:
+    assert 'Got 1 points but 0 colors' in str(err)
+",
+                Some(&globals),
+                None,
+            )
+            .unwrap();
+            let object = globals.get_item("box").unwrap().unwrap();
+            let object = object.cast::<PyLumpedObject>().unwrap().get();
+            assert_eq!(object.0.material.color, Some(0xff0000));
+        });
+    }
+}
@@ -0,0 +1,280 @@
+use std::error::Error;
+use std::time::{Duration, Instant};
+
+use nalgebra::{Isometry3, Vector3};
+use serde::{Deserialize, Serialize};
+
+use crate::scene::{pose, ObjectDescription};
+use crate::types::*;
+
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum Action {
+    // Sent with its pose
+    SetObject {
+        path: String,
+        object: ObjectDescription,
+    },
+    SetTransform {
+        path: String,
+        #[serde(with = "pose")]
+        pose: Isometry3<f64>,
+    },
+    // From one pose to the other in duration seconds, interpolated at the scenario's frame rate
+    Move {
+        path: String,
+        #[serde(with = "pose")]
+        from: Isometry3<f64>,
+        #[serde(with = "pose")]
+        to: Isometry3<f64>,
+        duration: f64,
+    },
+    SetVisible {
+        path: String,
+        visible: bool,
+    },
+    SetColor {
+        path: String,
+        color: [f64; 4],
+    },
+    SetOpacity {
+        path: String,
+        opacity: f64,
+    },
+    Delete {
+        path: String,
+    },
+    // See Meshcat::set_camera_pose
+    Camera {
+        position: Vector3<f64>,
+        target: Vector3<f64>,
+    },
+}
+
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
+pub struct Step {
+    // In seconds from the start of the scenario
+    pub time: f64,
+    #[serde(flatten)]
+    pub action: Action,
+}
+
+fn default_frame_rate() -> f64 {
+    30.0
+}
+
+// A timed sequence of actions replayed with Scenario::play, e.g. in YAML
+// steps:
+//   - {time: 0.0, action: set_object, path: /box, object: {shape: {type: box, size: [1, 1, 1]}}}
+//   - {time: 1.0, action: move, path: /box, from: {}, to: {xyz: [1, 0, 0]}, duration: 2.0}
+//   - {time: 3.0, action: camera, position: [3, 3, 3], target: [1, 0, 0]}
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
+pub struct Scenario {
+    #[serde(default = "default_frame_rate")]
+    pub frame_rate: f64,
+    pub steps: Vec<Step>,
+}
+
+impl Default for Scenario {
+    fn default() -> Self {
+        Scenario {
+            frame_rate: default_frame_rate(),
+            steps: Vec::new(),
+        }
+    }
+}
+
+// What is sent at a given time of the scenario
+#[derive(Debug)]
+enum Event {
+    Command(Command),
+    Camera(Vector3<f64>, Vector3<f64>),
+}
+
+impl Scenario {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_yaml(yaml: &str) -> Result<Self, Box<dyn Error>> {
+        Ok(serde_yaml::from_str(yaml)?)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, Box<dyn Error>> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    pub fn at(&mut self, time: f64, action: Action) -> &mut Self {
+        self.steps.push(Step { time, action });
+        self
+    }
+
+    // The seconds it takes to play at normal speed
+    pub fn duration(&self) -> f64 {
+        self.steps
+            .iter()
+            .map(|step| match &step.action {
+                Action::Move { duration, .. } => step.time + duration,
+                _ => step.time,
+            })
+            .fold(0.0, f64::max)
+    }
+
+    // The events sorted by time, the steps happening at the same time are kept in order
+    fn timeline(&self) -> Result<Vec<(f64, Event)>, Box<dyn Error>> {
+        if !self.frame_rate.is_finite() || self.frame_rate <= 0.0 {
+            return Err(format!("Invalid frame rate {}", self.frame_rate).into());
+        }
+        let mut events = Vec::new();
+        for Step { time, action } in &self.steps {
+            let time = *time;
+            if !time.is_finite() || time < 0.0 {
+                return Err(format!("Invalid step time {}", time).into());
+            }
+            let command = match action {
+                Action::SetObject { path, object } => {
+                    events.push((
+                        time,
+                        Event::Command(Command::SetObject(
+                            path.clone(),
+                            Box::new(object.to_lumped_object()?),
+                        )),
+                    ));
+                    Command::SetTransform(path.clone(), object.pose)
+                }
+                Action::SetTransform { path, pose } => Command::SetTransform(path.clone(), *pose),
+                Action::Move {
+                    path,
+                    from,
+                    to,
+                    duration,
+                } => {
+                    if !duration.is_finite() || *duration < 0.0 {
+                        return Err(format!(
+                            "Invalid duration {} of the move of {}",
+                            duration, path
+                        )
+                        .into());
+                    }
+                    let frames = (duration * self.frame_rate).ceil().max(1.0) as usize;
+                    for frame in 0..frames {
+                        let t = frame as f64 / frames as f64;
+                        events.push((
+                            time + t * duration,
+                            Event::Command(Command::SetTransform(
+                                path.clone(),
+                                from.lerp_slerp(to, t),
+                            )),
+                        ));
+                    }
+                    events.push((
+                        time + duration,
+                        Event::Command(Command::SetTransform(path.clone(), *to)),
+                    ));
+                    continue;
+                }
+                Action::SetVisible { path, visible } => {
+                    Command::SetProperty(path.clone(), PropertyType::Visible(*visible))
+                }
+                Action::SetColor { path, color } => {
+                    Command::SetProperty(path.clone(), PropertyType::Color(*color))
+                }
+                Action::SetOpacity { path, opacity } => {
+                    Command::SetProperty(path.clone(), PropertyType::Opacity(*opacity))
+                }
+                Action::Delete { path } => Command::Delete(path.clone()),
+                Action::Camera { position, target } => {
+                    events.push((time, Event::Camera(*position, *target)));
+                    continue;
+                }
+            };
+            events.push((time, Event::Command(command)));
+        }
+        events.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+        Ok(events)
+    }
+
+    // Sends the actions at their time, speed 2.0 plays twice as fast
+    pub fn play(&self, meshcat: &Meshcat, speed: f64) -> Result<(), Box<dyn Error>> {
+        if !speed.is_finite() || speed <= 0.0 {
+            return Err(format!("Invalid speed {}", speed).into());
+        }
+        let start = Instant::now();
+        for (time, event) in self.timeline()? {
+            let elapsed = start.elapsed();
+            let at = Duration::from_secs_f64(time / speed);
+            if at > elapsed {
+                std::thread::sleep(at - elapsed);
+            }
+            match event {
+                Event::Command(command) => meshcat.send(command)?,
+                Event::Camera(position, target) => meshcat.set_camera_pose(position, target)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timeline() {
+        let scenario = Scenario::from_yaml(
+            "
+frame_rate: 2
+steps:
+  - {time: 2.0, action: camera, position: [3, 3, 3], target: [0, 0, 0]}
+  - {time: 0.0, action: set_object, path: /box, object: {shape: {type: box, size: [1, 1, 1]}}}
+  - {time: 0.5, action: move, path: /box, from: {}, to: {xyz: [1, 0, 0]}, duration: 1.0}
+  - {time: 2.0, action: set_visible, path: /box, visible: false}
+",
+        )
+        .unwrap();
+        assert_eq!(scenario.duration(), 2.0);
+        let timeline = scenario.timeline().unwrap();
+        let times = timeline.iter().map(|(time, _)| *time).collect::<Vec<_>>();
+        assert_eq!(times, [0.0, 0.0, 0.5, 1.0, 1.5, 2.0, 2.0]);
+        assert!(matches!(
+            &timeline[0].1,
+            Event::Command(Command::SetObject(path, _)) if path == "/box"
+        ));
+        let Event::Command(Command::SetTransform(_, halfway)) = &timeline[3].1 else {
+            panic!("Expected a transform");
+        };
+        assert_eq!(halfway.translation.vector, Vector3::new(0.5, 0.0, 0.0));
+        assert!(matches!(timeline[5].1, Event::Camera(..)));
+        assert!(matches!(
+            timeline[6].1,
+            Event::Command(Command::SetProperty(_, PropertyType::Visible(false)))
+        ));
+
+        let mut scenario = Scenario::new();
+        scenario.frame_rate = 0.0;
+        assert!(scenario.timeline().is_err());
+        let mut scenario = Scenario::new();
+        scenario.at(
+            -1.0,
+            Action::Delete {
+                path: "/box".to_string(),
+            },
+        );
+        assert!(scenario.timeline().is_err());
+        let mut scenario = Scenario::new();
+        scenario.at(
+            0.0,
+            Action::Move {
+                path: "/box".to_string(),
+                from: Isometry3::identity(),
+                to: Isometry3::identity(),
+                duration: f64::NAN,
+            },
+        );
+        assert!(scenario.timeline().is_err());
+        let meshcat = Meshcat::with_transport(crate::transport::Recorder::default());
+        for speed in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            assert!(Scenario::new().play(&meshcat, speed).is_err());
+        }
+    }
+}
@@ -0,0 +1,602 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+
+use nalgebra::{Isometry3, Translation3, UnitQuaternion, Vector3};
+use serde::{Deserialize, Serialize};
+use typed_builder::TypedBuilder;
+
+use super::types::*;
+
+// Poses are written as a translation and roll/pitch/yaw angles (like URDF origins), both
+// default to zero
+pub(crate) mod pose {
+    use super::*;
+
+    #[derive(Serialize, Deserialize, Default)]
+    #[serde(default)]
+    struct Pose {
+        xyz: [f64; 3],
+        rpy: [f64; 3],
+    }
+
+    pub fn serialize<S: serde::Serializer>(
+        pose: &Isometry3<f64>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let (roll, pitch, yaw) = pose.rotation.euler_angles();
+        Pose {
+            xyz: pose.translation.vector.into(),
+            rpy: [roll, pitch, yaw],
+        }
+        .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Isometry3<f64>, D::Error> {
+        let pose = Pose::deserialize(deserializer)?;
+        Ok(Isometry3::from_parts(
+            Translation3::from(Vector3::from(pose.xyz)),
+            UnitQuaternion::from_euler_angles(pose.rpy[0], pose.rpy[1], pose.rpy[2]),
+        ))
+    }
+}
+
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ShapeDescription {
+    Box { size: Vector3<f64> },
+    Sphere { radius: f64 },
+    // Along the z axis (like URDF cylinders)
+    Cylinder { radius: f64, length: f64 },
+    Mesh { filename: String },
+}
+
+impl ShapeDescription {
+    pub fn geometry_type(&self) -> Result<GeometryType, Box<dyn Error>> {
+        Ok(match self {
+            ShapeDescription::Box { size } => GeometryType::Box {
+                width: size.x,
+                height: size.y,
+                depth: size.z,
+            },
+            ShapeDescription::Sphere { radius } => GeometryType::Sphere {
+                radius: *radius,
+                width_segments: 32,
+                height_segments: 16,
+                phi_start: 0.0,
+                phi_length: 2.0 * std::f64::consts::PI,
+                theta_start: 0.0,
+                theta_length: std::f64::consts::PI,
+            },
+            ShapeDescription::Cylinder { radius, length } => GeometryType::Cylinder {
+                radius_top: *radius,
+                radius_bottom: *radius,
+                height: *length,
+                radial_segments: 32,
+                height_segments: 1,
+                theta_start: 0.0,
+                theta_length: 2.0 * std::f64::consts::PI,
+            },
+            ShapeDescription::Mesh { filename } => crate::utils::load_mesh(filename)?,
+        })
+    }
+}
+
+#[derive(PartialEq, Clone, Debug, TypedBuilder, Serialize, Deserialize)]
+pub struct ObjectDescription {
+    pub shape: ShapeDescription,
+    #[builder(default, setter(strip_option))]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color: Option<u32>,
+    #[builder(default, setter(strip_option))]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub opacity: Option<f64>,
+    #[builder(default = Isometry3::identity())]
+    #[serde(default = "Isometry3::identity", with = "pose")]
+    pub pose: Isometry3<f64>,
+}
+
+impl ObjectDescription {
+    // The object without its pose, which is sent with set_transform
+    pub fn to_lumped_object(&self) -> Result<LumpedObject, Box<dyn Error>> {
+        let material = Material {
+            color: self.color,
+            opacity: self.opacity,
+            transparent: self.opacity.map(|opacity| opacity < 1.0),
+            ..Default::default()
+        };
+        Ok(LumpedObject::builder()
+            .geometries(vec![Geometry::new(self.shape.geometry_type()?)])
+            .material(material)
+            .build())
+    }
+
+    fn same_object(&self, other: &ObjectDescription) -> bool {
+        self.shape == other.shape && self.color == other.color && self.opacity == other.opacity
+    }
+}
+
+// The objects of a scene keyed by their meshcat path, e.g. in YAML
+// objects:
+//   /table:
+//     shape: {type: box, size: [1.0, 2.0, 0.05]}
+//     color: 0x8800ff
+//     pose: {xyz: [0.0, 0.0, 0.7]}
+#[derive(PartialEq, Clone, Debug, Default, Serialize, Deserialize)]
+pub struct SceneDescription {
+    pub objects: BTreeMap<String, ObjectDescription>,
+}
+
+impl SceneDescription {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_yaml(yaml: &str) -> Result<Self, Box<dyn Error>> {
+        Ok(serde_yaml::from_str(yaml)?)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, Box<dyn Error>> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    // Reads a .yaml/.yml or a .json file, relative mesh filenames are relative to the file
+    pub fn from_file(path: &str) -> Result<Self, Box<dyn Error>> {
+        let content = std::fs::read_to_string(path)?;
+        let mut scene = match crate::utils::file_extension(path)? {
+            "yaml" | "yml" => Self::from_yaml(&content)?,
+            "json" => Self::from_json(&content)?,
+            extension => return Err(format!("Unsupported scene format: {}", extension).into()),
+        };
+        let directory = std::path::Path::new(path)
+            .parent()
+            .unwrap_or(std::path::Path::new(""));
+        for object in scene.objects.values_mut() {
+            if let ShapeDescription::Mesh { filename } = &mut object.shape {
+                if std::path::Path::new(filename).is_relative() {
+                    *filename = directory.join(&filename).to_string_lossy().to_string();
+                }
+            }
+        }
+        Ok(scene)
+    }
+
+    pub fn insert(&mut self, path: &str, object: ObjectDescription) -> &mut Self {
+        self.objects.insert(path.to_string(), object);
+        self
+    }
+
+    // The commands that turn this scene into the desired one: removed objects are deleted,
+    // changed objects are re-sent and objects that only moved get a set_transform
+    pub fn diff(&self, desired: &SceneDescription) -> Result<Vec<Command>, Box<dyn Error>> {
+        let deleted = self
+            .objects
+            .keys()
+            .filter(|path| !desired.objects.contains_key(*path))
+            .collect::<BTreeSet<_>>();
+        let mut commands = deleted
+            .iter()
+            .map(|path| Command::Delete(path.to_string()))
+            .collect::<Vec<_>>();
+        for (path, object) in &desired.objects {
+            // Deleting a path also deletes everything below it
+            let parent_deleted = deleted
+                .iter()
+                .any(|deleted| path.starts_with(&format!("{}/", deleted)));
+            match self.objects.get(path) {
+                Some(current) if !parent_deleted && current.same_object(object) => {
+                    if current.pose != object.pose {
+                        commands.push(Command::SetTransform(path.clone(), object.pose));
+                    }
+                }
+                _ => {
+                    commands.push(Command::SetObject(
+                        path.clone(),
+                        Box::new(object.to_lumped_object()?),
+                    ));
+                    commands.push(Command::SetTransform(path.clone(), object.pose));
+                }
+            }
+        }
+        Ok(commands)
+    }
+}
+
+// A scene modified locally and sent with commit, which only sends what changed since the
+// last commit
+#[derive(Clone, Debug, Default)]
+pub struct Scene {
+    current: SceneDescription,
+    committed: SceneDescription,
+}
+
+impl Scene {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn description(&self) -> &SceneDescription {
+        &self.current
+    }
+
+    pub fn get(&self, path: &str) -> Option<&ObjectDescription> {
+        self.current.objects.get(path)
+    }
+
+    // To change the shape, the material or the pose of an object
+    pub fn get_mut(&mut self, path: &str) -> Option<&mut ObjectDescription> {
+        self.current.objects.get_mut(path)
+    }
+
+    // Adds or replaces the object at path
+    pub fn add(&mut self, path: &str, object: ObjectDescription) -> &mut Self {
+        self.current.insert(path, object);
+        self
+    }
+
+    // Removes the object at path and the objects below it, like Meshcat::delete
+    pub fn remove(&mut self, path: &str) -> &mut Self {
+        let prefix = format!("{}/", path);
+        self.current
+            .objects
+            .retain(|object_path, _| object_path != path && !object_path.starts_with(&prefix));
+        self
+    }
+
+    pub fn set_pose(&mut self, path: &str, pose: Isometry3<f64>) -> Result<(), Box<dyn Error>> {
+        self.get_mut(path)
+            .ok_or_else(|| format!("Unknown object '{}'", path))?
+            .pose = pose;
+        Ok(())
+    }
+
+    // A checkpoint of the scene (including the changes that aren't committed yet)
+    pub fn snapshot(&self) -> SceneDescription {
+        self.current.clone()
+    }
+
+    // Goes back to a snapshot, the next commit sends what's needed to show it again
+    pub fn restore(&mut self, snapshot: &SceneDescription) {
+        self.current = snapshot.clone();
+    }
+
+    // The commands commit would send
+    pub fn pending(&self) -> Result<Vec<Command>, Box<dyn Error>> {
+        self.committed.diff(&self.current)
+    }
+
+    pub fn commit(&mut self, meshcat: &Meshcat) -> Result<(), Box<dyn Error>> {
+        for command in self.pending()? {
+            meshcat.send(command)?;
+        }
+        self.committed = self.current.clone();
+        Ok(())
+    }
+}
+
+// Builds a SceneDescription, each entry is a path, a shape and optional modifiers
+// let scene = meshcat! {
+//     "/table" => box(1.0, 2.0, 0.05) color(0x8800ff) at(0.0, 0.0, 0.7),
+//     "/table/ball" => sphere(0.1) opacity(0.5) at(0.0, 0.0, 0.1) rpy(0.0, 0.0, 1.57),
+//     "/pillar" => cylinder(0.1, 2.0),
+//     "/mesh" => mesh("examples/data/mesh.obj"),
+// };
+#[macro_export]
+macro_rules! meshcat {
+    ($($path:expr => $shape:ident ($($arg:expr),*) $($modifier:ident ($($value:expr),*))*),* $(,)?) => {{
+        #[allow(unused_mut)]
+        let mut scene = $crate::scene::SceneDescription::new();
+        $(
+            #[allow(unused_mut)]
+            let mut object = $crate::scene::ObjectDescription::builder()
+                .shape($crate::meshcat!(@shape $shape ($($arg),*)))
+                .build();
+            $($crate::meshcat!(@modifier object $modifier ($($value),*));)*
+            scene.insert($path, object);
+        )*
+        scene
+    }};
+    (@shape box ($x:expr, $y:expr, $z:expr)) => {
+        $crate::scene::ShapeDescription::Box {
+            size: $crate::nalgebra::Vector3::new($x as f64, $y as f64, $z as f64),
+        }
+    };
+    (@shape sphere ($radius:expr)) => {
+        $crate::scene::ShapeDescription::Sphere { radius: $radius as f64 }
+    };
+    (@shape cylinder ($radius:expr, $length:expr)) => {
+        $crate::scene::ShapeDescription::Cylinder {
+            radius: $radius as f64,
+            length: $length as f64,
+        }
+    };
+    (@shape mesh ($filename:expr)) => {
+        $crate::scene::ShapeDescription::Mesh { filename: $filename.to_string() }
+    };
+    (@modifier $object:ident color ($color:expr)) => {
+        $object.color = Some($color);
+    };
+    (@modifier $object:ident opacity ($opacity:expr)) => {
+        $object.opacity = Some($opacity as f64);
+    };
+    (@modifier $object:ident at ($x:expr, $y:expr, $z:expr)) => {
+        $object.pose.translation =
+            $crate::nalgebra::Translation3::new($x as f64, $y as f64, $z as f64);
+    };
+    (@modifier $object:ident rpy ($roll:expr, $pitch:expr, $yaw:expr)) => {
+        $object.pose.rotation = $crate::nalgebra::UnitQuaternion::from_euler_angles(
+            $roll as f64,
+            $pitch as f64,
+            $yaw as f64,
+        );
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn summary(commands: &[Command]) -> Vec<String> {
+        commands
+            .iter()
+            .map(|command| match command {
+                Command::SetObject(path, _) => format!("set_object {}", path),
+                Command::SetTransform(path, _) => format!("set_transform {}", path),
+                Command::SetProperty(path, _) => format!("set_property {}", path),
+                Command::Delete(path) => format!("delete {}", path),
+            })
+            .collect()
+    }
+
+    fn sphere(radius: f64) -> ObjectDescription {
+        ObjectDescription::builder()
+            .shape(ShapeDescription::Sphere { radius })
+            .build()
+    }
+
+    #[test]
+    fn test_diff() {
+        let empty = SceneDescription::new();
+        let mut scene = SceneDescription::new();
+        scene
+            .insert("/a", sphere(1.0))
+            .insert("/a/b", sphere(1.0))
+            .insert("/c", sphere(1.0));
+        assert_eq!(
+            summary(&empty.diff(&scene).unwrap()),
+            [
+                "set_object /a",
+                "set_transform /a",
+                "set_object /a/b",
+                "set_transform /a/b",
+                "set_object /c",
+                "set_transform /c"
+            ]
+        );
+        assert!(scene.diff(&scene).unwrap().is_empty());
+
+        let mut moved = scene.clone();
+        moved.objects.get_mut("/c").unwrap().pose = Isometry3::translation(1.0, 0.0, 0.0);
+        moved.objects.get_mut("/a").unwrap().color = Some(0xff0000);
+        assert_eq!(
+            summary(&scene.diff(&moved).unwrap()),
+            ["set_object /a", "set_transform /a", "set_transform /c"]
+        );
+
+        // /a/b has to be re-sent since deleting /a deletes it as well
+        let mut removed = scene.clone();
+        removed.objects.remove("/a");
+        assert_eq!(
+            summary(&scene.diff(&removed).unwrap()),
+            ["delete /a", "set_object /a/b", "set_transform /a/b"]
+        );
+        assert_eq!(
+            summary(&scene.diff(&empty).unwrap()),
+            ["delete /a", "delete /a/b", "delete /c"]
+        );
+    }
+
+    #[test]
+    fn test_to_lumped_object() {
+        let object = ObjectDescription::builder()
+            .shape(ShapeDescription::Box {
+                size: Vector3::new(1.0, 2.0, 3.0),
+            })
+            .color(0x00ff00)
+            .opacity(0.5)
+            .build()
+            .to_lumped_object()
+            .unwrap();
+        assert_eq!(object.material.color, Some(0x00ff00));
+        assert_eq!(object.material.transparent, Some(true));
+        let mesh = ObjectDescription::builder()
+            .shape(ShapeDescription::Mesh {
+                filename: "missing.obj".to_string(),
+            })
+            .build();
+        assert!(mesh.to_lumped_object().is_err());
+    }
+
+    #[test]
+    fn test_from_yaml() {
+        let scene = SceneDescription::from_yaml(
+            r#"
+            objects:
+              /table:
+                shape: {type: box, size: [1.0, 2.0, 0.05]}
+                color: 0x8800ff
+                pose: {xyz: [0.0, 0.0, 0.7]}
+              /table/ball:
+                shape: {type: sphere, radius: 0.1}
+                pose: {rpy: [0.0, 0.0, 1.5707963267948966]}
+              /mesh:
+                shape: {type: mesh, filename: mesh.obj}
+            "#,
+        )
+        .unwrap();
+        let table = &scene.objects["/table"];
+        assert_eq!(
+            table.shape,
+            ShapeDescription::Box {
+                size: Vector3::new(1.0, 2.0, 0.05)
+            }
+        );
+        assert_eq!(table.color, Some(0x8800ff));
+        assert_eq!(table.pose, Isometry3::translation(0.0, 0.0, 0.7));
+        let ball = &scene.objects["/table/ball"];
+        assert!(ball.color.is_none());
+        assert!((ball.pose.rotation.angle() - std::f64::consts::FRAC_PI_2).abs() < 1e-9);
+        assert_eq!(scene.objects["/mesh"].pose, Isometry3::identity());
+        assert!(SceneDescription::from_yaml("objects: {/a: {shape: {type: cone}}}").is_err());
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let mut scene = SceneDescription::new();
+        scene.insert(
+            "/cylinder",
+            ObjectDescription::builder()
+                .shape(ShapeDescription::Cylinder {
+                    radius: 0.5,
+                    length: 2.0,
+                })
+                .opacity(0.5)
+                .pose(Isometry3::translation(1.0, 2.0, 3.0))
+                .build(),
+        );
+        let json = serde_json::to_string(&scene).unwrap();
+        assert_eq!(SceneDescription::from_json(&json).unwrap(), scene);
+    }
+
+    #[test]
+    fn test_from_file() {
+        let directory =
+            std::env::temp_dir().join(format!("meshcat-scene-{}", crate::types::new_uuid()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("scene.yaml");
+        std::fs::write(
+            &path,
+            "objects: {/mesh: {shape: {type: mesh, filename: mesh.obj}}}",
+        )
+        .unwrap();
+        let scene = SceneDescription::from_file(path.to_str().unwrap()).unwrap();
+        assert_eq!(
+            scene.objects["/mesh"].shape,
+            ShapeDescription::Mesh {
+                filename: directory.join("mesh.obj").to_string_lossy().to_string()
+            }
+        );
+        assert!(SceneDescription::from_file("examples/data/sample.urdf").is_err());
+    }
+
+    #[test]
+    fn test_meshcat_macro() {
+        let scene = crate::meshcat! {
+            "/table" => box(1, 2.0, 0.05) color(0x8800ff) at(0.0, 0.0, 0.7),
+            "/table/ball" => sphere(0.1) opacity(0.5) rpy(0.0, 0.0, 1.0) at(0, 0, 1),
+            "/pillar" => cylinder(0.1, 2.0),
+            "/mesh" => mesh("mesh.obj"),
+        };
+        let mut expected = SceneDescription::new();
+        expected
+            .insert(
+                "/table",
+                ObjectDescription::builder()
+                    .shape(ShapeDescription::Box {
+                        size: Vector3::new(1.0, 2.0, 0.05),
+                    })
+                    .color(0x8800ff)
+                    .pose(Isometry3::translation(0.0, 0.0, 0.7))
+                    .build(),
+            )
+            .insert(
+                "/table/ball",
+                ObjectDescription::builder()
+                    .shape(ShapeDescription::Sphere { radius: 0.1 })
+                    .opacity(0.5)
+                    .pose(Isometry3::from_parts(
+                        Translation3::new(0.0, 0.0, 1.0),
+                        UnitQuaternion::from_euler_angles(0.0, 0.0, 1.0),
+                    ))
+                    .build(),
+            )
+            .insert(
+                "/pillar",
+                ObjectDescription::builder()
+                    .shape(ShapeDescription::Cylinder {
+                        radius: 0.1,
+                        length: 2.0,
+                    })
+                    .build(),
+            )
+            .insert(
+                "/mesh",
+                ObjectDescription::builder()
+                    .shape(ShapeDescription::Mesh {
+                        filename: "mesh.obj".to_string(),
+                    })
+                    .build(),
+            );
+        assert_eq!(scene, expected);
+        assert!(crate::meshcat! {}.objects.is_empty());
+    }
+
+    #[test]
+    fn test_scene() {
+        let mut scene = Scene::new();
+        scene
+            .add("/a", sphere(1.0))
+            .add("/a/b", sphere(1.0))
+            .add("/ab", sphere(1.0));
+        assert_eq!(summary(&scene.pending().unwrap()).len(), 6);
+        scene.committed = scene.current.clone();
+        assert!(scene.pending().unwrap().is_empty());
+
+        scene
+            .set_pose("/ab", Isometry3::translation(1.0, 0.0, 0.0))
+            .unwrap();
+        scene.get_mut("/a/b").unwrap().opacity = Some(0.5);
+        assert_eq!(
+            summary(&scene.pending().unwrap()),
+            ["set_object /a/b", "set_transform /a/b", "set_transform /ab"]
+        );
+        assert!(scene
+            .set_pose("/c", Isometry3::translation(1.0, 0.0, 0.0))
+            .is_err());
+
+        scene.remove("/a");
+        assert!(scene.get("/a/b").is_none());
+        assert!(scene.get("/ab").is_some());
+        assert_eq!(
+            summary(&scene.pending().unwrap()),
+            ["delete /a", "delete /a/b", "set_transform /ab"]
+        );
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let mut scene = Scene::new();
+        scene.add("/a", sphere(1.0)).add("/b", sphere(1.0));
+        scene.committed = scene.current.clone();
+        let snapshot = scene.snapshot();
+
+        scene.remove("/a").add("/c", sphere(2.0));
+        scene
+            .set_pose("/b", Isometry3::translation(0.0, 1.0, 0.0))
+            .unwrap();
+        scene.committed = scene.current.clone();
+
+        scene.restore(&snapshot);
+        assert_eq!(scene.description(), &snapshot);
+        assert_eq!(
+            summary(&scene.pending().unwrap()),
+            [
+                "delete /c",
+                "set_object /a",
+                "set_transform /a",
+                "set_transform /b"
+            ]
+        );
+    }
+}
@@ -0,0 +1,845 @@
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+
+use log::{info, warn};
+use serde::Deserialize;
+use tungstenite::handshake::derive_accept_key;
+use tungstenite::protocol::Role;
+use tungstenite::{Message, WebSocket};
+
+// The number of requests a viewer can be behind before it's disconnected
+pub const DEFAULT_QUEUE_SIZE: usize = 1024;
+
+type Request = Arc<[u8]>;
+
+// The viewer's script when it isn't in the static directory or embedded (See
+// MeshcatServer::with_static_dir and MeshcatServer::with_viewer_script). It follows the master
+// branch of meshcat, embed a bundle to pin the viewer to a known version
+pub const VIEWER_SCRIPT_URL: &str =
+    "https://cdn.jsdelivr.net/gh/meshcat-dev/meshcat@master/dist/main.min.js";
+
+// The viewer page of meshcat-python, it connects to the WebSocket on the same address
+const INDEX_HTML: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+    <meta charset="utf-8">
+    <title>MeshCat</title>
+    <style>
+        body { margin: 0; }
+        #meshcat-pane { width: 100vw; height: 100vh; overflow: hidden; }
+    </style>
+</head>
+<body>
+    <div id="meshcat-pane"></div>
+    <script src="main.min.js"></script>
+    <script>
+        var viewer = new MeshCat.Viewer(document.getElementById("meshcat-pane"));
+        var url = location.href.replace("http://", "ws://").replace("https://", "wss://")
+            .replace("/static/", "/");
+        var connection = new WebSocket(url);
+        connection.binaryType = "arraybuffer";
+        connection.onmessage = (message) =>
+            viewer.handle_command_bytearray(new Uint8Array(message.data));
+        viewer.connection = connection;
+    </script>
+</body>
+</html>
+"#;
+
+// A page showing the scene without a server (See MeshcatServer::static_html), the requests are
+// embedded and replayed by the viewer
+fn static_html(requests: &[Request], script: Option<&str>) -> String {
+    use base64::Engine;
+    let script = match script {
+        // The script can't end the script element it's inlined in
+        Some(script) => format!(
+            "<script>{}</script>",
+            script.replace("</script", "<\\/script")
+        ),
+        None => format!("<script src=\"{}\"></script>", VIEWER_SCRIPT_URL),
+    };
+    let requests = requests
+        .iter()
+        .map(|request| {
+            format!(
+                "\"{}\"",
+                base64::engine::general_purpose::STANDARD.encode(request)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(",\n            ");
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+    <meta charset="utf-8">
+    <title>MeshCat</title>
+    <style>
+        body {{ margin: 0; }}
+        #meshcat-pane {{ width: 100vw; height: 100vh; overflow: hidden; }}
+    </style>
+</head>
+<body>
+    <div id="meshcat-pane"></div>
+    {}
+    <script>
+        var viewer = new MeshCat.Viewer(document.getElementById("meshcat-pane"));
+        var requests = [
+            {}
+        ];
+        for (var request of requests) {{
+            viewer.handle_command_bytearray(
+                Uint8Array.from(atob(request), (character) => character.charCodeAt(0)));
+        }}
+    </script>
+</body>
+</html>
+"#,
+        script, requests
+    )
+}
+
+// How the requests of a type are queued for each viewer (See MeshcatServer::with_priority)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Priority {
+    // Sent in order, a viewer whose queue is full is disconnected
+    Reliable,
+    // Sent ahead of the queued reliable requests and only the latest unsent one of each path is
+    // kept, for requests replacing the previous one (the default for set_transform). They don't
+    // overtake a queued delete, they would be deleted with the path
+    Realtime,
+}
+
+// The last object, transform, properties and animation sent to each path, so a viewer
+// connecting (or refreshing) after they were sent still gets the whole scene
+#[derive(Default)]
+struct SceneTree {
+    object: Option<Request>,
+    transform: Option<Request>,
+    properties: BTreeMap<String, Request>,
+    animation: Option<Request>,
+    children: BTreeMap<String, SceneTree>,
+}
+
+impl SceneTree {
+    fn find(&mut self, path: &str) -> &mut SceneTree {
+        path.split('/')
+            .filter(|name| !name.is_empty())
+            .fold(self, |node, name| {
+                node.children.entry(name.to_string()).or_default()
+            })
+    }
+
+    // Deletes the node at path and everything below it, deleting "/" clears the tree
+    fn delete(&mut self, path: &str) {
+        let mut names = path
+            .split('/')
+            .filter(|name| !name.is_empty())
+            .collect::<Vec<_>>();
+        let Some(name) = names.pop() else {
+            *self = SceneTree::default();
+            return;
+        };
+        let mut node = self;
+        for parent in names {
+            match node.children.get_mut(parent) {
+                Some(child) => node = child,
+                None => return,
+            }
+        }
+        node.children.remove(name);
+    }
+
+    // The animations come after the whole scene since their clips animate the descendants
+    fn messages(&self, messages: &mut Vec<Request>) {
+        self.scene_messages(messages);
+        self.animations(messages);
+    }
+
+    // Parents come before their children so they exist when the children are set
+    fn scene_messages(&self, messages: &mut Vec<Request>) {
+        messages.extend(self.object.clone());
+        messages.extend(self.properties.values().cloned());
+        messages.extend(self.transform.clone());
+        for child in self.children.values() {
+            child.scene_messages(messages);
+        }
+    }
+
+    fn animations(&self, messages: &mut Vec<Request>) {
+        messages.extend(self.animation.clone());
+        for child in self.children.values() {
+            child.animations(messages);
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct PropertyName {
+    property: String,
+}
+
+// The realtime requests not sent yet, by request type and path
+type RealtimeRequests = Arc<Mutex<BTreeMap<(String, String), Request>>>;
+
+// What the thread sending the requests to a viewer reads from
+struct ViewerQueue {
+    // An empty request only wakes the thread up to send the realtime requests
+    reliable: Receiver<Request>,
+    realtime: RealtimeRequests,
+    // The number of reliable requests sent
+    sent: Arc<AtomicUsize>,
+}
+
+struct Client {
+    reliable: SyncSender<Request>,
+    realtime: RealtimeRequests,
+    sent: Arc<AtomicUsize>,
+    // The number of reliable requests queued, and the index of the last delete among them
+    queued: usize,
+    last_delete: Option<usize>,
+}
+
+impl Client {
+    fn send_reliable(&mut self, request: Request) -> bool {
+        match self.reliable.try_send(request) {
+            Ok(()) => {
+                self.queued += 1;
+                true
+            }
+            Err(TrySendError::Full(_)) => {
+                warn!("Disconnecting a viewer that can't keep up");
+                false
+            }
+            Err(TrySendError::Disconnected(_)) => false,
+        }
+    }
+
+    // Returns false when the viewer should be disconnected
+    fn send(
+        &mut self,
+        request_type: &str,
+        path: &str,
+        priority: Priority,
+        request: Request,
+    ) -> bool {
+        let delete_pending = self
+            .last_delete
+            .is_some_and(|index| self.sent.load(Ordering::Acquire) <= index);
+        if priority == Priority::Reliable || delete_pending {
+            if request_type == "delete" {
+                self.last_delete = Some(self.queued);
+            }
+            return self.send_reliable(request);
+        }
+        let mut realtime = self.realtime.lock().unwrap();
+        let wake_up = realtime.is_empty();
+        realtime.insert((request_type.to_string(), path.to_string()), request);
+        drop(realtime);
+        if !wake_up {
+            return true;
+        }
+        // A full queue means the thread is busy sending and sends the realtime requests next
+        !matches!(
+            self.reliable.try_send(Request::from([])),
+            Err(TrySendError::Disconnected(_))
+        )
+    }
+}
+
+struct State {
+    tree: SceneTree,
+    // Each viewer has a thread sending its queued requests, so a slow viewer doesn't delay the
+    // others
+    clients: Vec<Client>,
+    // Request types default to Priority::Reliable
+    priorities: HashMap<String, Priority>,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        State {
+            tree: SceneTree::default(),
+            clients: Vec::new(),
+            priorities: HashMap::from([("set_transform".to_string(), Priority::Realtime)]),
+        }
+    }
+}
+
+impl State {
+    // Updates the tree and forwards the request to the connected viewers, returns false for
+    // unknown requests
+    fn handle(&mut self, request_type: &str, path: &str, data: &[u8]) -> bool {
+        let request = Request::from(data);
+        match request_type {
+            "set_object" => self.tree.find(path).object = Some(request.clone()),
+            "set_transform" => self.tree.find(path).transform = Some(request.clone()),
+            "set_property" => match rmp_serde::from_slice::<PropertyName>(data) {
+                Ok(PropertyName { property }) => {
+                    self.tree
+                        .find(path)
+                        .properties
+                        .insert(property, request.clone());
+                }
+                Err(err) => warn!("Ignoring an invalid set_property request: {}", err),
+            },
+            "set_animation" => self.tree.find(path).animation = Some(request.clone()),
+            "delete" => self.tree.delete(path),
+            // Not part of the scene (e.g. the camera target), only forwarded
+            "set_target" => {}
+            _ => return false,
+        }
+        self.broadcast(request_type, path, request);
+        true
+    }
+
+    // All the viewers get every reliable request in the same order, a viewer whose queue is full
+    // is disconnected rather than skipping requests (refreshing the page catches it up)
+    fn broadcast(&mut self, request_type: &str, path: &str, request: Request) {
+        let priority = self
+            .priorities
+            .get(request_type)
+            .copied()
+            .unwrap_or(Priority::Reliable);
+        self.clients
+            .retain_mut(|client| client.send(request_type, path, priority, request.clone()));
+    }
+
+    // The queue of a new viewer starts with the current scene
+    fn add_queue(&mut self, queue_size: usize) -> ViewerQueue {
+        let mut messages = Vec::new();
+        self.tree.messages(&mut messages);
+        let (sender, receiver) = sync_channel(messages.len() + queue_size);
+        let queued = messages.len();
+        for message in messages {
+            // Can't fail, the queue is large enough and the receiver is alive
+            let _ = sender.try_send(message);
+        }
+        let queue = ViewerQueue {
+            reliable: receiver,
+            realtime: RealtimeRequests::default(),
+            sent: Arc::default(),
+        };
+        self.clients.push(Client {
+            reliable: sender,
+            realtime: queue.realtime.clone(),
+            sent: queue.sent.clone(),
+            queued,
+            last_delete: None,
+        });
+        queue
+    }
+}
+
+// The requested path and the key of a WebSocket handshake
+struct HttpRequest {
+    path: String,
+    websocket_key: Option<String>,
+}
+
+fn read_request(stream: &TcpStream) -> Result<HttpRequest, Box<dyn Error>> {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let mut words = line.split_whitespace();
+    let (Some("GET"), Some(path)) = (words.next(), words.next()) else {
+        return Err(format!("Unsupported request '{}'", line.trim()).into());
+    };
+    let mut request = HttpRequest {
+        path: path.to_string(),
+        websocket_key: None,
+    };
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+            return Ok(request);
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("sec-websocket-key") {
+                request.websocket_key = Some(value.trim().to_string());
+            }
+        }
+    }
+}
+
+fn respond(
+    mut stream: &TcpStream,
+    status: &str,
+    headers: &str,
+    body: &[u8],
+) -> std::io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\n{}Content-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        headers,
+        body.len()
+    )?;
+    stream.write_all(body)?;
+    stream.flush()
+}
+
+fn content_type(name: &str) -> &'static str {
+    match Path::new(name)
+        .extension()
+        .and_then(|extension| extension.to_str())
+    {
+        Some("html") => "text/html; charset=utf-8",
+        Some("js") => "text/javascript",
+        Some("css") => "text/css",
+        Some("json") => "application/json",
+        Some("png") => "image/png",
+        _ => "application/octet-stream",
+    }
+}
+
+// The viewer is served under / and /static/ (the url of meshcat-python's server)
+fn serve(
+    stream: &TcpStream,
+    path: &str,
+    static_dir: Option<&Path>,
+    viewer_script: Option<&str>,
+) -> std::io::Result<()> {
+    let path = path.split(['?', '#']).next().unwrap_or_default();
+    let name = path.trim_start_matches('/');
+    let name = match name.strip_prefix("static") {
+        Some(rest) if rest.is_empty() || rest.starts_with('/') => rest.trim_start_matches('/'),
+        _ => name,
+    };
+    let name = if name.is_empty() { "index.html" } else { name };
+    let is_safe = Path::new(name)
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)));
+    let file = static_dir
+        .filter(|_| is_safe)
+        .map(|directory| directory.join(name))
+        .filter(|file| file.is_file());
+    match (file, name) {
+        (None, "main.min.js") if viewer_script.is_some() => {
+            let headers = format!("Content-Type: {}\r\n", content_type(name));
+            respond(
+                stream,
+                "200 OK",
+                &headers,
+                viewer_script.unwrap().as_bytes(),
+            )
+        }
+        (Some(file), _) => {
+            let headers = format!("Content-Type: {}\r\n", content_type(name));
+            respond(stream, "200 OK", &headers, &std::fs::read(file)?)
+        }
+        (None, "index.html") => {
+            let headers = format!("Content-Type: {}\r\n", content_type(name));
+            respond(stream, "200 OK", &headers, INDEX_HTML.as_bytes())
+        }
+        (None, "main.min.js") => {
+            let headers = format!("Location: {}\r\n", VIEWER_SCRIPT_URL);
+            respond(stream, "302 Found", &headers, b"")
+        }
+        _ => respond(stream, "404 Not Found", "", b"Not found"),
+    }
+}
+
+// Serves the viewer to browsers, or sends the requests to the viewer connecting to the WebSocket
+fn handle_connection(
+    stream: TcpStream,
+    state: &Mutex<State>,
+    queue_size: usize,
+    static_dir: Option<&Path>,
+    viewer_script: Option<&str>,
+) -> Result<(), Box<dyn Error>> {
+    let request = read_request(&stream)?;
+    let Some(key) = request.websocket_key else {
+        return Ok(serve(&stream, &request.path, static_dir, viewer_script)?);
+    };
+    write!(
+        &stream,
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+         Sec-WebSocket-Accept: {}\r\n\r\n",
+        derive_accept_key(key.as_bytes())
+    )?;
+    let client = WebSocket::from_raw_socket(stream, Role::Server, None);
+    let queue = state.lock().unwrap().add_queue(queue_size);
+    send_queue(client, queue);
+    Ok(())
+}
+
+fn send_queue(mut client: WebSocket<TcpStream>, queue: ViewerQueue) {
+    let mut send = |request: Request| match client.send(Message::binary(request.to_vec())) {
+        Ok(()) => true,
+        Err(err) => {
+            info!("Viewer disconnected: {}", err);
+            false
+        }
+    };
+    loop {
+        let realtime = std::mem::take(&mut *queue.realtime.lock().unwrap());
+        if !realtime.into_values().all(&mut send) {
+            return;
+        }
+        let Ok(request) = queue.reliable.recv() else {
+            break;
+        };
+        if !request.is_empty() {
+            if !send(request) {
+                return;
+            }
+            queue.sent.fetch_add(1, Ordering::Release);
+        }
+    }
+    // The queue was dropped because the viewer fell behind
+    let _ = client.close(None);
+    let _ = client.flush();
+}
+
+// Receives the requests of Meshcat clients on a ZMQ REP socket and sends them to the viewers
+// connected to its WebSocket, like the meshcat-server of meshcat-python. The viewer page is
+// served on the same address (See MeshcatServer::viewer_url)
+pub struct MeshcatServer {
+    socket: zmq::Socket,
+    listener: TcpListener,
+    state: Arc<Mutex<State>>,
+    queue_size: usize,
+    static_dir: Option<PathBuf>,
+    viewer_script: Option<Arc<str>>,
+}
+
+impl MeshcatServer {
+    // e.g. MeshcatServer::new(DEFAULT_ZMQ_URL, "127.0.0.1:7000")
+    pub fn new(zmq_url: &str, websocket_address: &str) -> Result<Self, Box<dyn Error>> {
+        let socket = zmq::Context::new().socket(zmq::REP)?;
+        socket.bind(zmq_url)?;
+        Ok(Self {
+            socket,
+            listener: TcpListener::bind(websocket_address)?,
+            state: Arc::default(),
+            queue_size: DEFAULT_QUEUE_SIZE,
+            static_dir: None,
+            viewer_script: None,
+        })
+    }
+
+    pub fn with_queue_size(mut self, queue_size: usize) -> Self {
+        self.queue_size = queue_size;
+        self
+    }
+
+    // The files served under /static/, e.g. the viewer/dist directory of meshcat-python to serve
+    // the viewer's script without fetching it from VIEWER_SCRIPT_URL
+    pub fn with_static_dir(mut self, static_dir: impl Into<PathBuf>) -> Self {
+        self.static_dir = Some(static_dir.into());
+        self
+    }
+
+    // The viewer's script served when it isn't in the static directory, e.g.
+    // with_viewer_script(include_str!("main.min.js")) to embed a pinned bundle of the viewer
+    pub fn with_viewer_script(mut self, viewer_script: &str) -> Self {
+        self.viewer_script = Some(viewer_script.into());
+        self
+    }
+
+    // e.g. with_priority("set_property", Priority::Realtime) when only the latest property of
+    // each path matters to the viewers (the properties of a path then replace each other)
+    pub fn with_priority(self, request_type: &str, priority: Priority) -> Self {
+        self.state
+            .lock()
+            .unwrap()
+            .priorities
+            .insert(request_type.to_string(), priority);
+        self
+    }
+
+    // The endpoint the ZMQ socket is bound to (useful when binding to port *)
+    pub fn zmq_url(&self) -> Result<String, Box<dyn Error>> {
+        Ok(self
+            .socket
+            .get_last_endpoint()?
+            .map_err(|_| "The endpoint isn't valid utf-8")?)
+    }
+
+    pub fn websocket_url(&self) -> Result<String, Box<dyn Error>> {
+        Ok(format!("ws://{}", self.listener.local_addr()?))
+    }
+
+    // A self contained page with the scene published so far, the reply to the get_scene requests
+    // (See Meshcat::static_html). The viewer's script is inlined when it's in the static
+    // directory or embedded, otherwise the page loads it from VIEWER_SCRIPT_URL
+    pub fn static_html(&self) -> Result<String, Box<dyn Error>> {
+        let script = self
+            .static_dir
+            .as_ref()
+            .map(|directory| directory.join("main.min.js"))
+            .filter(|script| script.is_file())
+            .map(std::fs::read_to_string)
+            .transpose()?;
+        let script = script.as_deref().or(self.viewer_script.as_deref());
+        let mut requests = Vec::new();
+        self.state.lock().unwrap().tree.messages(&mut requests);
+        Ok(static_html(&requests, script))
+    }
+
+    // The page to open in a browser, the reply to the url requests (See Meshcat::url)
+    pub fn viewer_url(&self) -> Result<String, Box<dyn Error>> {
+        Ok(format!("http://{}/static/", self.listener.local_addr()?))
+    }
+
+    // Accepts viewers in a background thread and handles the ZMQ requests until an error occurs
+    pub fn run(self) -> Result<(), Box<dyn Error>> {
+        let listener = self.listener.try_clone()?;
+        let state = self.state.clone();
+        let queue_size = self.queue_size;
+        let static_dir = self.static_dir.clone();
+        let viewer_script = self.viewer_script.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(err) => {
+                        warn!("Failed to accept a connection: {}", err);
+                        continue;
+                    }
+                };
+                let state = state.clone();
+                let static_dir = static_dir.clone();
+                let viewer_script = viewer_script.clone();
+                std::thread::spawn(move || {
+                    if let Err(err) = handle_connection(
+                        stream,
+                        &state,
+                        queue_size,
+                        static_dir.as_deref(),
+                        viewer_script.as_deref(),
+                    ) {
+                        warn!("Failed to handle a connection: {}", err);
+                    }
+                });
+            }
+        });
+        loop {
+            let frames = self.socket.recv_multipart(0)?;
+            let request_type = String::from_utf8_lossy(&frames[0]);
+            let reply = match (request_type.as_ref(), frames.as_slice()) {
+                ("url", _) => self.viewer_url()?,
+                ("get_scene", _) => self.static_html()?,
+                (request_type, [_, path, data]) => {
+                    let path = String::from_utf8_lossy(path);
+                    if self.state.lock().unwrap().handle(request_type, &path, data) {
+                        "ok".to_string()
+                    } else {
+                        format!("error: unknown request type '{}'", request_type)
+                    }
+                }
+                (request_type, _) => format!("error: invalid '{}' request", request_type),
+            };
+            self.socket.send(reply.as_str(), 0)?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::*;
+
+    fn property(path: &str, value: PropertyType) -> Vec<u8> {
+        rmp_serde::encode::to_vec_named(&SetPropertyData::new(path, value)).unwrap()
+    }
+
+    #[test]
+    fn test_scene_tree() {
+        let mut state = State::default();
+        assert!(state.handle("set_transform", "/a/b", b"transform b"));
+        assert!(state.handle("set_animation", "/a", b"animation a"));
+        assert!(state.handle("set_animation", "/a", b"animation a2"));
+        assert!(state.handle("set_object", "/a", b"object a"));
+        assert!(state.handle("set_object", "/a", b"object a2"));
+        assert!(state.handle("set_object", "/c", b"object c"));
+        let hidden = property("/a", PropertyType::Visible(false));
+        assert!(state.handle("set_property", "/a", &hidden));
+        let shown = property("/a", PropertyType::Visible(true));
+        assert!(state.handle("set_property", "/a", &shown));
+        assert!(!state.handle("capture_image", "", b""));
+
+        let mut messages = Vec::new();
+        state.tree.messages(&mut messages);
+        assert_eq!(
+            messages.iter().map(AsRef::as_ref).collect::<Vec<_>>(),
+            [
+                b"object a2".as_slice(),
+                shown.as_slice(),
+                b"transform b",
+                b"object c",
+                b"animation a2"
+            ]
+        );
+
+        state.handle("delete", "/a/b", b"");
+        state.handle("delete", "/missing/b", b"");
+        let mut messages = Vec::new();
+        state.tree.messages(&mut messages);
+        assert_eq!(messages.len(), 4);
+        state.handle("delete", "/", b"");
+        let mut messages = Vec::new();
+        state.tree.messages(&mut messages);
+        assert!(messages.is_empty());
+    }
+
+    #[test]
+    fn test_queues() {
+        let mut state = State::default();
+        state.handle("set_object", "/a", b"object a");
+        let fast = state.add_queue(2);
+        let slow = state.add_queue(2);
+        state.handle("set_object", "/b", b"object b");
+        state.handle("set_object", "/c", b"object c");
+        assert_eq!(fast.reliable.try_iter().count(), 3);
+        assert_eq!(state.clients.len(), 2);
+        // The slow viewer's queue is full, it's disconnected instead of missing a request
+        state.handle("set_object", "/d", b"object d");
+        assert_eq!(state.clients.len(), 1);
+        assert_eq!(slow.reliable.iter().count(), 3);
+        assert_eq!(&*fast.reliable.recv().unwrap(), b"object d");
+
+        drop(fast);
+        state.handle("delete", "/d", b"");
+        assert!(state.clients.is_empty());
+    }
+
+    #[test]
+    fn test_priorities() {
+        let mut state = State::default();
+        let queue = state.add_queue(4);
+        state.handle("set_object", "/a", b"object a");
+        state.handle("set_transform", "/a", b"transform 1");
+        state.handle("set_transform", "/a", b"transform 2");
+        let realtime = |queue: &ViewerQueue| {
+            let realtime = std::mem::take(&mut *queue.realtime.lock().unwrap());
+            realtime.into_values().collect::<Vec<_>>()
+        };
+        assert_eq!(realtime(&queue), [Request::from(*b"transform 2")]);
+
+        // Until the delete is sent the transforms are queued after it
+        state.handle("delete", "/a", b"delete a");
+        state.handle("set_transform", "/a", b"transform 3");
+        assert!(realtime(&queue).is_empty());
+        assert_eq!(
+            queue.reliable.try_iter().collect::<Vec<_>>(),
+            [b"object a".as_slice(), b"", b"delete a", b"transform 3"].map(Request::from)
+        );
+        queue.sent.store(3, Ordering::Release);
+        state.handle("set_transform", "/a", b"transform 4");
+        assert_eq!(realtime(&queue), [Request::from(*b"transform 4")]);
+        assert_eq!(state.clients.len(), 1);
+    }
+
+    #[test]
+    fn test_replay_to_new_viewer() {
+        let server = MeshcatServer::new("tcp://127.0.0.1:*", "127.0.0.1:0").unwrap();
+        let zmq_url = server.zmq_url().unwrap();
+        let websocket_url = server.websocket_url().unwrap();
+        std::thread::spawn(move || server.run().unwrap());
+
+        let meshcat = Meshcat::new(&zmq_url).unwrap();
+        assert_eq!(
+            meshcat.url().unwrap(),
+            websocket_url.replace("ws://", "http://") + "/static/"
+        );
+        meshcat
+            .set_transform("/box", nalgebra::Isometry3::translation(1.0, 0.0, 0.0))
+            .unwrap();
+
+        // The viewer connects after the transform was sent
+        let (mut viewer, _) = tungstenite::connect(&websocket_url).unwrap();
+        let message = viewer.read().unwrap().into_data();
+        let data: serde_json::Value = rmp_serde::from_slice(&message).unwrap();
+        assert_eq!(data["type"], "set_transform");
+        assert_eq!(data["path"], "/box");
+
+        // Followed by the new requests, which all the viewers get
+        let (mut other_viewer, _) = tungstenite::connect(&websocket_url).unwrap();
+        other_viewer.read().unwrap();
+        meshcat.delete("/box").unwrap();
+        for viewer in [&mut viewer, &mut other_viewer] {
+            let message = viewer.read().unwrap().into_data();
+            let data: serde_json::Value = rmp_serde::from_slice(&message).unwrap();
+            assert_eq!(data["type"], "delete");
+        }
+    }
+
+    fn get(address: &str, path: &str) -> String {
+        let mut stream = TcpStream::connect(address).unwrap();
+        write!(stream, "GET {} HTTP/1.1\r\nHost: {}\r\n\r\n", path, address).unwrap();
+        let mut response = String::new();
+        std::io::Read::read_to_string(&mut stream, &mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn test_static_files() {
+        let name = format!("meshcat-server-{}", crate::types::new_uuid());
+        let directory = std::env::temp_dir().join(&name);
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(directory.join("style.css"), "body {}").unwrap();
+        let server = MeshcatServer::new("tcp://127.0.0.1:*", "127.0.0.1:0")
+            .unwrap()
+            .with_static_dir(&directory);
+        let address = server.listener.local_addr().unwrap().to_string();
+        std::thread::spawn(move || server.run().unwrap());
+
+        for path in ["/", "/static/", "/static/index.html?query"] {
+            let response = get(&address, path);
+            assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+            assert!(response.contains("new MeshCat.Viewer"));
+        }
+        let response = get(&address, "/static/main.min.js");
+        assert!(response.starts_with("HTTP/1.1 302 Found"));
+        assert!(response.contains(&format!("Location: {}", VIEWER_SCRIPT_URL)));
+        let response = get(&address, "/static/style.css");
+        assert!(response.contains("Content-Type: text/css"));
+        assert!(response.ends_with("body {}"));
+        let response = get(&address, &format!("/static/../{}/style.css", name));
+        assert!(response.starts_with("HTTP/1.1 404 Not Found"));
+    }
+
+    #[test]
+    fn test_static_html() {
+        use base64::Engine;
+        let server = MeshcatServer::new("tcp://127.0.0.1:*", "127.0.0.1:0").unwrap();
+        let meshcat = Meshcat::new(&server.zmq_url().unwrap()).unwrap();
+        std::thread::spawn(move || server.run().unwrap());
+        meshcat.delete("/box").unwrap();
+        meshcat
+            .set_property("/box", PropertyType::Visible(false))
+            .unwrap();
+        let html = meshcat.static_html().unwrap();
+        let request = property("/box", PropertyType::Visible(false));
+        let request = base64::engine::general_purpose::STANDARD.encode(request);
+        assert!(html.contains(&format!("\"{}\"", request)), "{}", html);
+        assert!(html.contains(&format!("<script src=\"{}\">", VIEWER_SCRIPT_URL)));
+
+        let html = static_html(&[], Some("var end = '</script>';"));
+        assert!(html.contains("<script>var end = '<\\/script>';</script>"));
+    }
+
+    #[test]
+    fn test_viewer_script() {
+        let server = MeshcatServer::new("tcp://127.0.0.1:*", "127.0.0.1:0")
+            .unwrap()
+            .with_viewer_script("var viewer = 'pinned';");
+        let address = server.listener.local_addr().unwrap().to_string();
+        let html = server.static_html().unwrap();
+        assert!(html.contains("<script>var viewer = 'pinned';</script>"));
+        assert!(!html.contains(VIEWER_SCRIPT_URL));
+        std::thread::spawn(move || server.run().unwrap());
+
+        let response = get(&address, "/static/main.min.js");
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+        assert!(response.ends_with("var viewer = 'pinned';"));
+    }
+}
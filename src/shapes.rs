@@ -0,0 +1,119 @@
+use std::error::Error;
+
+use nalgebra::Matrix3xX;
+
+use super::types::*;
+
+// The points of a cloud have to be given with their colors (in [0, 1])
+fn check_colors(points: &Matrix3xX<f64>, colors: &Matrix3xX<f64>) -> Result<(), Box<dyn Error>> {
+    if points.ncols() != colors.ncols() {
+        return Err(format!(
+            "Got {} points but {} colors",
+            points.ncols(),
+            colors.ncols()
+        )
+        .into());
+    }
+    Ok(())
+}
+
+// Vertex colored points published with Meshcat::set_point_cloud, e.g.
+// PointCloud::new(points, colors)?.with_size(0.005). Points appended to a published cloud are
+// sent with it again, a stream::PointCloudStream only sends the new ones
+#[derive(Clone, Debug)]
+pub struct PointCloud {
+    points: Matrix3xX<f64>,
+    colors: Matrix3xX<f64>,
+    // In meters
+    size: f64,
+}
+
+impl PointCloud {
+    pub fn new(points: Matrix3xX<f64>, colors: Matrix3xX<f64>) -> Result<Self, Box<dyn Error>> {
+        check_colors(&points, &colors)?;
+        Ok(PointCloud {
+            points,
+            colors,
+            size: 0.01,
+        })
+    }
+
+    // All the points have the color (0xRRGGBB)
+    pub fn with_color(points: Matrix3xX<f64>, color: u32) -> Self {
+        let color = crate::utils::hex_to_rgb(color);
+        PointCloud {
+            colors: Matrix3xX::from_fn(points.ncols(), |row, _| color[row]),
+            points,
+            size: 0.01,
+        }
+    }
+
+    pub fn with_size(mut self, size: f64) -> Self {
+        self.size = size;
+        self
+    }
+
+    pub fn append(
+        &mut self,
+        points: &Matrix3xX<f64>,
+        colors: &Matrix3xX<f64>,
+    ) -> Result<(), Box<dyn Error>> {
+        check_colors(points, colors)?;
+        // Grows the storage like a Vec instead of copying the points at each append
+        self.points.extend(points.column_iter());
+        self.colors.extend(colors.column_iter());
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.points.ncols()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.points.ncols() == 0
+    }
+
+    pub fn points(&self) -> &Matrix3xX<f64> {
+        &self.points
+    }
+
+    pub fn colors(&self) -> &Matrix3xX<f64> {
+        &self.colors
+    }
+
+    pub fn size(&self) -> f64 {
+        self.size
+    }
+
+    // See utils::point_cloud
+    pub fn to_object(&self) -> LumpedObject {
+        crate::utils::point_cloud(self.points.clone(), self.colors.clone(), self.size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra::Vector3;
+
+    #[test]
+    fn test_point_cloud() {
+        let mut cloud = PointCloud::with_color(Matrix3xX::zeros(2), 0xff0000).with_size(0.5);
+        assert_eq!(cloud.colors().column(1), Vector3::x());
+        cloud
+            .append(&Matrix3xX::from_element(3, 1.0), &Matrix3xX::zeros(3))
+            .unwrap();
+        assert_eq!(cloud.len(), 5);
+        assert_eq!(cloud.points().column(4), Vector3::from_element(1.0));
+        assert_eq!(cloud.colors().column(4), Vector3::zeros());
+        assert!(cloud
+            .append(&Matrix3xX::zeros(1), &Matrix3xX::zeros(2))
+            .is_err());
+        let object = cloud.to_object();
+        assert!(matches!(
+            object.material.material_type,
+            MaterialType::Points { size } if size == 0.5
+        ));
+        assert!(PointCloud::new(Matrix3xX::zeros(1), Matrix3xX::zeros(0)).is_err());
+    }
+}
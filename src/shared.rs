@@ -0,0 +1,165 @@
+use std::borrow::Borrow;
+use std::error::Error;
+use std::sync::mpsc::{channel, Sender};
+
+use nalgebra::Isometry3;
+
+use crate::types::*;
+
+type Job = Box<dyn FnOnce(&Meshcat) + Send>;
+
+// A cloneable handle to a Meshcat owned by a worker thread, so several threads (e.g. the threads
+// of a simulation) can publish with the same connection, a Meshcat can't be sent to another
+// thread. Each call waits for the worker to send its request, the requests of the handles are
+// sent in the order the worker receives them. The worker stops once all the handles are dropped
+#[derive(Clone)]
+pub struct SharedMeshcat {
+    jobs: Sender<Job>,
+}
+
+impl SharedMeshcat {
+    #[cfg(feature = "zmq")]
+    pub fn new(endpoint: &str) -> Result<Self, Box<dyn Error>> {
+        let endpoint = endpoint.to_string();
+        Self::spawn(move || Ok(Meshcat::new(&endpoint)?))
+    }
+
+    // The Meshcat is created by the worker, e.g.
+    // SharedMeshcat::spawn(|| Ok(Meshcat::try_new(DEFAULT_ZMQ_URL, &options)?))
+    pub fn spawn(
+        connect: impl FnOnce() -> Result<Meshcat, Box<dyn Error>> + Send + 'static,
+    ) -> Result<Self, Box<dyn Error>> {
+        let (jobs, receiver) = channel::<Job>();
+        let (connected, connection) = channel();
+        std::thread::Builder::new()
+            .name("meshcat".to_string())
+            .spawn(move || {
+                let meshcat = match connect() {
+                    Ok(meshcat) => meshcat,
+                    Err(err) => {
+                        let _ = connected.send(Err(err.to_string()));
+                        return;
+                    }
+                };
+                let _ = connected.send(Ok(()));
+                for job in receiver {
+                    job(&meshcat);
+                }
+            })?;
+        connection
+            .recv()
+            .map_err(|_| "The meshcat worker stopped")??;
+        Ok(Self { jobs })
+    }
+
+    // Runs f with the worker's Meshcat and waits for its result, e.g. for the Meshcat functions
+    // without a SharedMeshcat counterpart
+    pub fn run<T: Send + 'static>(
+        &self,
+        f: impl FnOnce(&Meshcat) -> Result<T, Box<dyn Error>> + Send + 'static,
+    ) -> Result<T, Box<dyn Error>> {
+        let (result, receiver) = channel();
+        self.jobs
+            .send(Box::new(move |meshcat| {
+                let _ = result.send(f(meshcat).map_err(|err| err.to_string()));
+            }))
+            .map_err(|_| "The meshcat worker stopped")?;
+        Ok(receiver
+            .recv()
+            .map_err(|_| "The meshcat worker stopped")??)
+    }
+
+    pub fn url(&self) -> Result<String, Box<dyn Error>> {
+        self.run(|meshcat| meshcat.url())
+    }
+
+    // e.g. an Arc<LumpedObject> to publish the same object from several threads without cloning
+    // it
+    pub fn set_object(
+        &self,
+        path: impl AsRef<str>,
+        object: impl Borrow<LumpedObject> + Send + 'static,
+    ) -> Result<(), Box<dyn Error>> {
+        let path = path.as_ref().to_string();
+        self.run(move |meshcat| meshcat.set_object(&path, object))
+    }
+
+    pub fn set_transform(
+        &self,
+        path: impl AsRef<str>,
+        matrix: Isometry3<f64>,
+    ) -> Result<(), Box<dyn Error>> {
+        let path = path.as_ref().to_string();
+        self.run(move |meshcat| meshcat.set_transform(&path, matrix))
+    }
+
+    pub fn set_property(
+        &self,
+        path: impl AsRef<str>,
+        value: PropertyType,
+    ) -> Result<(), Box<dyn Error>> {
+        let path = path.as_ref().to_string();
+        self.run(move |meshcat| meshcat.set_property(&path, value))
+    }
+
+    pub fn delete(&self, path: impl AsRef<str>) -> Result<(), Box<dyn Error>> {
+        let path = path.as_ref().to_string();
+        self.run(move |meshcat| meshcat.delete(&path))
+    }
+
+    pub fn flush(&self) -> Result<(), Box<dyn Error>> {
+        self.run(|meshcat| meshcat.flush())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Forwards the paths of the requests
+    struct Forward(Sender<String>);
+
+    impl crate::transport::Transport for Forward {
+        fn request(&self, _: &str, path: &str, _: &[u8]) -> Result<(), Box<dyn Error>> {
+            self.0.send(path.to_string())?;
+            Ok(())
+        }
+
+        fn url(&self) -> Result<String, Box<dyn Error>> {
+            Ok("http://viewer".to_string())
+        }
+    }
+
+    #[test]
+    fn test_shared_meshcat() {
+        assert!(SharedMeshcat::spawn(|| Err("No server".into())).is_err());
+
+        let (paths, received) = channel();
+        let meshcat =
+            SharedMeshcat::spawn(move || Ok(Meshcat::with_transport(Forward(paths)))).unwrap();
+        assert_eq!(meshcat.url().unwrap(), "http://viewer");
+        let threads = (0..4)
+            .map(|thread| {
+                let meshcat = meshcat.clone();
+                std::thread::spawn(move || {
+                    meshcat
+                        .set_transform(format!("/{}", thread), Isometry3::identity())
+                        .unwrap()
+                })
+            })
+            .collect::<Vec<_>>();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        let object = std::sync::Arc::new(LumpedObject::builder().geometries(vec![]).build());
+        meshcat.set_object("/shared", object.clone()).unwrap();
+        let err = meshcat
+            .run(|_| -> Result<(), _> { Err("Failed".into()) })
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Failed");
+        drop(meshcat);
+        let mut paths = received.iter().collect::<Vec<_>>();
+        paths.sort();
+        assert_eq!(paths, ["/0", "/1", "/2", "/3", "/shared"]);
+    }
+}
@@ -0,0 +1,502 @@
+use std::collections::{HashSet, VecDeque};
+use std::error::Error;
+
+use nalgebra::Matrix3xX;
+
+use super::shared::SharedMeshcat;
+use super::types::*;
+
+struct Chunk {
+    index: usize,
+    points: Matrix3xX<f64>,
+    colors: Matrix3xX<f64>,
+}
+
+// A point cloud that grows by publishing only the new points, each push is a child object
+// {path}/{chunk index}. Once there are more than max_points, the oldest points are dropped by
+// deleting (or trimming) the oldest chunks
+pub struct PointCloudStream {
+    path: String,
+    max_points: usize,
+    point_size: f64,
+    chunks: VecDeque<Chunk>,
+    next_index: usize,
+    len: usize,
+}
+
+impl PointCloudStream {
+    pub fn new(path: &str, max_points: usize, point_size: f64) -> Self {
+        PointCloudStream {
+            path: path.to_string(),
+            max_points,
+            point_size,
+            chunks: VecDeque::new(),
+            next_index: 0,
+            len: 0,
+        }
+    }
+
+    // Number of points currently published
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn push(
+        &mut self,
+        meshcat: &Meshcat,
+        points: Matrix3xX<f64>,
+        colors: Matrix3xX<f64>,
+    ) -> Result<(), Box<dyn Error>> {
+        self.updates(points, colors)?
+            .into_iter()
+            .try_for_each(|command| meshcat.send(command))
+    }
+
+    // Publishes the points of the cloud (its size is ignored, the stream has its own)
+    pub fn push_point_cloud(
+        &mut self,
+        meshcat: &Meshcat,
+        cloud: &crate::shapes::PointCloud,
+    ) -> Result<(), Box<dyn Error>> {
+        self.push(meshcat, cloud.points().clone(), cloud.colors().clone())
+    }
+
+    // Deletes all the chunks
+    pub fn clear(&mut self, meshcat: &Meshcat) -> Result<(), Box<dyn Error>> {
+        self.chunks.clear();
+        self.len = 0;
+        meshcat.delete(&self.path)
+    }
+
+    fn chunk_path(&self, index: usize) -> String {
+        format!("{}/{}", self.path, index)
+    }
+
+    fn updates(
+        &mut self,
+        mut points: Matrix3xX<f64>,
+        mut colors: Matrix3xX<f64>,
+    ) -> Result<Vec<Command>, Box<dyn Error>> {
+        if points.ncols() != colors.ncols() {
+            return Err(format!(
+                "Got {} points but {} colors",
+                points.ncols(),
+                colors.ncols()
+            )
+            .into());
+        }
+        // Only the newest points of a push larger than the window are kept
+        if points.ncols() > self.max_points {
+            let excess = points.ncols() - self.max_points;
+            points = points.columns(excess, self.max_points).into_owned();
+            colors = colors.columns(excess, self.max_points).into_owned();
+        }
+        let mut updates = Vec::new();
+        if points.ncols() == 0 {
+            return Ok(updates);
+        }
+        updates.push(Command::SetObject(
+            self.chunk_path(self.next_index),
+            Box::new(crate::utils::point_cloud(
+                points.clone(),
+                colors.clone(),
+                self.point_size,
+            )),
+        ));
+        self.len += points.ncols();
+        self.chunks.push_back(Chunk {
+            index: self.next_index,
+            points,
+            colors,
+        });
+        self.next_index += 1;
+
+        while self.len > self.max_points {
+            let excess = self.len - self.max_points;
+            let path = self.chunk_path(self.chunks.front().unwrap().index);
+            let oldest = self.chunks.front_mut().unwrap();
+            let oldest_len = oldest.points.ncols();
+            if oldest_len <= excess {
+                updates.push(Command::Delete(path));
+                self.chunks.pop_front();
+                self.len -= oldest_len;
+            } else {
+                let remaining = oldest_len - excess;
+                oldest.points = oldest.points.columns(excess, remaining).into_owned();
+                oldest.colors = oldest.colors.columns(excess, remaining).into_owned();
+                let object = crate::utils::point_cloud(
+                    oldest.points.clone(),
+                    oldest.colors.clone(),
+                    self.point_size,
+                );
+                updates.push(Command::SetObject(path, Box::new(object)));
+                self.len -= excess;
+            }
+        }
+        Ok(updates)
+    }
+}
+
+// Replaces an object without the frame where it disappears while the viewer loads the new one.
+// The object alternates between two slots {path}/0 and {path}/1, the new version is uploaded to
+// the hidden slot which is then shown before hiding the current one
+pub struct DoubleBufferedObject {
+    path: String,
+    active: Option<usize>,
+}
+
+impl DoubleBufferedObject {
+    pub fn new(path: &str) -> Self {
+        DoubleBufferedObject {
+            path: path.to_string(),
+            active: None,
+        }
+    }
+
+    // The path of the visible slot, if an object has been set
+    pub fn active_path(&self) -> Option<String> {
+        self.active.map(|slot| self.slot_path(slot))
+    }
+
+    pub fn set_object(
+        &mut self,
+        meshcat: &Meshcat,
+        object: LumpedObject,
+    ) -> Result<(), Box<dyn Error>> {
+        self.updates(object)
+            .into_iter()
+            .try_for_each(|command| meshcat.send(command))
+    }
+
+    fn slot_path(&self, slot: usize) -> String {
+        format!("{}/{}", self.path, slot)
+    }
+
+    fn updates(&mut self, object: LumpedObject) -> Vec<Command> {
+        let slot = self.active.map_or(0, |active| 1 - active);
+        let slot_path = self.slot_path(slot);
+        // The object is nested under the slot so replacing it keeps the slot's visibility
+        let mut updates = vec![
+            Command::SetProperty(slot_path.clone(), PropertyType::Visible(false)),
+            Command::SetObject(format!("{}/object", slot_path), Box::new(object)),
+            Command::SetProperty(slot_path, PropertyType::Visible(true)),
+        ];
+        if let Some(active) = self.active {
+            updates.push(Command::SetProperty(
+                self.slot_path(active),
+                PropertyType::Visible(false),
+            ));
+        }
+        self.active = Some(slot);
+        updates
+    }
+}
+
+// The deepest octree level used to order the points of a ProgressivePointCloud
+const MAX_OCTREE_DEPTH: u32 = 12;
+
+// The indices of the points ordered level by level of an octree over their bounding box: one
+// point per cell at each level, so every prefix is spread over the whole cloud
+fn octree_order(points: &Matrix3xX<f64>) -> Vec<usize> {
+    let count = points.ncols();
+    if count == 0 {
+        return Vec::new();
+    }
+    let min = points
+        .column_iter()
+        .fold(points.column(0).into_owned(), |min, point| {
+            min.inf(&point.into_owned())
+        });
+    let max = points
+        .column_iter()
+        .fold(points.column(0).into_owned(), |max, point| {
+            max.sup(&point.into_owned())
+        });
+    let extent = (max - min).max().max(f64::EPSILON);
+    let mut chosen = vec![false; count];
+    let mut order = Vec::with_capacity(count);
+    for depth in 0..=MAX_OCTREE_DEPTH {
+        let cell_size = extent / (1u64 << depth) as f64;
+        let cell = |index: usize| {
+            let cell = (points.column(index) - min) / cell_size;
+            (cell.x as i64, cell.y as i64, cell.z as i64)
+        };
+        // The cells that already have a point from the coarser levels
+        let mut cells = order
+            .iter()
+            .map(|&index| cell(index))
+            .collect::<HashSet<_>>();
+        for (index, chosen) in chosen.iter_mut().enumerate() {
+            if !*chosen && cells.insert(cell(index)) {
+                *chosen = true;
+                order.push(index);
+            }
+        }
+        if order.len() == count {
+            return order;
+        }
+    }
+    // The points sharing a cell of the deepest level
+    order.extend((0..count).filter(|&index| !chosen[index]));
+    order
+}
+
+// Publishes a cloud too large to upload at once in chunks, the first one is a coarse subsample
+// of the whole cloud and the next ones refine it (See octree_order). Each chunk is a child
+// object {path}/{chunk index}. spawn sends the chunks from a background thread, otherwise the
+// caller paces them, sending one with send_next between its other updates (or all of them with
+// send_all)
+pub struct ProgressivePointCloud {
+    path: String,
+    points: Matrix3xX<f64>,
+    colors: Matrix3xX<f64>,
+    point_size: f64,
+    chunk_size: usize,
+    order: Vec<usize>,
+    sent: usize,
+}
+
+impl ProgressivePointCloud {
+    pub fn new(
+        path: &str,
+        points: Matrix3xX<f64>,
+        colors: Matrix3xX<f64>,
+        point_size: f64,
+        chunk_size: usize,
+    ) -> Result<Self, Box<dyn Error>> {
+        if points.ncols() != colors.ncols() {
+            return Err(format!(
+                "Got {} points but {} colors",
+                points.ncols(),
+                colors.ncols()
+            )
+            .into());
+        }
+        if chunk_size == 0 {
+            return Err("The chunk size can't be 0".into());
+        }
+        Ok(ProgressivePointCloud {
+            path: path.to_string(),
+            order: octree_order(&points),
+            points,
+            colors,
+            point_size,
+            chunk_size,
+            sent: 0,
+        })
+    }
+
+    // The fraction of the points published
+    pub fn progress(&self) -> f64 {
+        if self.order.is_empty() {
+            1.0
+        } else {
+            self.sent as f64 / self.order.len() as f64
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.sent == self.order.len()
+    }
+
+    // Sends the next chunk, returns false once all the points are published
+    pub fn send_next(&mut self, meshcat: &Meshcat) -> Result<bool, Box<dyn Error>> {
+        if let Some(command) = self.next_chunk() {
+            meshcat.send(command)?;
+        }
+        Ok(!self.is_done())
+    }
+
+    pub fn send_all(&mut self, meshcat: &Meshcat) -> Result<(), Box<dyn Error>> {
+        while self.send_next(meshcat)? {}
+        Ok(())
+    }
+
+    // Sends the remaining chunks from a thread through the SharedMeshcat, the requests of its
+    // other handles are sent between the chunks. The thread returns the first error
+    pub fn spawn(mut self, meshcat: SharedMeshcat) -> std::thread::JoinHandle<Result<(), String>> {
+        std::thread::spawn(move || {
+            while let Some(command) = self.next_chunk() {
+                meshcat
+                    .run(move |meshcat| meshcat.send(command))
+                    .map_err(|err| err.to_string())?;
+            }
+            Ok(())
+        })
+    }
+
+    fn next_chunk(&mut self) -> Option<Command> {
+        if self.is_done() {
+            return None;
+        }
+        let indices = &self.order[self.sent..(self.sent + self.chunk_size).min(self.order.len())];
+        let object = crate::utils::point_cloud(
+            self.points.select_columns(indices),
+            self.colors.select_columns(indices),
+            self.point_size,
+        );
+        let path = format!("{}/{}", self.path, self.sent / self.chunk_size);
+        self.sent += indices.len();
+        Some(Command::SetObject(path, Box::new(object)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paths(updates: &[Command]) -> Vec<String> {
+        updates
+            .iter()
+            .map(|update| match update {
+                Command::SetObject(path, _) => format!("set {}", path),
+                Command::SetProperty(path, value) => format!("{} {:?}", path, value),
+                Command::Delete(path) => format!("delete {}", path),
+                Command::SetTransform(path, _) => format!("transform {}", path),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_rolling_window() {
+        let mut stream = PointCloudStream::new("/cloud", 10, 0.01);
+        let updates = stream
+            .updates(Matrix3xX::zeros(4), Matrix3xX::zeros(4))
+            .unwrap();
+        assert_eq!(paths(&updates), ["set /cloud/0"]);
+        stream
+            .updates(Matrix3xX::zeros(4), Matrix3xX::zeros(4))
+            .unwrap();
+        assert_eq!(stream.len(), 8);
+
+        // The first chunk is trimmed to 2 points
+        let updates = stream
+            .updates(Matrix3xX::zeros(4), Matrix3xX::zeros(4))
+            .unwrap();
+        assert_eq!(paths(&updates), ["set /cloud/2", "set /cloud/0"]);
+        assert_eq!(stream.len(), 10);
+        assert_eq!(stream.chunks[0].points.ncols(), 2);
+
+        // The first chunk is deleted and the second one trimmed
+        let updates = stream
+            .updates(Matrix3xX::zeros(5), Matrix3xX::zeros(5))
+            .unwrap();
+        assert_eq!(
+            paths(&updates),
+            ["set /cloud/3", "delete /cloud/0", "set /cloud/1"]
+        );
+        assert_eq!(stream.len(), 10);
+
+        // A push larger than the window replaces everything
+        let updates = stream
+            .updates(Matrix3xX::zeros(20), Matrix3xX::zeros(20))
+            .unwrap();
+        assert_eq!(
+            paths(&updates),
+            [
+                "set /cloud/4",
+                "delete /cloud/1",
+                "delete /cloud/2",
+                "delete /cloud/3"
+            ]
+        );
+        assert_eq!(stream.len(), 10);
+        assert_eq!(stream.chunks.len(), 1);
+
+        assert!(stream
+            .updates(Matrix3xX::zeros(2), Matrix3xX::zeros(1))
+            .is_err());
+        assert!(stream
+            .updates(Matrix3xX::zeros(0), Matrix3xX::zeros(0))
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_double_buffered_object() {
+        let mut object = DoubleBufferedObject::new("/mesh");
+        assert!(object.active_path().is_none());
+        let updates = object.updates(LumpedObject::builder().geometries(vec![]).build());
+        assert_eq!(
+            paths(&updates),
+            [
+                "/mesh/0 Visible(false)",
+                "set /mesh/0/object",
+                "/mesh/0 Visible(true)"
+            ]
+        );
+        assert_eq!(object.active_path().unwrap(), "/mesh/0");
+        let updates = object.updates(LumpedObject::builder().geometries(vec![]).build());
+        assert_eq!(
+            paths(&updates),
+            [
+                "/mesh/1 Visible(false)",
+                "set /mesh/1/object",
+                "/mesh/1 Visible(true)",
+                "/mesh/0 Visible(false)"
+            ]
+        );
+        assert_eq!(object.active_path().unwrap(), "/mesh/1");
+        object.updates(LumpedObject::builder().geometries(vec![]).build());
+        assert_eq!(object.active_path().unwrap(), "/mesh/0");
+    }
+
+    #[test]
+    fn test_progressive_point_cloud() {
+        // 100 points in each octant of the unit cube
+        let points = Matrix3xX::from_fn(800, |row, column| {
+            let octant = column % 8;
+            let offset = (column / 8) as f64 / 200.0;
+            ((octant >> row) & 1) as f64 * 0.5 + offset
+        });
+        let order = octree_order(&points);
+        let mut sorted = order.clone();
+        sorted.sort();
+        assert_eq!(sorted, (0..800).collect::<Vec<_>>());
+        // The first points cover all the octants
+        let octants = order[..8]
+            .iter()
+            .map(|index| index % 8)
+            .collect::<HashSet<_>>();
+        assert_eq!(octants.len(), 8);
+
+        let mut cloud =
+            ProgressivePointCloud::new("/map", points.clone(), Matrix3xX::zeros(800), 0.01, 300)
+                .unwrap();
+        let spawned =
+            ProgressivePointCloud::new("/map", points, Matrix3xX::zeros(800), 0.01, 300).unwrap();
+        let chunks = std::iter::from_fn(|| cloud.next_chunk()).collect::<Vec<_>>();
+        assert_eq!(paths(&chunks), ["set /map/0", "set /map/1", "set /map/2"]);
+        assert!(cloud.is_done());
+        assert_eq!(cloud.progress(), 1.0);
+
+        let meshcat = SharedMeshcat::spawn(|| {
+            Ok(Meshcat::with_transport(
+                crate::transport::Recorder::default(),
+            ))
+        })
+        .unwrap();
+        spawned.spawn(meshcat.clone()).join().unwrap().unwrap();
+        let published = meshcat
+            .run(|meshcat| {
+                let tree = meshcat.tree();
+                Ok(["/map/0", "/map/1", "/map/2"]
+                    .iter()
+                    .all(|path| tree.contains(path)))
+            })
+            .unwrap();
+        assert!(published);
+        assert!(ProgressivePointCloud::new(
+            "/map",
+            Matrix3xX::zeros(2),
+            Matrix3xX::zeros(1),
+            0.01,
+            1
+        )
+        .is_err());
+    }
+}
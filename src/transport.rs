@@ -0,0 +1,575 @@
+use std::error::Error;
+
+#[cfg(feature = "zmq")]
+use log::info;
+use serde::Serialize;
+#[cfg(feature = "zmq")]
+use std::cell::{Cell, RefCell};
+#[cfg(feature = "zmq")]
+use std::collections::VecDeque;
+#[cfg(feature = "zmq")]
+use std::time::Duration;
+#[cfg(feature = "zmq")]
+use typed_builder::TypedBuilder;
+
+// How Meshcat encodes the requests (See Meshcat::with_encoding), meshcat's viewer and server
+// expect MessagePack. JSON is readable, e.g. to debug a custom transport, and can be sent to
+// WebSocket text endpoints
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Encoding {
+    #[default]
+    MessagePack,
+    Json,
+}
+
+impl Encoding {
+    // Appends the encoded data to buf
+    pub fn encode(&self, buf: &mut Vec<u8>, data: &impl Serialize) -> Result<(), Box<dyn Error>> {
+        match self {
+            Encoding::MessagePack => rmp_serde::encode::write_named(buf, data)?,
+            Encoding::Json => serde_json::to_writer(buf, data)?,
+        }
+        Ok(())
+    }
+
+    pub fn to_vec(&self, data: &impl Serialize) -> Result<Vec<u8>, Box<dyn Error>> {
+        let mut buf = Vec::new();
+        self.encode(&mut buf, data)?;
+        Ok(buf)
+    }
+
+    // Like to_vec for the requests carrying objects, with the rayon feature their large buffer
+    // attributes are encoded in parallel
+    pub(crate) fn object_to_vec(&self, data: &impl Serialize) -> Result<Vec<u8>, Box<dyn Error>> {
+        let mut buf = Vec::new();
+        match self {
+            #[cfg(feature = "rayon")]
+            Encoding::MessagePack => crate::parallel::write_named(&mut buf, data)?,
+            _ => self.encode(&mut buf, data)?,
+        }
+        Ok(buf)
+    }
+}
+
+// How the requests reach the meshcat server, a request is its type, its path and its msgpack
+// encoded data
+pub trait Transport {
+    fn request(&self, request_type: &str, path: &str, data: &[u8]) -> Result<(), Box<dyn Error>>;
+
+    // The url of the viewer
+    fn url(&self) -> Result<String, Box<dyn Error>>;
+
+    // A self contained HTML page of the scene published so far
+    fn static_html(&self) -> Result<String, Box<dyn Error>> {
+        Err("The transport can't get the scene from the server".into())
+    }
+
+    // Waits for the replies of the requests still in flight (See PipelinedZmqTransport)
+    fn flush(&self) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+}
+
+// How Meshcat::try_new connects to the meshcat-server, e.g. so a CI job without a server fails
+// instead of hanging. A timeout of None blocks (like Meshcat::new)
+#[cfg(feature = "zmq")]
+#[derive(Clone, Debug, TypedBuilder)]
+pub struct MeshcatOptions {
+    // How long try_new waits for the server to reply to a first request
+    #[builder(default = Some(Duration::from_secs(5)))]
+    pub connect_timeout: Option<Duration>,
+    #[builder(default, setter(strip_option))]
+    pub send_timeout: Option<Duration>,
+    // How long a request waits for its reply before it's sent again (or fails)
+    #[builder(default, setter(strip_option))]
+    pub receive_timeout: Option<Duration>,
+    // The number of times a request without a reply is sent again, meshcat's requests are
+    // idempotent so a request received twice is harmless
+    #[builder(default)]
+    pub retries: u32,
+    // false sends the requests without waiting for their replies (See
+    // PipelinedZmqTransport::fire_and_forget), e.g. to stream transforms at 1 kHz, only the
+    // connect timeout applies
+    #[builder(default = true)]
+    pub wait_for_replies: bool,
+}
+
+#[cfg(feature = "zmq")]
+impl Default for MeshcatOptions {
+    fn default() -> Self {
+        MeshcatOptions::builder().build()
+    }
+}
+
+// zmq's timeouts are in milliseconds, -1 blocks
+#[cfg(feature = "zmq")]
+fn timeout_millis(timeout: Option<Duration>) -> i32 {
+    timeout.map_or(-1, |timeout| {
+        timeout.as_millis().min(i32::MAX as u128) as i32
+    })
+}
+
+// The REQ socket of the meshcat-server (the default transport)
+#[cfg(feature = "zmq")]
+pub struct ZmqTransport {
+    socket: zmq::Socket,
+    retries: u32,
+    receive_timeout: Option<Duration>,
+}
+
+#[cfg(feature = "zmq")]
+impl ZmqTransport {
+    pub fn new(endpoint: &str) -> Result<Self, crate::Error> {
+        let socket = zmq::Context::new().socket(zmq::REQ)?;
+        socket.connect(endpoint)?;
+        Ok(Self {
+            socket,
+            retries: 0,
+            receive_timeout: None,
+        })
+    }
+
+    // Doesn't wait for the server (See Meshcat::try_new)
+    pub fn with_options(endpoint: &str, options: &MeshcatOptions) -> Result<Self, crate::Error> {
+        let socket = zmq::Context::new().socket(zmq::REQ)?;
+        socket.set_sndtimeo(timeout_millis(options.send_timeout))?;
+        socket.set_rcvtimeo(timeout_millis(options.receive_timeout))?;
+        // A request can be sent again without its reply, a late reply of the previous attempt
+        // is dropped
+        socket.set_req_relaxed(true)?;
+        socket.set_req_correlate(true)?;
+        // Dropping the socket doesn't wait for the requests the server never received
+        socket.set_linger(0)?;
+        socket.connect(endpoint)?;
+        Ok(Self {
+            socket,
+            retries: options.retries,
+            receive_timeout: options.receive_timeout,
+        })
+    }
+
+    // Sends the frames and receives the reply, sending them again when the reply times out
+    fn exchange<T>(
+        &self,
+        frames: &[&[u8]],
+        mut receive: impl FnMut(&zmq::Socket) -> zmq::Result<T>,
+    ) -> Result<T, Box<dyn Error>> {
+        for attempt in 0..=self.retries {
+            self.socket
+                .send_multipart(frames, 0)
+                .map_err(|err| match err {
+                    zmq::Error::EAGAIN => "Timed out sending a request to the server".into(),
+                    err => Box::<dyn Error>::from(err),
+                })?;
+            match receive(&self.socket) {
+                Err(zmq::Error::EAGAIN) if attempt < self.retries => {
+                    info!("No reply within {:?}, retrying", self.receive_timeout);
+                }
+                Err(zmq::Error::EAGAIN) => break,
+                result => return Ok(result?),
+            }
+        }
+        Err(format!(
+            "No reply from the server within {:?} ({} retries)",
+            self.receive_timeout.unwrap_or_default(),
+            self.retries
+        )
+        .into())
+    }
+
+    fn query(&self, request_type: &str) -> Result<String, Box<dyn Error>> {
+        Ok(self
+            .exchange(&[request_type.as_bytes()], |socket| socket.recv_string(0))?
+            .map_err(|_| format!("The reply to '{}' isn't valid utf-8", request_type))?)
+    }
+}
+
+#[cfg(feature = "zmq")]
+impl Transport for ZmqTransport {
+    // Reads the reply without allocating
+    fn request(&self, request_type: &str, path: &str, data: &[u8]) -> Result<(), Box<dyn Error>> {
+        let mut reply = [0; 64];
+        let size = self.exchange(
+            &[request_type.as_bytes(), path.as_bytes(), data],
+            |socket| socket.recv_into(&mut reply, 0),
+        )?;
+        let message = std::str::from_utf8(&reply[..size.min(reply.len())])
+            .map_err(|_| "The reply isn't valid utf-8")?;
+        info!("Received reply {} {}", 0, message);
+        Ok(())
+    }
+
+    fn url(&self) -> Result<String, Box<dyn Error>> {
+        self.query("url")
+    }
+
+    // Like meshcat-python's Visualizer::static_html
+    fn static_html(&self) -> Result<String, Box<dyn Error>> {
+        self.query("get_scene")
+    }
+}
+
+// The default send high water mark of a zmq socket, the window of a fire and forget
+// PipelinedZmqTransport when the socket's is unlimited
+#[cfg(feature = "zmq")]
+const DEFAULT_SEND_HWM: usize = 1000;
+
+// Like ZmqTransport but doesn't wait for the reply of a request before sending the next one, up
+// to window requests are in flight. The server replies in order so the replies are matched to
+// the requests by their ids in the order they were sent, a failed request is reported by the
+// request that reads its reply (or by flush)
+#[cfg(feature = "zmq")]
+pub struct PipelinedZmqTransport {
+    socket: zmq::Socket,
+    window: usize,
+    // The replies are read when they arrive instead of when the window is full
+    fire_and_forget: bool,
+    next_id: Cell<u64>,
+    // The ids of the requests whose reply wasn't received yet, their type and path are logged
+    // when they're sent
+    in_flight: RefCell<VecDeque<u64>>,
+}
+
+#[cfg(feature = "zmq")]
+impl PipelinedZmqTransport {
+    // e.g. PipelinedZmqTransport::new(DEFAULT_ZMQ_URL, 64), a window of 1 is the lockstep of
+    // ZmqTransport
+    pub fn new(endpoint: &str, window: usize) -> Result<Self, crate::Error> {
+        if window == 0 {
+            return Err(crate::Error::InvalidArgument(
+                "The window has to be at least 1".to_string(),
+            ));
+        }
+        Self::connect(endpoint, Some(window), false)
+    }
+
+    // Doesn't wait for a reply before sending a request unless the socket's send high water mark
+    // of requests are in flight, the replies received so far are read (and the failed requests
+    // reported) by the next request
+    pub fn fire_and_forget(endpoint: &str) -> Result<Self, crate::Error> {
+        Self::connect(endpoint, None, true)
+    }
+
+    // The window defaults to the send high water mark of the socket
+    fn connect(
+        endpoint: &str,
+        window: Option<usize>,
+        fire_and_forget: bool,
+    ) -> Result<Self, crate::Error> {
+        // A DEALER socket talks to the server's REP socket like a REQ socket without the
+        // lockstep, the frames start with an empty delimiter
+        let socket = zmq::Context::new().socket(zmq::DEALER)?;
+        socket.connect(endpoint)?;
+        let window = match window {
+            Some(window) => window,
+            // A high water mark of 0 is unlimited
+            None => match socket.get_sndhwm()? {
+                hwm if hwm > 0 => hwm as usize,
+                _ => DEFAULT_SEND_HWM,
+            },
+        };
+        Ok(Self {
+            socket,
+            window,
+            fire_and_forget,
+            next_id: Cell::new(0),
+            in_flight: RefCell::default(),
+        })
+    }
+
+    // The number of requests whose reply wasn't received yet
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.borrow().len()
+    }
+
+    // Sends a request without a path or data once the requests in flight are replied
+    fn query(&self, request_type: &str) -> Result<String, Box<dyn Error>> {
+        self.flush()?;
+        self.socket
+            .send_multipart([b"".as_slice(), request_type.as_bytes()], 0)?;
+        let mut frames = self.socket.recv_multipart(0)?;
+        let reply = frames.pop().ok_or("Received an empty reply")?;
+        Ok(String::from_utf8(reply)
+            .map_err(|_| format!("The reply to '{}' isn't valid utf-8", request_type))?)
+    }
+
+    fn receive_reply(&self) -> Result<(), Box<dyn Error>> {
+        let frames = self.socket.recv_multipart(0)?;
+        let id = self
+            .in_flight
+            .borrow_mut()
+            .pop_front()
+            .ok_or("Received a reply without a request in flight")?;
+        let reply = frames.last().map(|reply| String::from_utf8_lossy(reply));
+        let reply = reply.as_deref().unwrap_or_default();
+        info!("Received reply {} {}", id, reply);
+        if reply.starts_with("error") {
+            return Err(format!("Request {} failed: {}", id, reply).into());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "zmq")]
+impl Transport for PipelinedZmqTransport {
+    fn request(&self, request_type: &str, path: &str, data: &[u8]) -> Result<(), Box<dyn Error>> {
+        while self.in_flight() >= self.window
+            || (self.fire_and_forget
+                && self.in_flight() > 0
+                && self.socket.poll(zmq::POLLIN, 0)? > 0)
+        {
+            self.receive_reply()?;
+        }
+        self.socket.send_multipart(
+            [
+                b"".as_slice(),
+                request_type.as_bytes(),
+                path.as_bytes(),
+                data,
+            ],
+            0,
+        )?;
+        let id = self.next_id.get();
+        self.next_id.set(id + 1);
+        info!("Sent request {} {} '{}'", id, request_type, path);
+        self.in_flight.borrow_mut().push_back(id);
+        Ok(())
+    }
+
+    fn url(&self) -> Result<String, Box<dyn Error>> {
+        self.query("url")
+    }
+
+    fn static_html(&self) -> Result<String, Box<dyn Error>> {
+        self.query("get_scene")
+    }
+
+    // Reads all the replies, the first failed request is reported
+    fn flush(&self) -> Result<(), Box<dyn Error>> {
+        let mut result = Ok(());
+        while self.in_flight() > 0 {
+            let reply = self.receive_reply();
+            if result.is_ok() {
+                result = reply;
+            }
+        }
+        result
+    }
+}
+
+// The default transport on wasm32 (where there's no zmq), sends the encoded data (which
+// contains the type and the path) to a WebSocket endpoint without waiting for replies. The
+// endpoint has to read the requests of its clients, e.g. a relay forwarding them to the ZMQ socket
+// of a meshcat-server: neither the viewer's WebSocket of meshcat-server nor MeshcatServer read
+// requests from their WebSocket clients. The requests sent while the socket is connecting are
+// sent once it's open
+#[cfg(target_arch = "wasm32")]
+pub struct WebSocketTransport {
+    url: String,
+    socket: web_sys::WebSocket,
+    pending: std::rc::Rc<std::cell::RefCell<Vec<Vec<u8>>>>,
+    // Sends the pending requests, kept alive as long as the socket may call it
+    _on_open: wasm_bindgen::closure::Closure<dyn FnMut()>,
+}
+
+#[cfg(target_arch = "wasm32")]
+impl WebSocketTransport {
+    pub fn new(url: &str) -> Result<Self, crate::Error> {
+        use wasm_bindgen::JsCast;
+        let socket = web_sys::WebSocket::new(url).map_err(|err| crate::Error::Connection {
+            endpoint: url.to_string(),
+            message: format!("{:?}", err),
+        })?;
+        socket.set_binary_type(web_sys::BinaryType::Arraybuffer);
+        let pending = std::rc::Rc::<std::cell::RefCell<Vec<Vec<u8>>>>::default();
+        let on_open = {
+            let socket = socket.clone();
+            let pending = pending.clone();
+            let url = url.to_string();
+            wasm_bindgen::closure::Closure::<dyn FnMut()>::new(move || {
+                for data in pending.take() {
+                    if let Err(err) = socket.send_with_u8_array(&data) {
+                        log::warn!("Failed to send to '{}': {:?}", url, err);
+                    }
+                }
+            })
+        };
+        socket.set_onopen(Some(on_open.as_ref().unchecked_ref()));
+        Ok(Self {
+            url: url.to_string(),
+            socket,
+            pending,
+            _on_open: on_open,
+        })
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+impl Drop for WebSocketTransport {
+    fn drop(&mut self) {
+        self.socket.set_onopen(None);
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+impl Transport for WebSocketTransport {
+    fn request(&self, _: &str, _: &str, data: &[u8]) -> Result<(), Box<dyn Error>> {
+        // Sending while connecting throws an InvalidStateError
+        if self.socket.ready_state() == web_sys::WebSocket::CONNECTING {
+            self.pending.borrow_mut().push(data.to_vec());
+            return Ok(());
+        }
+        self.socket
+            .send_with_u8_array(data)
+            .map_err(|err| format!("Failed to send to '{}': {:?}", self.url, err).into())
+    }
+
+    fn url(&self) -> Result<String, Box<dyn Error>> {
+        Ok(self.url.clone())
+    }
+}
+
+// The type, path and data of a request
+#[cfg(test)]
+pub(crate) type RecordedRequest = (String, String, Vec<u8>);
+
+// Keeps the requests instead of sending them, the transport of the tests
+#[cfg(test)]
+#[derive(Clone, Default)]
+pub(crate) struct Recorder(std::rc::Rc<std::cell::RefCell<Vec<RecordedRequest>>>);
+
+#[cfg(test)]
+impl Recorder {
+    // The requests sent since the last call
+    pub(crate) fn take(&self) -> Vec<RecordedRequest> {
+        self.0.take()
+    }
+}
+
+#[cfg(test)]
+impl Transport for Recorder {
+    fn request(&self, request_type: &str, path: &str, data: &[u8]) -> Result<(), Box<dyn Error>> {
+        self.0
+            .borrow_mut()
+            .push((request_type.to_string(), path.to_string(), data.to_vec()));
+        Ok(())
+    }
+
+    fn url(&self) -> Result<String, Box<dyn Error>> {
+        Ok("recorder".to_string())
+    }
+}
+
+#[cfg(all(test, feature = "zmq"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pipelined_zmq_transport() {
+        let server = zmq::Context::new().socket(zmq::REP).unwrap();
+        server.bind("tcp://127.0.0.1:*").unwrap();
+        let endpoint = server.get_last_endpoint().unwrap().unwrap();
+        let replies = std::thread::spawn(move || {
+            let mut paths = Vec::new();
+            for _ in 0..4 {
+                let frames = server.recv_multipart(0).unwrap();
+                let path = String::from_utf8(frames[1].clone()).unwrap();
+                let reply = if path == "/b" { "error: b" } else { "ok" };
+                server.send(reply, 0).unwrap();
+                paths.push(path);
+            }
+            paths
+        });
+
+        assert!(PipelinedZmqTransport::new(&endpoint, 0).is_err());
+        let transport = PipelinedZmqTransport::new(&endpoint, 2).unwrap();
+        transport.request("set_object", "/a", b"a").unwrap();
+        transport.request("set_object", "/b", b"b").unwrap();
+        assert_eq!(transport.in_flight(), 2);
+        // The window is full, the reply of /a is read before sending /c
+        transport.request("delete", "/c", b"").unwrap();
+        assert_eq!(transport.in_flight(), 2);
+        let err = transport.request("delete", "/d", b"").unwrap_err();
+        assert_eq!(err.to_string(), "Request 1 failed: error: b");
+        transport.request("delete", "/e", b"").unwrap();
+        transport.flush().unwrap();
+        assert_eq!(transport.in_flight(), 0);
+        assert_eq!(replies.join().unwrap(), ["/a", "/b", "/c", "/e"]);
+    }
+
+    #[test]
+    fn test_fire_and_forget() {
+        let server = zmq::Context::new().socket(zmq::REP).unwrap();
+        server.bind("tcp://127.0.0.1:*").unwrap();
+        let endpoint = server.get_last_endpoint().unwrap().unwrap();
+        let replies = std::thread::spawn(move || loop {
+            let frames = server.recv_multipart(0).unwrap();
+            server.send("ok", 0).unwrap();
+            if frames[0] == b"delete" {
+                break;
+            }
+        });
+        let transport = PipelinedZmqTransport::fire_and_forget(&endpoint).unwrap();
+        assert_eq!(transport.window, DEFAULT_SEND_HWM);
+        for _ in 0..100 {
+            transport.request("set_transform", "/a", b"").unwrap();
+        }
+        // The replies received so far are read by the next request, once one was received
+        while transport.in_flight() > 1 {
+            transport.socket.poll(zmq::POLLIN, -1).unwrap();
+            let in_flight = transport.in_flight();
+            transport.request("set_transform", "/a", b"").unwrap();
+            assert!(transport.in_flight() <= in_flight);
+        }
+        transport.request("delete", "/a", b"").unwrap();
+        transport.flush().unwrap();
+        assert_eq!(transport.in_flight(), 0);
+        replies.join().unwrap();
+    }
+
+    #[test]
+    fn test_timeouts() {
+        // Nothing listens on the endpoint
+        let unused = zmq::Context::new().socket(zmq::REP).unwrap();
+        unused.bind("tcp://127.0.0.1:*").unwrap();
+        let endpoint = unused.get_last_endpoint().unwrap().unwrap();
+        drop(unused);
+        let options = MeshcatOptions::builder()
+            .connect_timeout(Some(Duration::from_millis(100)))
+            .build();
+        assert!(matches!(
+            crate::types::Meshcat::try_new(&endpoint, &options),
+            Err(crate::Error::Connection { .. })
+        ));
+
+        // Drops the first request, the retry is replied
+        let server = zmq::Context::new().socket(zmq::ROUTER).unwrap();
+        server.bind("tcp://127.0.0.1:*").unwrap();
+        let endpoint = server.get_last_endpoint().unwrap().unwrap();
+        let requests = std::thread::spawn(move || {
+            let mut requests = Vec::new();
+            for _ in 0..2 {
+                let mut frames = server.recv_multipart(0).unwrap();
+                // The identity, the request id and the empty delimiter are sent back
+                let delimiter = frames.iter().position(|frame| frame.is_empty()).unwrap();
+                let request = frames.split_off(delimiter + 1);
+                requests.push(String::from_utf8(request[0].clone()).unwrap());
+                if requests.len() == 2 {
+                    frames.push(b"ok".to_vec());
+                    server.send_multipart(frames, 0).unwrap();
+                }
+            }
+            requests
+        });
+        let options = MeshcatOptions::builder()
+            .connect_timeout(None)
+            .receive_timeout(Duration::from_millis(100))
+            .retries(1)
+            .build();
+        let transport = ZmqTransport::with_options(&endpoint, &options).unwrap();
+        transport.request("delete", "/a", b"").unwrap();
+        assert_eq!(requests.join().unwrap(), ["delete", "delete"]);
+        let err = transport.request("delete", "/a", b"").unwrap_err();
+        assert!(err.to_string().starts_with("No reply from the server"));
+    }
+}
@@ -0,0 +1,142 @@
+use std::collections::BTreeMap;
+
+use nalgebra::Isometry3;
+use uuid::Uuid;
+
+// The nodes of the viewer's default scene, Meshcat::clear keeps them
+const VIEWER_PATHS: [&str; 5] = ["/Axes", "/Background", "/Cameras", "/Grid", "/Lights"];
+
+// What was published to a path
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Node {
+    // The uuid of the last object set at the path
+    pub object: Option<Uuid>,
+    pub transform: Option<Isometry3<f64>>,
+}
+
+// A client side mirror of what a Meshcat published (See Meshcat::tree), the paths without a
+// trailing /. Deleting a path deletes its descendants like in the viewer
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SceneTree {
+    nodes: BTreeMap<String, Node>,
+}
+
+// How a request changes the tree
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum TreeUpdate {
+    Object(Uuid),
+    Transform(Isometry3<f64>),
+    Delete,
+}
+
+fn normalize(path: &str) -> &str {
+    path.trim_end_matches('/')
+}
+
+// Whether path is ancestor or ancestor's descendant
+fn is_within(path: &str, ancestor: &str) -> bool {
+    ancestor.is_empty()
+        || path
+            .strip_prefix(ancestor)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+impl SceneTree {
+    pub(crate) fn apply(&mut self, path: &str, update: TreeUpdate) {
+        let path = normalize(path);
+        match update {
+            TreeUpdate::Object(uuid) => self.node_mut(path).object = Some(uuid),
+            TreeUpdate::Transform(transform) => self.node_mut(path).transform = Some(transform),
+            TreeUpdate::Delete => self.nodes.retain(|node, _| !is_within(node, path)),
+        }
+    }
+
+    // The key is only allocated for a new path, not when updating a path at a high rate
+    fn node_mut(&mut self, path: &str) -> &mut Node {
+        if !self.nodes.contains_key(path) {
+            self.nodes.insert(path.to_string(), Node::default());
+        }
+        self.nodes.get_mut(path).unwrap()
+    }
+
+    pub fn get(&self, path: &str) -> Option<&Node> {
+        self.nodes.get(normalize(path))
+    }
+
+    // Whether something was published to the path or to one of its descendants
+    pub fn contains(&self, path: &str) -> bool {
+        let path = normalize(path);
+        let prefix = format!("{}/", path);
+        self.nodes.contains_key(path)
+            || self
+                .nodes
+                .range::<String, _>(&prefix..)
+                .next()
+                .is_some_and(|(node, _)| node.starts_with(&prefix))
+    }
+
+    pub fn paths(&self) -> impl Iterator<Item = &str> {
+        self.nodes.keys().map(String::as_str)
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    // The paths not below another path of the tree nor of the viewer's default scene, deleting
+    // them deletes everything else that was published
+    pub(crate) fn roots(&self) -> Vec<String> {
+        let mut roots: Vec<String> = Vec::new();
+        for path in self.paths() {
+            let is_viewer_path = VIEWER_PATHS
+                .iter()
+                .any(|viewer_path| is_within(path, viewer_path));
+            // The paths are sorted so an ancestor comes before its descendants
+            let is_descendant = roots.iter().any(|root| is_within(path, root));
+            if !is_viewer_path && !is_descendant {
+                roots.push(path.to_string());
+            }
+        }
+        roots
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scene_tree() {
+        let mut tree = SceneTree::default();
+        let uuid = Uuid::new_v4();
+        tree.apply("/robot/base/", TreeUpdate::Object(uuid));
+        tree.apply(
+            "/robot/base",
+            TreeUpdate::Transform(Isometry3::translation(1.0, 0.0, 0.0)),
+        );
+        tree.apply("/robot/arm", TreeUpdate::Object(Uuid::new_v4()));
+        tree.apply("/robots", TreeUpdate::Object(Uuid::new_v4()));
+        tree.apply("/robot-2", TreeUpdate::Object(Uuid::new_v4()));
+        tree.apply(
+            "/Cameras/default",
+            TreeUpdate::Transform(Isometry3::identity()),
+        );
+        assert_eq!(tree.get("/robot/base").unwrap().object, Some(uuid));
+        assert!(tree.contains("/robot"));
+        assert!(!tree.contains("/rob"));
+        assert!(!tree.contains("/robot/base/link"));
+        assert_eq!(
+            tree.roots(),
+            ["/robot-2", "/robot/arm", "/robot/base", "/robots"]
+        );
+        tree.apply("/robot", TreeUpdate::Delete);
+        assert_eq!(
+            tree.paths().collect::<Vec<_>>(),
+            ["/Cameras/default", "/robot-2", "/robots"]
+        );
+    }
+}
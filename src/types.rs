@@ -1,13 +1,49 @@
+use std::borrow::Borrow;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
 use std::error::Error;
+use std::sync::{Arc, Weak};
 
 use base64::{engine::general_purpose, Engine as _};
-use log::info;
-use nalgebra::{Isometry3, Matrix3xX, Matrix4, Translation3, UnitQuaternion};
+use nalgebra::{Isometry3, Matrix3xX, Matrix4, Point3, Translation3, UnitQuaternion, Vector3};
 use serde::ser::{SerializeSeq, SerializeStruct, Serializer};
 use serde::{Deserialize, Serialize};
+use serde_repr::{Deserialize_repr, Serialize_repr};
 use typed_builder::TypedBuilder;
 use uuid::Uuid;
 
+use crate::tree::TreeUpdate;
+
+pub mod animation;
+
+thread_local! {
+    static UUID_SEED: std::cell::Cell<Option<u64>> = const { std::cell::Cell::new(None) };
+}
+
+// Uuids generated on this thread are derived from the seed instead of being random (None goes
+// back to random uuids), so the same objects serialize to the same bytes across runs
+pub fn set_uuid_seed(seed: Option<u64>) {
+    UUID_SEED.with(|state| state.set(seed));
+}
+
+pub(crate) fn new_uuid() -> Uuid {
+    UUID_SEED.with(|state| match state.get() {
+        None => Uuid::new_v4(),
+        Some(seed) => {
+            // splitmix64
+            let next = || {
+                let mut z = state.get().unwrap_or(seed).wrapping_add(0x9e3779b97f4a7c15);
+                state.set(Some(z));
+                z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+                z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+                z ^ (z >> 31)
+            };
+            let bytes = ((next() as u128) << 64 | next() as u128).to_le_bytes();
+            uuid::Builder::from_random_bytes(bytes).into_uuid()
+        }
+    })
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Metadata {
     #[serde(rename = "type")]
@@ -27,26 +63,272 @@ impl Default for Metadata {
 #[derive(Clone, Debug)]
 pub struct BufferGeometryAttribute {
     pub item_size: usize,
-    pub attribute_type: String,
-    // TODO: ext type?
-    pub array: Matrix3xX<f64>,
+    pub array: AttributeArray,
     pub normalized: bool,
 }
+
 impl Serialize for BufferGeometryAttribute {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: Serializer,
     {
+        // JSON has no typed arrays, the values are written as numbers
+        let is_human_readable = serializer.is_human_readable();
         let mut state = serializer.serialize_struct("BufferGeometryAttribute", 4)?;
         state.serialize_field("itemSize", &self.item_size)?;
-        state.serialize_field("type", &self.attribute_type)?;
-        // Using nalgebra's serialization will save it as [.., number of rows, number of columns]
-        // which is not what we want
-        state.serialize_field("array", &self.array.as_slice())?;
+        state.serialize_field("type", self.attribute_type().name())?;
+        if is_human_readable {
+            self.array.serialize_field(&mut state)?;
+        } else {
+            #[cfg(feature = "rayon")]
+            let placeholder =
+                crate::parallel::defer_array(&self.array).map_err(serde::ser::Error::custom)?;
+            #[cfg(not(feature = "rayon"))]
+            let placeholder: Option<String> = None;
+            match placeholder {
+                Some(placeholder) => state.serialize_field("array", &placeholder)?,
+                None => state.serialize_field("array", &TypedArrayExt(&self.array))?,
+            }
+        }
         state.serialize_field("normalized", &self.normalized)?;
         state.end()
     }
 }
+
+// The JavaScript typed arrays the attributes are stored in by the viewer
+// https://developer.mozilla.org/en-US/docs/Web/JavaScript/Reference/Global_Objects/TypedArray
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ArrayType {
+    Float32,
+    // WebGL has no 64 bits floats, three.js doesn't render the attributes stored as Float64Array
+    // (they can only be read back, e.g. by a custom viewer)
+    Float64,
+    Uint8,
+    Uint16,
+    Uint32,
+    Int8,
+    Int16,
+    Int32,
+}
+
+impl ArrayType {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ArrayType::Float32 => "Float32Array",
+            ArrayType::Float64 => "Float64Array",
+            ArrayType::Uint8 => "Uint8Array",
+            ArrayType::Uint16 => "Uint16Array",
+            ArrayType::Uint32 => "Uint32Array",
+            ArrayType::Int8 => "Int8Array",
+            ArrayType::Int16 => "Int16Array",
+            ArrayType::Int32 => "Int32Array",
+        }
+    }
+
+    // The values of the integer types
+    fn integer_range(&self) -> Option<(f64, f64)> {
+        match self {
+            ArrayType::Float32 | ArrayType::Float64 => None,
+            ArrayType::Uint8 => Some((0.0, u8::MAX as f64)),
+            ArrayType::Uint16 => Some((0.0, u16::MAX as f64)),
+            ArrayType::Uint32 => Some((0.0, u32::MAX as f64)),
+            ArrayType::Int8 => Some((-(i8::MAX as f64), i8::MAX as f64)),
+            ArrayType::Int16 => Some((-(i16::MAX as f64), i16::MAX as f64)),
+            ArrayType::Int32 => Some((-(i32::MAX as f64), i32::MAX as f64)),
+        }
+    }
+
+    // The msgpack extension type of the typed array, the codes of msgpack-lite decoded by the
+    // viewer (and used by meshcat-python for the numpy arrays)
+    pub(crate) fn ext_type(&self) -> i8 {
+        match self {
+            ArrayType::Int8 => 0x11,
+            ArrayType::Uint8 => 0x12,
+            ArrayType::Int16 => 0x13,
+            ArrayType::Uint16 => 0x14,
+            ArrayType::Int32 => 0x15,
+            ArrayType::Uint32 => 0x16,
+            ArrayType::Float32 => 0x17,
+            ArrayType::Float64 => 0x18,
+        }
+    }
+}
+
+// The values of an attribute stored as the typed array they're sent as, e.g. 1 byte per value
+// for Uint8 colors
+#[derive(Clone, Debug, PartialEq)]
+pub enum AttributeArray {
+    Float32(Vec<f32>),
+    Float64(Vec<f64>),
+    Uint8(Vec<u8>),
+    Uint16(Vec<u16>),
+    Uint32(Vec<u32>),
+    Int8(Vec<i8>),
+    Int16(Vec<i16>),
+    Int32(Vec<i32>),
+}
+
+// Applies the expression to the values of each variant
+macro_rules! map_values {
+    ($array:expr, $values:ident => $expr:expr) => {
+        match $array {
+            AttributeArray::Float32($values) => $expr,
+            AttributeArray::Float64($values) => $expr,
+            AttributeArray::Uint8($values) => $expr,
+            AttributeArray::Uint16($values) => $expr,
+            AttributeArray::Uint32($values) => $expr,
+            AttributeArray::Int8($values) => $expr,
+            AttributeArray::Int16($values) => $expr,
+            AttributeArray::Int32($values) => $expr,
+        }
+    };
+}
+
+impl AttributeArray {
+    // The values converted to the type, rounded and clamped for the integer types
+    pub fn from_values(values: impl Iterator<Item = f64>, array_type: ArrayType) -> Self {
+        let integer = |value: f64| {
+            let (min, max) = array_type.integer_range().unwrap_or_default();
+            value.round().clamp(min, max)
+        };
+        match array_type {
+            ArrayType::Float32 => AttributeArray::Float32(values.map(|v| v as f32).collect()),
+            ArrayType::Float64 => AttributeArray::Float64(values.collect()),
+            ArrayType::Uint8 => AttributeArray::Uint8(values.map(|v| integer(v) as u8).collect()),
+            ArrayType::Uint16 => {
+                AttributeArray::Uint16(values.map(|v| integer(v) as u16).collect())
+            }
+            ArrayType::Uint32 => {
+                AttributeArray::Uint32(values.map(|v| integer(v) as u32).collect())
+            }
+            ArrayType::Int8 => AttributeArray::Int8(values.map(|v| integer(v) as i8).collect()),
+            ArrayType::Int16 => AttributeArray::Int16(values.map(|v| integer(v) as i16).collect()),
+            ArrayType::Int32 => AttributeArray::Int32(values.map(|v| integer(v) as i32).collect()),
+        }
+    }
+
+    pub fn array_type(&self) -> ArrayType {
+        match self {
+            AttributeArray::Float32(_) => ArrayType::Float32,
+            AttributeArray::Float64(_) => ArrayType::Float64,
+            AttributeArray::Uint8(_) => ArrayType::Uint8,
+            AttributeArray::Uint16(_) => ArrayType::Uint16,
+            AttributeArray::Uint32(_) => ArrayType::Uint32,
+            AttributeArray::Int8(_) => ArrayType::Int8,
+            AttributeArray::Int16(_) => ArrayType::Int16,
+            AttributeArray::Int32(_) => ArrayType::Int32,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        map_values!(self, values => values.len())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn values(&self) -> Box<dyn Iterator<Item = f64> + '_> {
+        fn values<T: Copy + Into<f64>>(values: &[T]) -> Box<dyn Iterator<Item = f64> + '_> {
+            Box::new(values.iter().map(|value| (*value).into()))
+        }
+        map_values!(self, array => values(array))
+    }
+
+    // Appends the little endian bytes of the values in the range
+    pub(crate) fn extend_le_bytes(&self, range: std::ops::Range<usize>, buf: &mut Vec<u8>) {
+        map_values!(self, values => {
+            for value in &values[range] {
+                buf.extend_from_slice(&value.to_le_bytes());
+            }
+        })
+    }
+
+    pub(crate) fn value_size(&self) -> usize {
+        map_values!(self, values => std::mem::size_of_val(&values[..]) / values.len().max(1))
+    }
+
+    fn serialize_field<S: SerializeStruct>(&self, state: &mut S) -> Result<(), S::Error> {
+        map_values!(self, values => state.serialize_field("array", values))
+    }
+}
+
+// An attribute array as a msgpack extension of its little endian bytes
+struct TypedArrayExt<'a>(&'a AttributeArray);
+
+impl Serialize for TypedArrayExt<'_> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        struct Bytes(Vec<u8>);
+        impl Serialize for Bytes {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.serialize_bytes(&self.0)
+            }
+        }
+        let mut bytes = Vec::with_capacity(self.0.len() * self.0.value_size());
+        self.0.extend_le_bytes(0..self.0.len(), &mut bytes);
+        serializer.serialize_newtype_struct(
+            rmp_serde::MSGPACK_EXT_STRUCT_NAME,
+            &(self.0.array_type().ext_type(), Bytes(bytes)),
+        )
+    }
+}
+
+impl BufferGeometryAttribute {
+    // Stored as 32 bits floats
+    pub fn new(array: Matrix3xX<f64>) -> Self {
+        Self::with_type(array, ArrayType::Float32)
+    }
+
+    // Stored as the given type without normalization, e.g. ArrayType::Int16 for integer
+    // coordinates (See ArrayType::Float64)
+    pub fn with_type(array: Matrix3xX<f64>, array_type: ArrayType) -> Self {
+        BufferGeometryAttribute {
+            item_size: 3,
+            array: AttributeArray::from_values(array.iter().copied(), array_type),
+            normalized: false,
+        }
+    }
+
+    // Values in [0, 1] (for the unsigned types) or [-1, 1] (for the signed ones) sent as integers
+    // of the type and normalized back by the viewer, e.g. ArrayType::Uint8 for colors and
+    // ArrayType::Int16 for normals
+    pub fn quantized(array: Matrix3xX<f64>, array_type: ArrayType) -> Result<Self, Box<dyn Error>> {
+        // WebGL only normalizes 8 and 16 bits integers
+        if !matches!(
+            array_type,
+            ArrayType::Uint8 | ArrayType::Uint16 | ArrayType::Int8 | ArrayType::Int16
+        ) {
+            return Err(format!("Can't quantize to {}", array_type.name()).into());
+        }
+        let (_, max) = array_type.integer_range().unwrap_or_default();
+        Ok(BufferGeometryAttribute {
+            item_size: 3,
+            array: AttributeArray::from_values(array.iter().map(|value| value * max), array_type),
+            normalized: true,
+        })
+    }
+
+    pub fn attribute_type(&self) -> ArrayType {
+        self.array.array_type()
+    }
+
+    // The values of an attribute of 3 values per item (e.g. the positions, normals or colors)
+    // as seen by the viewer, the normalized integers are scaled back
+    pub fn to_matrix(&self) -> Matrix3xX<f64> {
+        let scale = match self.attribute_type().integer_range() {
+            Some((_, max)) if self.normalized => 1.0 / max,
+            _ => 1.0,
+        };
+        Matrix3xX::from_iterator(
+            self.array.len() / 3,
+            self.array.values().map(|value| value * scale),
+        )
+    }
+}
+
 #[derive(Clone, Debug, Serialize)]
 pub struct BufferGeometryAttributes {
     pub position: BufferGeometryAttribute,
@@ -60,6 +342,370 @@ pub struct BufferGeometryAttributes {
 #[derive(Clone, Debug, Serialize)]
 pub struct BufferGeometryData {
     pub attributes: BufferGeometryAttributes,
+    // The vertices of the triangles (or segments, or points) by their index in the attributes,
+    // without it each group of attributes is a vertex
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub index: Option<BufferGeometryIndex>,
+}
+
+impl BufferGeometryData {
+    // The vertices of the index have to be in the attributes
+    pub fn indexed(
+        attributes: BufferGeometryAttributes,
+        index: Vec<u32>,
+    ) -> Result<Self, Box<dyn Error>> {
+        check_index(&index, attributes.position.to_matrix().ncols())?;
+        Ok(BufferGeometryData {
+            attributes,
+            index: Some(BufferGeometryIndex { array: index }),
+        })
+    }
+
+    // A triangle mesh with the vertices shared by its triangles (counterclockwise when seen from
+    // the front) and smooth normals (the area weighted average of the normals of the triangles
+    // of each vertex), it's shown with the color of its material
+    pub fn triangles(
+        vertices: Matrix3xX<f64>,
+        triangles: &[[u32; 3]],
+    ) -> Result<Self, Box<dyn Error>> {
+        let index = triangles.iter().flatten().copied().collect::<Vec<_>>();
+        check_index(&index, vertices.ncols())?;
+        let mut normals = Matrix3xX::zeros(vertices.ncols());
+        for triangle in triangles {
+            let [a, b, c] = triangle.map(|vertex| vertices.column(vertex as usize).into_owned());
+            // Its length is twice the area of the triangle
+            let normal = (b - a).cross(&(c - a));
+            for vertex in triangle {
+                let mut column = normals.column_mut(*vertex as usize);
+                column += normal;
+            }
+        }
+        for mut normal in normals.column_iter_mut() {
+            normal.try_normalize_mut(f64::EPSILON);
+        }
+        let attributes = BufferGeometryAttributes {
+            color: BufferGeometryAttribute::new(Matrix3xX::from_element(vertices.ncols(), 1.0)),
+            position: BufferGeometryAttribute::new(vertices),
+            normal: Some(BufferGeometryAttribute::new(normals)),
+            uv: None,
+        };
+        Self::indexed(attributes, index)
+    }
+}
+
+// https://threejs.org/docs/index.html#api/en/core/BufferGeometry.index
+#[derive(Clone, Debug, PartialEq)]
+pub struct BufferGeometryIndex {
+    pub array: Vec<u32>,
+}
+
+impl Serialize for BufferGeometryIndex {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut state = serializer.serialize_struct("BufferGeometryIndex", 2)?;
+        state.serialize_field("type", "Uint32Array")?;
+        state.serialize_field("array", &self.array)?;
+        state.end()
+    }
+}
+
+// A triangle mesh built in Rust (e.g. by marching cubes) like MeshCat.jl's
+// TriangularMeshGeometry, e.g.
+// TriangularMesh::builder().vertices(vertices).faces(faces).build().into_object(0xff0000)
+#[derive(Clone, Debug, TypedBuilder)]
+pub struct TriangularMesh {
+    pub vertices: Matrix3xX<f64>,
+    // The indices of the vertices of each triangle, counterclockwise when seen from the front
+    pub faces: Matrix3xX<u32>,
+    // The colors of the vertices (in [0, 1]), multiplied by the color of the material
+    #[builder(default, setter(strip_option))]
+    pub colors: Option<Matrix3xX<f64>>,
+    // Smooth normals are computed when they're not given (See BufferGeometryData::triangles)
+    #[builder(default, setter(strip_option))]
+    pub normals: Option<Matrix3xX<f64>>,
+}
+
+impl TriangularMesh {
+    pub fn into_object(self, color: u32) -> Result<LumpedObject, Box<dyn Error>> {
+        let vertex_colors = self.colors.is_some();
+        Ok(LumpedObject::builder()
+            .geometries(vec![Geometry::new(GeometryType::try_from(self)?)])
+            .material(
+                Material::builder()
+                    .material_type(MaterialType::MeshLambert)
+                    .color(color)
+                    .vertex_colors(vertex_colors)
+                    .build(),
+            )
+            .build())
+    }
+}
+
+impl TryFrom<TriangularMesh> for GeometryType {
+    type Error = Box<dyn Error>;
+
+    fn try_from(mesh: TriangularMesh) -> Result<Self, Self::Error> {
+        for (name, attribute) in [("colors", &mesh.colors), ("normals", &mesh.normals)] {
+            if let Some(attribute) = attribute.as_ref() {
+                if attribute.ncols() != mesh.vertices.ncols() {
+                    return Err(format!(
+                        "Got {} vertices but {} {}",
+                        mesh.vertices.ncols(),
+                        attribute.ncols(),
+                        name
+                    )
+                    .into());
+                }
+            }
+        }
+        let faces = mesh
+            .faces
+            .column_iter()
+            .map(|face| [face[0], face[1], face[2]])
+            .collect::<Vec<_>>();
+        let mut data = BufferGeometryData::triangles(mesh.vertices, &faces)?;
+        if let Some(colors) = mesh.colors {
+            data.attributes.color = BufferGeometryAttribute::new(colors);
+        }
+        if let Some(normals) = mesh.normals {
+            data.attributes.normal = Some(BufferGeometryAttribute::new(normals));
+        }
+        Ok(GeometryType::Buffer {
+            data: Box::new(data),
+        })
+    }
+}
+
+fn check_index(index: &[u32], vertices: usize) -> Result<(), Box<dyn Error>> {
+    match index.iter().find(|vertex| **vertex as usize >= vertices) {
+        Some(vertex) => {
+            Err(format!("Vertex {} is out of bounds ({} vertices)", vertex, vertices).into())
+        }
+        None => Ok(()),
+    }
+}
+
+// The attributes of a buffer geometry stored in one array with a stride, vertex after vertex
+// (position, then color and normal if any), which the GPU uploads at once
+// https://threejs.org/docs/#api/en/core/InterleavedBufferAttribute
+#[derive(Clone, Debug)]
+pub struct InterleavedBufferData {
+    buffer_uuid: Uuid,
+    array_buffer_uuid: Uuid,
+    // The names of the attributes and their offsets in a vertex
+    attributes: Vec<(&'static str, usize)>,
+    stride: usize,
+    array: Vec<f32>,
+}
+
+impl InterleavedBufferData {
+    pub fn new(
+        positions: &Matrix3xX<f64>,
+        colors: Option<&Matrix3xX<f64>>,
+        normals: Option<&Matrix3xX<f64>>,
+    ) -> Result<Self, Box<dyn Error>> {
+        let mut attributes = vec![("position", positions)];
+        attributes.extend(colors.map(|colors| ("color", colors)));
+        attributes.extend(normals.map(|normals| ("normal", normals)));
+        if let Some((name, array)) = attributes
+            .iter()
+            .find(|(_, array)| array.ncols() != positions.ncols())
+        {
+            return Err(format!(
+                "Got {} positions but {} {}s",
+                positions.ncols(),
+                array.ncols(),
+                name
+            )
+            .into());
+        }
+        let stride = 3 * attributes.len();
+        let array = (0..positions.ncols())
+            .flat_map(|vertex| {
+                attributes
+                    .iter()
+                    .flat_map(move |(_, array)| array.column(vertex).into_iter().copied())
+            })
+            .map(|value| value as f32)
+            .collect();
+        Ok(InterleavedBufferData {
+            buffer_uuid: new_uuid(),
+            array_buffer_uuid: new_uuid(),
+            attributes: attributes
+                .iter()
+                .enumerate()
+                .map(|(index, (name, _))| (*name, 3 * index))
+                .collect(),
+            stride,
+            array,
+        })
+    }
+
+    pub fn positions(&self) -> impl Iterator<Item = Point3<f64>> + '_ {
+        self.array
+            .chunks(self.stride)
+            .map(|vertex| Point3::new(vertex[0] as f64, vertex[1] as f64, vertex[2] as f64))
+    }
+}
+
+#[derive(Serialize)]
+struct InterleavedAttribute {
+    #[serde(rename = "isInterleavedBufferAttribute")]
+    is_interleaved_buffer_attribute: bool,
+    #[serde(rename = "itemSize")]
+    item_size: usize,
+    data: Uuid,
+    offset: usize,
+    normalized: bool,
+}
+
+#[derive(Serialize)]
+struct InterleavedBuffer {
+    uuid: Uuid,
+    buffer: Uuid,
+    #[serde(rename = "type")]
+    buffer_type: &'static str,
+    stride: usize,
+}
+
+// The format of BufferGeometry.toJSON, the array buffer is a list of 32 bits words. The uuids
+// are serialized like the other uuids so the viewer can match them
+impl Serialize for InterleavedBufferData {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let attributes = self
+            .attributes
+            .iter()
+            .map(|(name, offset)| {
+                let attribute = InterleavedAttribute {
+                    is_interleaved_buffer_attribute: true,
+                    item_size: 3,
+                    data: self.buffer_uuid,
+                    offset: *offset,
+                    normalized: false,
+                };
+                (*name, attribute)
+            })
+            .collect::<BTreeMap<_, _>>();
+        let buffer = InterleavedBuffer {
+            uuid: self.buffer_uuid,
+            buffer: self.array_buffer_uuid,
+            buffer_type: "Float32Array",
+            stride: self.stride,
+        };
+        let words = self
+            .array
+            .iter()
+            .map(|value| value.to_bits())
+            .collect::<Vec<_>>();
+        let mut state = serializer.serialize_struct("InterleavedBufferData", 3)?;
+        state.serialize_field("attributes", &attributes)?;
+        state.serialize_field(
+            "interleavedBuffers",
+            &BTreeMap::from([(self.buffer_uuid, buffer)]),
+        )?;
+        state.serialize_field(
+            "arrayBuffers",
+            &BTreeMap::from([(self.array_buffer_uuid, words)]),
+        )?;
+        state.end()
+    }
+}
+
+// The content of a mesh file, meshcat's loaders take the text formats (obj, dae) as strings and
+// stl files (ASCII or binary) as bytes
+#[derive(PartialEq, Clone, Debug)]
+pub enum MeshData {
+    Text(String),
+    Binary(Vec<u8>),
+}
+
+impl From<String> for MeshData {
+    fn from(text: String) -> Self {
+        MeshData::Text(text)
+    }
+}
+
+impl From<Vec<u8>> for MeshData {
+    fn from(bytes: Vec<u8>) -> Self {
+        MeshData::Binary(bytes)
+    }
+}
+
+impl Serialize for MeshData {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self {
+            MeshData::Text(text) => serializer.serialize_str(text),
+            MeshData::Binary(bytes) => serializer.serialize_bytes(bytes),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for MeshData {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        struct MeshDataVisitor;
+
+        impl<'de> serde::de::Visitor<'de> for MeshDataVisitor {
+            type Value = MeshData;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                formatter.write_str("a string or bytes")
+            }
+
+            fn visit_str<E: serde::de::Error>(self, text: &str) -> Result<MeshData, E> {
+                Ok(MeshData::Text(text.to_string()))
+            }
+
+            fn visit_bytes<E: serde::de::Error>(self, bytes: &[u8]) -> Result<MeshData, E> {
+                Ok(MeshData::Binary(bytes.to_vec()))
+            }
+
+            // JSON has no bytes, they're a list of numbers
+            fn visit_seq<A: serde::de::SeqAccess<'de>>(
+                self,
+                mut seq: A,
+            ) -> Result<MeshData, A::Error> {
+                let mut bytes = Vec::new();
+                while let Some(byte) = seq.next_element()? {
+                    bytes.push(byte);
+                }
+                Ok(MeshData::Binary(bytes))
+            }
+        }
+
+        deserializer.deserialize_any(MeshDataVisitor)
+    }
+}
+
+impl MeshData {
+    // The 80 bytes header is followed by the number of triangles and 50 bytes per triangle, a
+    // binary file can also start with "solid" like the ASCII ones
+    pub fn is_binary_stl(&self) -> bool {
+        let MeshData::Binary(bytes) = self else {
+            return false;
+        };
+        bytes.get(80..84).is_some_and(|count| {
+            let count = u32::from_le_bytes([count[0], count[1], count[2], count[3]]);
+            84 + 50 * count as usize == bytes.len()
+        })
+    }
+
+    fn text(&self) -> std::borrow::Cow<'_, str> {
+        match self {
+            MeshData::Text(text) => text.into(),
+            MeshData::Binary(bytes) => String::from_utf8_lossy(bytes),
+        }
+    }
 }
 
 // https://threejs.org/docs/#api/en/geometries/
@@ -69,8 +715,10 @@ pub enum GeometryType {
     // https://threejs.org/docs/#api/en/core/BufferGeometry
     #[serde(rename = "BufferGeometry")]
     Buffer { data: Box<BufferGeometryData> },
+    #[serde(rename = "BufferGeometry")]
+    InterleavedBuffer { data: Box<InterleavedBufferData> },
     #[serde(rename = "_meshfile_geometry")]
-    Mesh { format: String, data: String },
+    Mesh { format: String, data: MeshData },
     #[serde(rename = "BoxGeometry")]
     Box { width: f64, height: f64, depth: f64 },
     // TODO: Unsupported by meshcat
@@ -152,12 +800,21 @@ pub enum GeometryType {
         theta_length: f64,
     },
     #[serde(rename = "SphereGeometry")]
+    // The full sphere has phi_length 2π and theta_length π, theta starts at the top (+y)
     Sphere {
         radius: f64,
         #[serde(rename = "widthSegments")]
         width_segments: u32,
         #[serde(rename = "heightSegments")]
         height_segments: u32,
+        #[serde(rename = "phiStart")]
+        phi_start: f64,
+        #[serde(rename = "phiLength")]
+        phi_length: f64,
+        #[serde(rename = "thetaStart")]
+        theta_start: f64,
+        #[serde(rename = "thetaLength")]
+        theta_length: f64,
     },
     #[serde(rename = "TetrahedronGeometry")]
     Tetrahedron { radius: f64, detail: u32 },
@@ -169,9 +826,211 @@ pub enum GeometryType {
         radial_segments: u32,
         #[serde(rename = "tubularSegments")]
         tubular_segments: u32,
+        // The central angle, 2π for the full torus
+        arc: f64,
     },
 }
 
+impl GeometryType {
+    // The three.js cylinders, cones and partial spheres (whose sweeps are around y) have their
+    // axis along y, they're rotated to have it along z (like URDF and most robotics tools), the
+    // other primitives (and the full spheres) are kept as is
+    pub fn default_axis_correction(&self) -> Isometry3<f64> {
+        let y_to_z = Isometry3::from_parts(
+            Translation3::identity(),
+            UnitQuaternion::from_euler_angles(std::f64::consts::FRAC_PI_2, 0.0, 0.0),
+        );
+        match self {
+            GeometryType::Cylinder { .. } | GeometryType::Cone { .. } => y_to_z,
+            GeometryType::Sphere {
+                phi_length,
+                theta_start,
+                theta_length,
+                ..
+            } if *phi_length < 2.0 * std::f64::consts::PI
+                || *theta_start > 0.0
+                || *theta_length < std::f64::consts::PI =>
+            {
+                y_to_z
+            }
+            _ => Isometry3::identity(),
+        }
+    }
+}
+
+// Axis-aligned bounding box
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct BoundingBox {
+    pub min: Point3<f64>,
+    pub max: Point3<f64>,
+}
+
+impl BoundingBox {
+    pub fn new(min: Point3<f64>, max: Point3<f64>) -> Self {
+        BoundingBox { min, max }
+    }
+
+    // Returns None if there are no points
+    pub fn from_points<'a>(points: impl IntoIterator<Item = &'a Point3<f64>>) -> Option<Self> {
+        points.into_iter().fold(None, |bounding_box, point| {
+            Some(match bounding_box {
+                None => BoundingBox::new(*point, *point),
+                Some(bounding_box) => {
+                    BoundingBox::new(bounding_box.min.inf(point), bounding_box.max.sup(point))
+                }
+            })
+        })
+    }
+
+    pub fn center(&self) -> Point3<f64> {
+        nalgebra::center(&self.min, &self.max)
+    }
+
+    pub fn size(&self) -> Vector3<f64> {
+        self.max - self.min
+    }
+
+    pub fn corners(&self) -> [Point3<f64>; 8] {
+        let (min, max) = (self.min, self.max);
+        [
+            Point3::new(min.x, min.y, min.z),
+            Point3::new(max.x, min.y, min.z),
+            Point3::new(min.x, max.y, min.z),
+            Point3::new(max.x, max.y, min.z),
+            Point3::new(min.x, min.y, max.z),
+            Point3::new(max.x, min.y, max.z),
+            Point3::new(min.x, max.y, max.z),
+            Point3::new(max.x, max.y, max.z),
+        ]
+    }
+
+    pub fn union(&self, other: &BoundingBox) -> Self {
+        BoundingBox::new(self.min.inf(&other.min), self.max.sup(&other.max))
+    }
+
+    // The bounding box of the transformed corners
+    pub fn transform(&self, matrix: &Matrix4<f64>) -> Self {
+        let corners = self.corners().map(|corner| matrix.transform_point(&corner));
+        BoundingBox::from_points(&corners).unwrap()
+    }
+}
+
+impl GeometryType {
+    // The bounds in the geometry's frame (three.js' primitives are centered at the origin),
+    // polyhedrons use their circumscribed sphere, partial spheres and tori the bounds of the
+    // whole shape and meshes are only supported for obj and ASCII stl
+    pub fn bounding_box(&self) -> Option<BoundingBox> {
+        let symmetric = |x: f64, y: f64, z: f64| {
+            Some(BoundingBox::new(
+                Point3::new(-x, -y, -z),
+                Point3::new(x, y, z),
+            ))
+        };
+        match self {
+            GeometryType::Buffer { data } => BoundingBox::from_points(
+                &data
+                    .attributes
+                    .position
+                    .to_matrix()
+                    .column_iter()
+                    .map(|column| Point3::from(column.into_owned()))
+                    .collect::<Vec<_>>(),
+            ),
+            GeometryType::InterleavedBuffer { data } => {
+                BoundingBox::from_points(&data.positions().collect::<Vec<_>>())
+            }
+            GeometryType::Mesh { format, data } => {
+                BoundingBox::from_points(&mesh_vertices(format, data))
+            }
+            GeometryType::Box {
+                width,
+                height,
+                depth,
+            } => symmetric(width / 2.0, height / 2.0, depth / 2.0),
+            GeometryType::Circle { radius, .. } => symmetric(*radius, *radius, 0.0),
+            GeometryType::Cone { radius, height, .. } => symmetric(*radius, height / 2.0, *radius),
+            GeometryType::Cylinder {
+                radius_top,
+                radius_bottom,
+                height,
+                ..
+            } => {
+                let radius = radius_top.max(*radius_bottom);
+                symmetric(radius, height / 2.0, radius)
+            }
+            GeometryType::Dodecahedron { radius, .. }
+            | GeometryType::Icosahedron { radius, .. }
+            | GeometryType::Octahedron { radius, .. }
+            | GeometryType::Tetrahedron { radius, .. }
+            | GeometryType::Sphere { radius, .. } => symmetric(*radius, *radius, *radius),
+            GeometryType::Plane { width, height, .. } => symmetric(width / 2.0, height / 2.0, 0.0),
+            GeometryType::Ring { outer_radius, .. } => symmetric(*outer_radius, *outer_radius, 0.0),
+            GeometryType::Torus { radius, tube, .. } => {
+                symmetric(radius + tube, radius + tube, *tube)
+            }
+        }
+    }
+}
+
+// The vertices of an obj ("v x y z") or an ASCII stl ("vertex x y z") file
+fn mesh_vertices(format: &str, data: &MeshData) -> Vec<Point3<f64>> {
+    if let (MeshData::Binary(bytes), true) = (data, data.is_binary_stl()) {
+        // The normal then the 3 vertices of each triangle as little endian f32
+        return bytes[84..]
+            .chunks_exact(50)
+            .flat_map(|triangle| triangle[12..48].chunks_exact(12))
+            .map(|vertex| {
+                let coordinate = |i: usize| {
+                    let bytes = &vertex[4 * i..4 * i + 4];
+                    f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64
+                };
+                Point3::new(coordinate(0), coordinate(1), coordinate(2))
+            })
+            .collect();
+    }
+    let keyword = match format {
+        "obj" => "v",
+        "stl" => "vertex",
+        _ => return Vec::new(),
+    };
+    data.text()
+        .lines()
+        .filter_map(|line| {
+            let mut tokens = line.split_whitespace();
+            if tokens.next() != Some(keyword) {
+                return None;
+            }
+            let coordinates = tokens
+                .take(3)
+                .map(str::parse::<f64>)
+                .collect::<Result<Vec<_>, _>>()
+                .ok()?;
+            (coordinates.len() == 3)
+                .then(|| Point3::new(coordinates[0], coordinates[1], coordinates[2]))
+        })
+        .collect()
+}
+
+// A ShaderMaterial uniform, the type tells three.js which value to construct
+// https://threejs.org/docs/index.html#api/en/core/Uniform
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", content = "value")]
+pub enum Uniform {
+    #[serde(rename = "f")]
+    Float(f64),
+    #[serde(rename = "c")]
+    Color(u32),
+    #[serde(rename = "v2")]
+    Vector2([f64; 2]),
+    #[serde(rename = "v3")]
+    Vector3([f64; 3]),
+    #[serde(rename = "v4")]
+    Vector4([f64; 4]),
+    // Column major
+    #[serde(rename = "m4")]
+    Matrix4([f64; 16]),
+}
+
 // properties??
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(tag = "type")]
@@ -184,16 +1043,87 @@ pub enum MaterialType {
     MeshLambert,
     #[serde(rename = "MeshToonMaterial")]
     MeshToon,
+    // Physically based, roughness and metalness are in [0, 1] and the emissive color (0xRRGGBB)
+    // is added to the lit color (See MaterialType::standard)
+    #[serde(rename = "MeshStandardMaterial")]
+    MeshStandard {
+        roughness: f64,
+        metalness: f64,
+        emissive: u32,
+        #[serde(rename = "emissiveIntensity")]
+        emissive_intensity: f64,
+    },
+    // MeshStandard with a clear coat layer (e.g. car paint), clearcoat and its roughness are in
+    // [0, 1]
+    #[serde(rename = "MeshPhysicalMaterial")]
+    MeshPhysical {
+        roughness: f64,
+        metalness: f64,
+        emissive: u32,
+        #[serde(rename = "emissiveIntensity")]
+        emissive_intensity: f64,
+        clearcoat: f64,
+        #[serde(rename = "clearcoatRoughness")]
+        clearcoat_roughness: f64,
+    },
+    // Colors faces by their normal, for debugging flipped normals
+    #[serde(rename = "MeshNormalMaterial")]
+    MeshNormal,
+    // Colors faces by their distance to the camera, for debugging depth issues
+    #[serde(rename = "MeshDepthMaterial")]
+    MeshDepth,
+    // For ObjectType::Sprite, with size_attenuation the sprite gets smaller with the distance
+    #[serde(rename = "SpriteMaterial")]
+    Sprite {
+        #[serde(rename = "sizeAttenuation")]
+        size_attenuation: bool,
+    },
+    // GLSL shaders, three.js adds the usual attributes and matrices (position,
+    // projectionMatrix, ...) so the shaders only declare the uniforms
+    #[serde(rename = "ShaderMaterial")]
+    Shader {
+        #[serde(rename = "vertexShader")]
+        vertex_shader: String,
+        #[serde(rename = "fragmentShader")]
+        fragment_shader: String,
+        uniforms: BTreeMap<String, Uniform>,
+    },
     #[serde(rename = "LineBasicMaterial")]
     LineBasic,
     #[serde(rename = "PointsMaterial")]
     Points { size: f64 },
+    // Transparent except for the shadows it receives
+    #[serde(rename = "ShadowMaterial")]
+    Shadow,
+}
+
+impl MaterialType {
+    // Without emission
+    pub fn standard(roughness: f64, metalness: f64) -> Self {
+        MaterialType::MeshStandard {
+            roughness,
+            metalness,
+            emissive: 0,
+            emissive_intensity: 1.0,
+        }
+    }
+
+    pub fn physical(roughness: f64, metalness: f64, clearcoat: f64) -> Self {
+        MaterialType::MeshPhysical {
+            roughness,
+            metalness,
+            emissive: 0,
+            emissive_intensity: 1.0,
+            clearcoat,
+            clearcoat_roughness: 0.0,
+        }
+    }
 }
 
 // https://threejs.org/docs/index.html#api/en/materials/Material
 #[derive(Clone, Debug, TypedBuilder, Serialize, Deserialize)]
 pub struct Material {
-    #[builder(default = Uuid::new_v4(), setter(skip))]
+    #[builder(default = new_uuid(), setter(skip))]
     pub uuid: Uuid,
     #[builder(default = MaterialType::MeshPhong)]
     #[serde(flatten)]
@@ -230,6 +1160,11 @@ pub struct Material {
     #[builder(default, setter(skip))]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub map: Option<Uuid>,
+    // Any other three.js material property, e.g. {"depthTest": false, "fog": false}, merged in
+    // the material, a key also set by a typed field is sent twice
+    #[builder(default, setter(strip_option))]
+    #[serde(flatten, skip_serializing_if = "Option::is_none")]
+    pub extra: Option<serde_json::Map<String, serde_json::Value>>,
 }
 
 impl Default for Material {
@@ -276,56 +1211,248 @@ impl TextureType {
     }
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+// https://threejs.org/docs/#api/en/constants/Textures
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Serialize_repr, Deserialize_repr)]
+#[repr(u16)]
+pub enum TextureFilter {
+    Nearest = 1003,
+    NearestMipmapNearest = 1004,
+    NearestMipmapLinear = 1005,
+    Linear = 1006,
+    LinearMipmapNearest = 1007,
+    LinearMipmapLinear = 1008,
+}
+
+// https://threejs.org/docs/#api/en/textures/Texture
+#[derive(Clone, Debug, TypedBuilder, Serialize, Deserialize)]
 pub struct Texture {
+    #[builder(default = new_uuid(), setter(skip))]
     pub uuid: Uuid,
     #[serde(flatten)]
     pub texture_type: TextureType,
+    // Only used by image textures, the viewer ignores them for text textures
+    #[builder(default, setter(strip_option))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "minFilter")]
+    pub min_filter: Option<TextureFilter>,
+    #[builder(default, setter(strip_option))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "magFilter")]
+    pub mag_filter: Option<TextureFilter>,
+    #[builder(default, setter(strip_option))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "generateMipmaps")]
+    pub generate_mipmaps: Option<bool>,
+    // Number of samples along the axis with the highest texel density, the viewer clamps it to
+    // the renderer's maximum anisotropy
+    #[builder(default, setter(strip_option))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub anisotropy: Option<u32>,
 }
 
 impl Texture {
     pub fn new(texture_type: TextureType) -> Self {
-        Texture {
-            uuid: Uuid::new_v4(),
-            texture_type,
-        }
+        Texture::builder().texture_type(texture_type).build()
     }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Image {
-    // #[builder(default = Uuid::new_v4(), setter(skip))]
+    // #[builder(default = new_uuid(), setter(skip))]
     pub uuid: Uuid,
     pub url: String,
 }
 
 impl Image {
-    pub fn new(url: &str) -> Self {
-        let mut buf = String::new();
-        match crate::utils::file_extension(url) {
-            Ok("png") => {
-                buf.push_str("data:image/png;base64,");
-                general_purpose::STANDARD.encode_string(
-                    std::fs::read(url)
-                        .unwrap_or_else(|err| panic!("Unable to load file '{}': {}", url, err)),
-                    &mut buf,
-                );
-            }
-            _ => panic!("Unsupported image type"),
-        }
+    // The formats decoded by the browsers (png, jpeg, bmp, gif and webp)
+    pub fn new(url: &str) -> Result<Self, crate::Error> {
+        let mime_type = match crate::utils::image_mime_type(url) {
+            Some(mime_type) if mime_type != "image/x-tga" => mime_type,
+            _ => return Err(crate::Error::UnsupportedFormat(url.to_string())),
+        };
+        let data = std::fs::read(url).map_err(|source| crate::Error::Io {
+            path: url.to_string(),
+            source,
+        })?;
+        Ok(Image::from_bytes(&data, mime_type))
+    }
+
+    // An encoded image in memory, e.g. Image::from_bytes(&jpeg, "image/jpeg")
+    pub fn from_bytes(bytes: &[u8], mime_type: &str) -> Self {
+        let mut url = format!("data:{};base64,", mime_type);
+        general_purpose::STANDARD.encode_string(bytes, &mut url);
         Image {
-            uuid: Uuid::new_v4(),
-            url: buf,
+            uuid: new_uuid(),
+            url,
+        }
+    }
+
+    // Raw pixels (4 bytes per pixel, row by row from the top), e.g. a camera frame, encoded as png
+    #[cfg(feature = "image")]
+    pub fn from_rgba(width: u32, height: u32, pixels: &[u8]) -> Result<Self, Box<dyn Error>> {
+        use image::ImageEncoder;
+        if pixels.len() != width as usize * height as usize * 4 {
+            return Err(format!(
+                "Expected {} bytes for a {}x{} RGBA image, got {}",
+                width as usize * height as usize * 4,
+                width,
+                height,
+                pixels.len()
+            )
+            .into());
         }
+        let mut png = Vec::new();
+        image::codecs::png::PngEncoder::new(&mut png).write_image(
+            pixels,
+            width,
+            height,
+            image::ExtendedColorType::Rgba8,
+        )?;
+        Ok(Image::from_bytes(&png, "image/png"))
+    }
+}
+
+// The viewer doesn't support video textures (nor MJPEG or webm urls), so an image sequence is
+// played by re-publishing the textured object with the next frame (see Meshcat::set_image_frame)
+#[derive(Debug, Clone)]
+pub struct ImageSequence {
+    pub frames: Vec<Image>,
+    pub frame_rate: f64,
+}
+
+impl ImageSequence {
+    // Fails for a frame rate that isn't finite and positive
+    pub fn new(paths: &[&str], frame_rate: f64) -> Result<Self, crate::Error> {
+        check_frame_rate(frame_rate)?;
+        Ok(ImageSequence {
+            frames: paths
+                .iter()
+                .map(|path| Image::new(path))
+                .collect::<Result<_, _>>()?,
+            frame_rate,
+        })
+    }
+
+    pub fn frame_duration(&self) -> std::time::Duration {
+        std::time::Duration::from_secs_f64(1.0 / self.frame_rate)
+    }
+
+    // The frame shown after playing for elapsed, None once the sequence is over
+    pub fn frame_at(&self, elapsed: std::time::Duration) -> Option<usize> {
+        let index = (elapsed.as_secs_f64() * self.frame_rate) as usize;
+        (index < self.frames.len()).then_some(index)
     }
 }
 
+fn check_frame_rate(frame_rate: f64) -> Result<(), crate::Error> {
+    if !frame_rate.is_finite() || frame_rate <= 0.0 {
+        return Err(crate::Error::InvalidFrameRate(frame_rate));
+    }
+    Ok(())
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(tag = "type")]
 pub enum ObjectType {
     Mesh,
     Points,
     LineSegments,
+    // The points are connected one after the other (See utils::polyline)
+    Line,
+    // Like Line with the last point connected back to the first one (See utils::polygon)
+    LineLoop,
+    // Always faces the camera, only uses the material (See utils::sprite)
+    Sprite,
+    // A mesh file loaded by the viewer with its own materials (See utils::mesh_file)
+    #[serde(rename = "_meshfile_object")]
+    MeshFile {
+        format: String,
+        data: MeshData,
+        // The .mtl files of an obj file
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        mtl_library: Option<String>,
+        // The files the mesh refers to (e.g. the textures of the .mtl files) by their name in
+        // the mesh, as data uris
+        #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+        resources: BTreeMap<String, String>,
+    },
+    // The geometries drawn once per instance in a single draw call, the instance matrices are in
+    // the frame of the geometries' origins (See ObjectType::instanced_mesh)
+    InstancedMesh {
+        count: usize,
+        #[serde(rename = "instanceMatrix")]
+        instance_matrix: InstanceAttribute,
+        // Multiplied by the color of the material
+        #[serde(
+            rename = "instanceColor",
+            default,
+            skip_serializing_if = "Option::is_none"
+        )]
+        instance_color: Option<InstanceAttribute>,
+    },
+}
+
+impl ObjectType {
+    // The instances at the poses with their colors (in [0, 1]) if any
+    pub fn instanced_mesh(
+        poses: &[Isometry3<f64>],
+        colors: Option<&Matrix3xX<f64>>,
+    ) -> Result<Self, Box<dyn Error>> {
+        if let Some(colors) = colors {
+            if colors.ncols() != poses.len() {
+                return Err(format!(
+                    "Got {} instances but {} colors",
+                    poses.len(),
+                    colors.ncols()
+                )
+                .into());
+            }
+        }
+        let instance_matrix = InstanceAttribute::new(
+            16,
+            poses
+                .iter()
+                .flat_map(|pose| pose.to_homogeneous().as_slice().to_vec())
+                .collect(),
+        );
+        Ok(ObjectType::InstancedMesh {
+            count: poses.len(),
+            instance_matrix,
+            instance_color: colors
+                .map(|colors| InstanceAttribute::new(3, colors.iter().copied().collect())),
+        })
+    }
+}
+
+// The per instance values of an InstancedMesh, item_size values per instance (the column major
+// 4x4 matrices or the rgb colors)
+// https://threejs.org/docs/#api/en/core/InstancedBufferAttribute
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct InstanceAttribute {
+    #[serde(rename = "itemSize")]
+    pub item_size: usize,
+    #[serde(rename = "type")]
+    pub attribute_type: String,
+    pub array: Vec<f32>,
+    pub normalized: bool,
+}
+
+impl InstanceAttribute {
+    pub fn new(item_size: usize, values: Vec<f64>) -> Self {
+        InstanceAttribute {
+            item_size,
+            attribute_type: ArrayType::Float32.name().to_string(),
+            array: values.into_iter().map(|value| value as f32).collect(),
+            normalized: false,
+        }
+    }
+
+    // The instance matrices of an instance_matrix attribute
+    fn matrices(&self) -> impl Iterator<Item = Matrix4<f64>> + '_ {
+        self.array
+            .chunks_exact(16)
+            .map(|values| Matrix4::from_iterator(values.iter().map(|value| *value as f64)))
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -341,6 +1468,20 @@ pub struct Object {
     pub matrix: Matrix4<f64>,
     #[serde(flatten)]
     pub object_type: ObjectType,
+    // Shadows are only drawn between objects casting and receiving them, from lights casting
+    // them (See utils::ground_plane)
+    #[serde(
+        default,
+        rename = "castShadow",
+        skip_serializing_if = "std::ops::Not::not"
+    )]
+    pub cast_shadow: bool,
+    #[serde(
+        default,
+        rename = "receiveShadow",
+        skip_serializing_if = "std::ops::Not::not"
+    )]
+    pub receive_shadow: bool,
 }
 
 impl Default for Object {
@@ -352,12 +1493,14 @@ impl Default for Object {
 impl Object {
     pub fn new(origin: Isometry3<f64>, object_type: ObjectType) -> Self {
         Object {
-            uuid: Uuid::new_v4(),
+            uuid: new_uuid(),
             material: None,
             geometry: None,
             children: Vec::new(),
             matrix: origin.to_homogeneous(),
             object_type,
+            cast_shadow: false,
+            receive_shadow: false,
         }
     }
 }
@@ -372,6 +1515,18 @@ where
     seq.end()
 }
 
+fn unique_geometries<S>(geometries: &[Geometry], serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    let mut uuids = std::collections::HashSet::new();
+    serializer.collect_seq(
+        geometries
+            .iter()
+            .filter(|geometry| uuids.insert(geometry.uuid)),
+    )
+}
+
 // textures, images, materials should be a Vec<_>,
 // but I don't see a use case for it yet, so to simplify the code it's just an element (Drake's meshcat interface does the same)
 // https://github.com/mrdoob/three.js/wiki/JSON-Object-Scene-format-4
@@ -394,7 +1549,9 @@ pub struct LumpedObject {
         skip_serializing_if = "Option::is_none"
     )]
     pub image: Option<Image>,
+    // Geometries sharing a uuid (See utils::GeometryRegistry) are only sent once
     #[builder(default)]
+    #[serde(serialize_with = "unique_geometries")]
     pub geometries: Vec<Geometry>,
     #[builder(default)]
     #[serde(rename = "materials", serialize_with = "to_one_element_array")]
@@ -439,28 +1596,32 @@ impl<
         }
         // Setting the uuid for the object
         lumped_object.object.material = Some(lumped_object.material.uuid);
-        // Meshcat cylinders have their long axis in y.
         lumped_object.object.children = lumped_object
             .geometries
             .iter()
             .map(|geometry| {
-                let mut object_pose = geometry.origin;
-                if let GeometryType::Cylinder { .. } = &geometry.geometry {
-                    object_pose *= Isometry3::from_parts(
-                        Translation3::new(0.0, 0.0, 0.0),
-                        UnitQuaternion::from_euler_angles(std::f64::consts::FRAC_PI_2, 0.0, 0.0),
-                    );
-                }
+                let object_pose = geometry.origin * geometry.axis_correction;
+                let matrix =
+                    object_pose.to_homogeneous() * Matrix4::new_nonuniform_scaling(&geometry.scale);
                 Box::new(Object {
-                    uuid: Uuid::new_v4(),
+                    uuid: new_uuid(),
                     material: Some(lumped_object.material.uuid),
                     geometry: Some(geometry.uuid),
                     children: Vec::new(),
-                    matrix: object_pose.to_homogeneous(),
+                    matrix,
                     object_type: lumped_object.object.object_type.clone(),
+                    cast_shadow: lumped_object.object.cast_shadow,
+                    receive_shadow: lumped_object.object.receive_shadow,
                 })
             })
             .collect();
+        // Only the children are instanced, the parent groups them
+        if matches!(
+            lumped_object.object.object_type,
+            ObjectType::InstancedMesh { .. }
+        ) {
+            lumped_object.object.object_type = ObjectType::Mesh;
+        }
         LumpedObject {
             metadata: lumped_object.metadata,
             texture: lumped_object.texture,
@@ -472,38 +1633,294 @@ impl<
     }
 }
 
+impl LumpedObject {
+    // The bounds of all the geometries (with their origins) in the parent frame of the object,
+    // returns None if none of the geometries has known bounds
+    pub fn bounding_box(&self) -> Option<BoundingBox> {
+        self.object
+            .children
+            .iter()
+            .filter_map(|child| {
+                let geometry = self
+                    .geometries
+                    .iter()
+                    .find(|geometry| Some(geometry.uuid) == child.geometry)?;
+                let bounds = geometry.geometry.bounding_box()?;
+                let pose = self.object.matrix * child.matrix;
+                match &child.object_type {
+                    ObjectType::InstancedMesh {
+                        instance_matrix, ..
+                    } => instance_matrix
+                        .matrices()
+                        .map(|instance| bounds.transform(&(pose * instance)))
+                        .reduce(|lhs, rhs| lhs.union(&rhs)),
+                    _ => Some(bounds.transform(&pose)),
+                }
+            })
+            .reduce(|lhs, rhs| lhs.union(&rhs))
+    }
+}
+
+// The request data borrows the path so sending it doesn't allocate
 #[derive(Debug, Serialize, Deserialize)]
-pub struct SetTransformData {
+pub struct SetTransformData<'a> {
     matrix: Matrix4<f64>,
-    path: String,
+    path: &'a str,
     #[serde(rename = "type")]
-    request_type: String,
+    request_type: &'a str,
 }
 
-impl SetTransformData {
-    pub fn new(matrix: Isometry3<f64>, path: &str) -> Self {
+impl<'a> SetTransformData<'a> {
+    pub fn new(matrix: Isometry3<f64>, path: &'a str) -> Self {
         SetTransformData {
             matrix: matrix.to_homogeneous(),
-            path: path.to_string(),
-            request_type: "set_transform".to_string(),
+            path,
+            request_type: "set_transform",
         }
     }
 }
 
 #[derive(Debug, Serialize)]
-pub struct SetObjectData {
-    pub object: LumpedObject,
+pub struct SetObjectData<'a> {
+    pub object: &'a LumpedObject,
     pub path: String,
     #[serde(rename = "type")]
     pub request_type: String,
 }
 
-// TODO: LumpedCameraData and SetCameraData
+// https://github.com/meshcat-dev/meshcat#api (set_property)
+#[derive(PartialEq, Clone, Debug, Serialize)]
+#[serde(untagged)]
+pub enum PropertyType {
+    Visible(bool),
+    Position([f64; 3]),
+    Quaternion([f64; 4]),
+    Scale([f64; 3]),
+    Color([f64; 4]),
+    Opacity(f64),
+    // The gradient of the /Background
+    TopColor([f64; 3]),
+    BottomColor([f64; 3]),
+    // The url (or data url) of an equirectangular image lighting the scene, "" removes it
+    EnvironmentMap(String),
+    // Whether the /Background shows the environment map instead of the gradient
+    BackgroundOnEnvironmentMap(bool),
+}
+
+impl PropertyType {
+    // The name of the three.js property
+    pub fn name(&self) -> &'static str {
+        match self {
+            PropertyType::Visible(_) => "visible",
+            PropertyType::Position(_) => "position",
+            PropertyType::Quaternion(_) => "quaternion",
+            PropertyType::Scale(_) => "scale",
+            PropertyType::Color(_) => "color",
+            PropertyType::Opacity(_) => "opacity",
+            PropertyType::TopColor(_) => "top_color",
+            PropertyType::BottomColor(_) => "bottom_color",
+            PropertyType::EnvironmentMap(_) => "environment_map",
+            PropertyType::BackgroundOnEnvironmentMap(_) => "background_on_env_map",
+        }
+    }
+}
+
+// The value is any serializable value for the properties PropertyType doesn't model (See
+// Meshcat::set_property_raw)
+#[derive(Debug, Serialize)]
+pub struct SetPropertyData<'a, V = PropertyType> {
+    pub path: &'a str,
+    pub property: &'a str,
+    pub value: V,
+    #[serde(rename = "type")]
+    pub request_type: &'a str,
+}
+
+impl<'a> SetPropertyData<'a> {
+    pub fn new(path: &'a str, value: PropertyType) -> Self {
+        SetPropertyData {
+            path,
+            property: value.name(),
+            value,
+            request_type: "set_property",
+        }
+    }
+}
+
+impl<'a, V: Serialize> SetPropertyData<'a, V> {
+    // For nested three.js properties, e.g. "material.opacity" or "children[0].visible"
+    pub fn with_name(path: &'a str, property: &'a str, value: V) -> Result<Self, Box<dyn Error>> {
+        if !is_valid_property_name(property) {
+            return Err(format!("Invalid property name '{}'", property).into());
+        }
+        Ok(SetPropertyData {
+            path,
+            property,
+            value,
+            request_type: "set_property",
+        })
+    }
+}
+
+// Identifiers separated by dots, each optionally followed by [index]s
+fn is_valid_property_name(property: &str) -> bool {
+    property.split('.').all(|segment| {
+        let (name, indices) = match segment.split_once('[') {
+            Some((name, indices)) => (name, Some(indices)),
+            None => (segment, None),
+        };
+        let is_identifier = name
+            .chars()
+            .next()
+            .is_some_and(|first| first.is_ascii_alphabetic() || first == '_')
+            && name
+                .chars()
+                .all(|character| character.is_ascii_alphanumeric() || character == '_');
+        let are_indices = indices.is_none_or(|indices| {
+            indices.strip_suffix(']').is_some_and(|indices| {
+                indices.split("][").all(|index| {
+                    !index.is_empty() && index.chars().all(|digit| digit.is_ascii_digit())
+                })
+            })
+        });
+        is_identifier && are_indices
+    })
+}
+
+// https://threejs.org/docs/index.html#api/en/cameras/PerspectiveCamera
+// https://threejs.org/docs/index.html#api/en/cameras/OrthographicCamera
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "type")]
+pub enum CameraType {
+    // The vertical field of view in degrees, the aspect (width / height) is updated by the
+    // viewer when its window is resized
+    #[serde(rename = "PerspectiveCamera")]
+    Perspective {
+        fov: f64,
+        aspect: f64,
+        near: f64,
+        far: f64,
+        zoom: f64,
+    },
+    // The bounds of the view in the camera frame
+    #[serde(rename = "OrthographicCamera")]
+    Orthographic {
+        left: f64,
+        right: f64,
+        top: f64,
+        bottom: f64,
+        near: f64,
+        far: f64,
+        zoom: f64,
+    },
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Camera {
+    pub uuid: Uuid,
+    #[serde(flatten)]
+    pub camera_type: CameraType,
+}
+
+impl Camera {
+    pub fn new(camera_type: CameraType) -> Self {
+        Camera {
+            uuid: new_uuid(),
+            camera_type,
+        }
+    }
+
+    // Like the default camera of the viewer
+    pub fn perspective(fov: f64) -> Self {
+        Camera::new(CameraType::Perspective {
+            fov,
+            aspect: 1.0,
+            near: 0.01,
+            far: 100.0,
+            zoom: 1.0,
+        })
+    }
+
+    // Shows the rectangle of the given width and height around the target (See
+    // Meshcat::set_camera_target)
+    pub fn orthographic(width: f64, height: f64) -> Self {
+        let distance = width.max(height);
+        Camera::new(CameraType::Orthographic {
+            left: -width / 2.0,
+            right: width / 2.0,
+            top: height / 2.0,
+            bottom: -height / 2.0,
+            near: -1000.0 * distance,
+            far: 1000.0 * distance,
+            zoom: 1.0,
+        })
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct LumpedCameraData {
+    pub metadata: Metadata,
+    pub object: Camera,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SetCameraData<'a> {
+    pub object: LumpedCameraData,
+    pub path: &'a str,
+    #[serde(rename = "type")]
+    pub request_type: &'a str,
+}
+
+impl<'a> SetCameraData<'a> {
+    pub fn new(path: &'a str, camera: Camera) -> Self {
+        SetCameraData {
+            object: LumpedCameraData {
+                metadata: Metadata::default(),
+                object: camera,
+            },
+            path,
+            request_type: "set_object",
+        }
+    }
+}
+
+// The point the orbit controls of the camera rotate around (in the y up frame of three.js)
+#[derive(Debug, Serialize)]
+pub struct SetTargetData<'a> {
+    pub path: &'a str,
+    pub value: [f64; 3],
+    #[serde(rename = "type")]
+    pub request_type: &'a str,
+}
+
+impl SetTargetData<'_> {
+    pub fn new(target: Vector3<f64>) -> Self {
+        SetTargetData {
+            path: "",
+            value: to_camera_frame(target),
+            request_type: "set_target",
+        }
+    }
+}
+
+// The viewer's initial background colors (light sky blue to midnight blue), camera field of
+// view and camera position (in the scene frame)
+pub const DEFAULT_BACKGROUND_TOP: u32 = 0x87cefa;
+pub const DEFAULT_BACKGROUND_BOTTOM: u32 = 0x191970;
+pub const DEFAULT_CAMERA_FOV: f64 = 75.0;
+pub const DEFAULT_CAMERA_POSITION: [f64; 3] = [3.0, 0.0, 1.0];
+
+// The default camera is under /Cameras/default/rotated which turns the z up scene frame into
+// the y up frame of three.js
+fn to_camera_frame(point: Vector3<f64>) -> [f64; 3] {
+    [point.x, point.z, -point.y]
+}
+
 #[derive(Debug, Serialize, Deserialize)]
-pub struct DeleteData {
-    pub path: String,
+pub struct DeleteData<'a> {
+    pub path: &'a str,
     #[serde(rename = "type")]
-    pub request_type: String,
+    pub request_type: &'a str,
 }
 
 #[derive(Clone, Debug, Serialize)]
@@ -515,6 +1932,12 @@ pub struct Geometry {
     // Object)
     #[serde(skip)]
     pub origin: Isometry3<f64>,
+    // Applied after the origin, defaults to GeometryType::default_axis_correction
+    #[serde(skip)]
+    pub axis_correction: Isometry3<f64>,
+    // Applied after the axis correction, along the axes of the three.js geometry
+    #[serde(skip)]
+    pub scale: Vector3<f64>,
 }
 
 impl Geometry {
@@ -524,56 +1947,47 @@ impl Geometry {
 
     pub fn new_with_origin(geometry: GeometryType, origin: Isometry3<f64>) -> Self {
         Self {
-            uuid: Uuid::new_v4(),
+            uuid: new_uuid(),
+            axis_correction: geometry.default_axis_correction(),
             geometry,
             origin,
+            scale: Vector3::from_element(1.0),
         }
     }
-}
 
-impl From<&urdf_rs::Visual> for Geometry {
-    fn from(visual: &urdf_rs::Visual) -> Self {
-        Geometry::new_with_origin(
-            GeometryType::from(&visual.geometry),
-            Isometry3::from_parts(
-                Translation3::new(
-                    visual.origin.xyz[0],
-                    visual.origin.xyz[1],
-                    visual.origin.xyz[2],
-                ),
-                UnitQuaternion::from_euler_angles(
-                    visual.origin.rpy[0],
-                    visual.origin.rpy[1],
-                    visual.origin.rpy[2],
-                ),
-            ),
-        )
+    // A unit sphere scaled to the radii along the axes of the origin, e.g. for covariances
+    // (like MeshCat.jl's HyperEllipsoid)
+    pub fn ellipsoid(radii: Vector3<f64>, origin: Isometry3<f64>) -> Self {
+        let sphere = GeometryType::Sphere {
+            radius: 1.0,
+            width_segments: 32,
+            height_segments: 16,
+            phi_start: 0.0,
+            phi_length: 2.0 * std::f64::consts::PI,
+            theta_start: 0.0,
+            theta_length: std::f64::consts::PI,
+        };
+        Geometry::new_with_origin(sphere, origin).with_scale(radii)
     }
-}
 
-impl From<&urdf_rs::Collision> for Geometry {
-    fn from(collision: &urdf_rs::Collision) -> Self {
-        Geometry::new_with_origin(
-            GeometryType::from(&collision.geometry),
-            Isometry3::from_parts(
-                Translation3::new(
-                    collision.origin.xyz[0],
-                    collision.origin.xyz[1],
-                    collision.origin.xyz[2],
-                ),
-                UnitQuaternion::from_euler_angles(
-                    collision.origin.rpy[0],
-                    collision.origin.rpy[1],
-                    collision.origin.rpy[2],
-                ),
-            ),
-        )
+    pub fn with_scale(mut self, scale: Vector3<f64>) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    // e.g. Isometry3::identity() to keep the three.js axes
+    pub fn with_axis_correction(mut self, axis_correction: Isometry3<f64>) -> Self {
+        self.axis_correction = axis_correction;
+        self
     }
 }
 
-impl From<&urdf_rs::Geometry> for GeometryType {
-    fn from(geometry: &urdf_rs::Geometry) -> Self {
-        match geometry {
+// Fails for capsules (See urdf_geometries)
+impl TryFrom<&urdf_rs::Geometry> for GeometryType {
+    type Error = crate::Error;
+
+    fn try_from(geometry: &urdf_rs::Geometry) -> Result<Self, Self::Error> {
+        Ok(match geometry {
             urdf_rs::Geometry::Box { size } => GeometryType::Box {
                 width: size[0],
                 height: size[1],
@@ -589,99 +2003,909 @@ impl From<&urdf_rs::Geometry> for GeometryType {
                 theta_length: 2.0 * std::f64::consts::PI,
             },
             urdf_rs::Geometry::Capsule { .. } => {
-                panic!("Capsule geometry is not supported by Meshcat.")
+                return Err(crate::Error::UnsupportedGeometry(
+                    "A capsule is made of several geometries (See urdf_geometries)".to_string(),
+                ))
             }
             urdf_rs::Geometry::Sphere { radius } => GeometryType::Sphere {
                 radius: *radius,
                 width_segments: 32,
                 height_segments: 16,
+                phi_start: 0.0,
+                phi_length: 2.0 * std::f64::consts::PI,
+                theta_start: 0.0,
+                theta_length: std::f64::consts::PI,
             },
+            // See UrdfVisualizer::with_package_paths for other package paths than ROS_PACKAGE_PATH
             urdf_rs::Geometry::Mesh { filename, .. } => {
-                crate::utils::load_mesh(filename).expect("Failed to load mesh")
+                let filename =
+                    crate::urdf::resolve_uri(filename, &crate::urdf::ros_package_paths())?;
+                crate::utils::load_mesh(&filename)?
             }
-        }
+        })
     }
 }
 
+// The geometries of a URDF visual or collision element at its origin. Meshcat has no capsule
+// geometry, a capsule is a cylinder and two hemispheres
+pub fn urdf_geometries(
+    geometry: &urdf_rs::Geometry,
+    origin: Isometry3<f64>,
+) -> Result<Vec<Geometry>, crate::Error> {
+    let urdf_rs::Geometry::Capsule { radius, length } = *geometry else {
+        let mut single = Geometry::new_with_origin(GeometryType::try_from(geometry)?, origin);
+        // e.g. scale="0.001 0.001 0.001" for a mesh in millimeters
+        if let urdf_rs::Geometry::Mesh {
+            scale: Some(scale), ..
+        } = geometry
+        {
+            single = single.with_scale(Vector3::from(scale.0));
+        }
+        return Ok(vec![single]);
+    };
+    let cylinder = GeometryType::try_from(&urdf_rs::Geometry::Cylinder { radius, length })?;
+    // The y axis of the hemispheres is along the z axis of the capsule like the cylinder's (See
+    // GeometryType::default_axis_correction)
+    let hemisphere = |theta_start: f64, z: f64| {
+        let hemisphere = GeometryType::Sphere {
+            radius,
+            width_segments: 32,
+            height_segments: 8,
+            phi_start: 0.0,
+            phi_length: 2.0 * std::f64::consts::PI,
+            theta_start,
+            theta_length: std::f64::consts::FRAC_PI_2,
+        };
+        Geometry::new_with_origin(hemisphere, origin * Translation3::new(0.0, 0.0, z))
+    };
+    Ok(vec![
+        Geometry::new_with_origin(cylinder, origin),
+        hemisphere(0.0, length / 2.0),
+        hemisphere(std::f64::consts::FRAC_PI_2, -length / 2.0),
+    ])
+}
+
+pub fn urdf_visual_geometries(visual: &urdf_rs::Visual) -> Result<Vec<Geometry>, crate::Error> {
+    urdf_geometries(&visual.geometry, crate::urdf::isometry(&visual.origin))
+}
+
+pub fn urdf_collision_geometries(
+    collision: &urdf_rs::Collision,
+) -> Result<Vec<Geometry>, crate::Error> {
+    urdf_geometries(
+        &collision.geometry,
+        crate::urdf::isometry(&collision.origin),
+    )
+}
+
+// The endpoint meshcat-server listens on by default
+pub const DEFAULT_ZMQ_URL: &str = "tcp://127.0.0.1:6000";
+
+// A request to the meshcat server
+#[derive(Debug)]
+pub enum Command {
+    SetObject(String, Box<LumpedObject>),
+    SetTransform(String, Isometry3<f64>),
+    SetProperty(String, PropertyType),
+    Delete(String),
+}
+
 pub struct Meshcat {
-    socket: zmq::Socket,
+    transport: Box<dyn crate::transport::Transport>,
+    // The last scene published with Meshcat::sync
+    synced_scene: RefCell<crate::scene::SceneDescription>,
+    // The encoded objects sent with Meshcat::set_shared_object keyed by their uuid, entries are
+    // dropped with the objects
+    object_cache: RefCell<HashMap<Uuid, CachedObject>>,
+    // Reused to encode the small requests (set_transform, set_property, delete) so sending them
+    // in a control loop doesn't allocate
+    encode_buffer: RefCell<Vec<u8>>,
+    encoding: crate::transport::Encoding,
+    // What was published so far (See Meshcat::tree)
+    tree: RefCell<crate::tree::SceneTree>,
+}
+
+type CachedObject = (Weak<LumpedObject>, Vec<u8>);
+
+// Same encoding as SetObjectData but with an already encoded object
+fn encode_set_object(
+    encoding: crate::transport::Encoding,
+    path: &str,
+    object: &[u8],
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut buf = Vec::with_capacity(object.len() + path.len() + 32);
+    match encoding {
+        crate::transport::Encoding::MessagePack => {
+            rmp::encode::write_map_len(&mut buf, 3)?;
+            rmp::encode::write_str(&mut buf, "object")?;
+            buf.extend_from_slice(object);
+            rmp::encode::write_str(&mut buf, "path")?;
+            rmp::encode::write_str(&mut buf, path)?;
+            rmp::encode::write_str(&mut buf, "type")?;
+            rmp::encode::write_str(&mut buf, "set_object")?;
+        }
+        crate::transport::Encoding::Json => {
+            buf.extend_from_slice(b"{\"object\":");
+            buf.extend_from_slice(object);
+            buf.extend_from_slice(b",\"path\":");
+            serde_json::to_writer(&mut buf, path)?;
+            buf.extend_from_slice(b",\"type\":\"set_object\"}");
+        }
+    }
+    Ok(buf)
 }
 
 impl Meshcat {
-    pub fn new(endpoint: &str) -> Self {
-        let context = zmq::Context::new();
-        let socket = context.socket(zmq::REQ).unwrap();
-        socket.connect(endpoint).unwrap_or_else(|err| {
-            panic!(
-                "Failed to connect to Meshcat server '{}': {}.",
-                endpoint, err
-            )
-        });
-        Self { socket }
+    #[cfg(feature = "zmq")]
+    pub fn new(endpoint: &str) -> Result<Self, crate::Error> {
+        let transport = crate::transport::ZmqTransport::new(endpoint)?;
+        Ok(Self::with_transport(transport))
+    }
+
+    // Fails instead of blocking when the server doesn't reply within options.connect_timeout,
+    // e.g. Meshcat::try_new(DEFAULT_ZMQ_URL, &MeshcatOptions::default())
+    #[cfg(feature = "zmq")]
+    pub fn try_new(
+        endpoint: &str,
+        options: &crate::transport::MeshcatOptions,
+    ) -> Result<Self, crate::Error> {
+        if options.connect_timeout.is_some() {
+            let probe = crate::transport::ZmqTransport::with_options(
+                endpoint,
+                &crate::transport::MeshcatOptions {
+                    receive_timeout: options.connect_timeout,
+                    retries: 0,
+                    ..options.clone()
+                },
+            )?;
+            crate::transport::Transport::url(&probe).map_err(|err| crate::Error::Connection {
+                endpoint: endpoint.to_string(),
+                message: err.to_string(),
+            })?;
+        }
+        if !options.wait_for_replies {
+            let transport = crate::transport::PipelinedZmqTransport::fire_and_forget(endpoint)?;
+            return Ok(Self::with_transport(transport));
+        }
+        let transport = crate::transport::ZmqTransport::with_options(endpoint, options)?;
+        Ok(Self::with_transport(transport))
+    }
+
+    // Without zmq (wasm32) the requests are sent to a WebSocket url
+    #[cfg(all(target_arch = "wasm32", not(feature = "zmq")))]
+    pub fn new(url: &str) -> Result<Self, crate::Error> {
+        let transport = crate::transport::WebSocketTransport::new(url)?;
+        Ok(Self::with_transport(transport))
+    }
+
+    pub fn with_transport(transport: impl crate::transport::Transport + 'static) -> Self {
+        Self {
+            transport: Box::new(transport),
+            synced_scene: RefCell::default(),
+            object_cache: RefCell::default(),
+            encode_buffer: RefCell::default(),
+            encoding: crate::transport::Encoding::default(),
+            tree: RefCell::default(),
+        }
+    }
+
+    pub(crate) fn encoding(&self) -> crate::transport::Encoding {
+        self.encoding
+    }
+
+    // The encoding of the requests, it has to be set before sending set_shared_object requests
+    // (the cached objects are in the encoding they were sent with)
+    pub fn with_encoding(mut self, encoding: crate::transport::Encoding) -> Self {
+        self.encoding = encoding;
+        self
+    }
+
+    // Loads a scene file (See SceneDescription::from_file) and syncs it, so reloading an edited
+    // file only sends what changed
+    pub fn load_scene(&self, path: &str) -> Result<(), Box<dyn Error>> {
+        self.sync(&crate::scene::SceneDescription::from_file(path)?)
+    }
+
+    pub fn send(&self, command: Command) -> Result<(), Box<dyn Error>> {
+        match command {
+            Command::SetObject(path, object) => self.set_object(&path, object),
+            Command::SetTransform(path, matrix) => self.set_transform(&path, matrix),
+            Command::SetProperty(path, value) => self.set_property(&path, value),
+            Command::Delete(path) => self.delete(&path),
+        }
+    }
+
+    // Only sends the commands needed to go from the previously synced scene to this one
+    // (See SceneDescription::diff), objects published by other means aren't affected
+    pub fn sync(&self, scene: &crate::scene::SceneDescription) -> Result<(), Box<dyn Error>> {
+        for command in self.synced_scene.borrow().diff(scene)? {
+            self.send(command)?;
+        }
+        *self.synced_scene.borrow_mut() = scene.clone();
+        Ok(())
+    }
+
+    // The url of the viewer served by the meshcat server
+    pub fn url(&self) -> Result<String, Box<dyn Error>> {
+        self.transport.url()
+    }
+
+    // The paths, objects and transforms published so far (except with send_raw), e.g. to know
+    // what to delete
+    pub fn tree(&self) -> crate::tree::SceneTree {
+        self.tree.borrow().clone()
+    }
+
+    pub fn exists(&self, path: impl AsRef<str>) -> bool {
+        self.tree.borrow().contains(path.as_ref())
+    }
+
+    // The last transform set at the path
+    pub fn get_transform(&self, path: impl AsRef<str>) -> Option<Isometry3<f64>> {
+        self.tree.borrow().get(path.as_ref())?.transform
+    }
+
+    // Deletes everything published so far, the viewer's default scene (e.g. /Grid) is kept. The
+    // viewer refuses deleting its root path so this is the way to delete all, the paths published
+    // with send_raw or by other clients aren't known and are kept
+    pub fn clear(&self) -> Result<(), Box<dyn Error>> {
+        let roots = self.tree.borrow().roots();
+        for root in roots {
+            self.delete(&root)?;
+        }
+        Ok(())
+    }
+
+    // Starts over from the viewer's initial state: deletes everything (See clear), shows the
+    // grid but not the axes, restores the default gradient background and perspective camera,
+    // and looks at the origin from the default position
+    pub fn reset(&self) -> Result<(), Box<dyn Error>> {
+        self.clear()?;
+        self.set_grid_visible(true)?;
+        self.set_axes_visible(false)?;
+        self.clear_panorama_background()?;
+        self.set_background(DEFAULT_BACKGROUND_TOP, DEFAULT_BACKGROUND_BOTTOM)?;
+        self.set_camera(Camera::perspective(DEFAULT_CAMERA_FOV))?;
+        self.set_camera_pose(DEFAULT_CAMERA_POSITION.into(), Vector3::zeros())
+    }
+
+    // A self contained HTML page of the scene published so far (from the server, like
+    // meshcat-python's static_html), e.g. to share it with someone not running a server
+    pub fn static_html(&self) -> Result<String, Box<dyn Error>> {
+        self.transport.static_html()
+    }
+
+    pub fn save_html(&self, path: &str) -> Result<(), Box<dyn Error>> {
+        std::fs::write(path, self.static_html()?)?;
+        Ok(())
+    }
+
+    // Waits until the server received the requests sent so far, with a transport that doesn't
+    // wait for each reply (e.g. PipelinedZmqTransport)
+    pub fn flush(&self) -> Result<(), Box<dyn Error>> {
+        self.transport.flush()
+    }
+
+    // Sends already encoded (msgpack) data, e.g. for viewer commands this crate doesn't support
+    // yet. The data is sent as is, it has to contain the type and path like the other requests
+    pub fn send_raw(
+        &self,
+        request_type: &str,
+        path: impl AsRef<str>,
+        data: &[u8],
+    ) -> Result<(), Box<dyn Error>> {
+        self.transport.request(request_type, path.as_ref(), data)
     }
 
-    pub fn set_object(&self, path: &str, object: LumpedObject) -> Result<(), Box<dyn Error>> {
+    // Takes the object by value, by reference or as an Arc, so it doesn't have to be cloned to be
+    // sent to several paths
+    pub fn set_object(
+        &self,
+        path: impl AsRef<str>,
+        object: impl Borrow<LumpedObject>,
+    ) -> Result<(), Box<dyn Error>> {
+        let path = crate::path::checked(path.as_ref())?;
         let data = SetObjectData {
-            object,
+            object: object.borrow(),
             path: path.to_string(),
             request_type: "set_object".to_string(),
         };
-        let buf = rmp_serde::encode::to_vec_named(&data)?;
-        self.socket.send_multipart(
-            [data.request_type.as_bytes(), data.path.as_bytes(), &buf],
-            0,
-        )?;
-        let message = self.socket.recv_string(0)?;
-        info!("Received reply {} {}", 0, message.unwrap());
+        let buf = self.encoding.object_to_vec(&data)?;
+        self.transport
+            .request(&data.request_type, &data.path, &buf)?;
+        self.tree
+            .borrow_mut()
+            .apply(path, TreeUpdate::Object(data.object.object.uuid));
         Ok(())
     }
 
-    pub fn set_transform(&self, path: &str, matrix: Isometry3<f64>) -> Result<(), Box<dyn Error>> {
-        let data = SetTransformData::new(matrix, path);
-        let buf = rmp_serde::encode::to_vec_named(&data)?;
-        self.socket.send_multipart(
-            [data.request_type.as_bytes(), data.path.as_bytes(), &buf],
-            0,
-        )?;
-        let message = self.socket.recv_string(0)?;
-        info!("Received reply {} {}", 0, message.unwrap());
+    // Like set_object but the object is only encoded the first time it's sent, the cached
+    // encoding is used as long as the object is alive (an Arc can't be mutated in place while
+    // it's cached, Arc::make_mut moves it which invalidates the cache entry)
+    pub fn set_shared_object(
+        &self,
+        path: impl AsRef<str>,
+        object: &Arc<LumpedObject>,
+    ) -> Result<(), Box<dyn Error>> {
+        let path = crate::path::checked(path.as_ref())?;
+        let buf = {
+            let mut cache = self.object_cache.borrow_mut();
+            cache.retain(|_, (cached, _)| cached.strong_count() > 0);
+            let uuid = object.object.uuid;
+            let is_cached = cache.get(&uuid).is_some_and(|(cached, _)| {
+                cached
+                    .upgrade()
+                    .is_some_and(|cached| Arc::ptr_eq(&cached, object))
+            });
+            if !is_cached {
+                let encoded = self.encoding.object_to_vec(object.as_ref())?;
+                cache.insert(uuid, (Arc::downgrade(object), encoded));
+            }
+            encode_set_object(self.encoding, path, &cache[&uuid].1)?
+        };
+        self.transport.request("set_object", path, &buf)?;
+        self.tree
+            .borrow_mut()
+            .apply(path, TreeUpdate::Object(object.object.uuid));
         Ok(())
     }
 
-    pub fn delete(&self, path: &str) -> Result<(), Box<dyn Error>> {
-        let data = DeleteData {
-            path: path.to_string(),
-            request_type: "delete".to_string(),
-        };
-        let buf = rmp_serde::encode::to_vec_named(&data)?;
-        self.socket.send_multipart(
-            [data.request_type.as_bytes(), data.path.as_bytes(), &buf],
-            0,
-        )?;
-        let message = self.socket.recv_string(0)?;
-        info!("Received reply {} {}", 0, message.unwrap());
+    pub fn set_transform(
+        &self,
+        path: impl AsRef<str>,
+        matrix: Isometry3<f64>,
+    ) -> Result<(), Box<dyn Error>> {
+        let path = crate::path::checked(path.as_ref())?;
+        let data = SetTransformData::new(matrix, path);
+        self.send_small_request(data.request_type, path, &data)?;
+        self.tree
+            .borrow_mut()
+            .apply(path, TreeUpdate::Transform(matrix));
         Ok(())
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    // Encodes the request in the reused buffer
+    fn send_small_request(
+        &self,
+        request_type: &str,
+        path: &str,
+        data: &impl Serialize,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut buf = self.encode_buffer.borrow_mut();
+        buf.clear();
+        self.encoding.encode(&mut buf, data)?;
+        self.transport.request(request_type, path, &buf)
+    }
 
-    #[test]
-    fn test_lumped_object() {
-        let lumped_object = LumpedObject::builder()
-            .geometries(vec![Geometry::new(GeometryType::Box {
-                width: 1.0,
-                height: 1.0,
-                depth: 1.0,
-            })])
-            .build();
-        assert_eq!(lumped_object.geometries.len(), 1);
-        assert!(lumped_object.texture.is_none());
-        assert!(lumped_object.image.is_none());
-        // We only use this field for the children (The geometries the object is composed of)
-        assert!(lumped_object.object.geometry.is_none());
+    pub fn set_point_cloud(
+        &self,
+        path: impl AsRef<str>,
+        cloud: &crate::shapes::PointCloud,
+    ) -> Result<(), Box<dyn Error>> {
+        let path = crate::path::checked(path.as_ref())?;
+        self.set_object(path, cloud.to_object())
+    }
+
+    // Publishes the text under {path}/text and its background (if any) under {path}/background
+    pub fn set_label(
+        &self,
+        path: impl AsRef<str>,
+        text: &str,
+        options: &crate::utils::LabelOptions,
+    ) -> Result<(), Box<dyn Error>> {
+        let path = crate::path::checked(path.as_ref())?;
+        let (text, background) = crate::utils::label(text, options);
+        self.set_object(format!("{}/text", path), text)?;
+        match background {
+            Some(background) => self.set_object(format!("{}/background", path), background),
+            None => self.delete(format!("{}/background", path)),
+        }
+    }
+
+    // Publishes the objects of the triad under the path (See utils::triad_objects), replacing
+    // what was there, and places it at the pose
+    pub fn set_triad(
+        &self,
+        path: impl AsRef<str>,
+        pose: Isometry3<f64>,
+        options: &crate::utils::TriadOptions,
+    ) -> Result<(), Box<dyn Error>> {
+        let path = crate::path::checked(path.as_ref())?;
+        self.delete(path)?;
+        for (name, object) in crate::utils::triad_objects(options) {
+            self.set_object(format!("{}/{}", path, name), object)?;
+        }
+        self.set_transform(path, pose)
+    }
+
+    // Publishes the plane under {path}/plane and its grid (if any) under {path}/grid, the
+    // directional light is made to cast shadows
+    pub fn set_ground_plane(
+        &self,
+        path: impl AsRef<str>,
+        size: f64,
+        options: &crate::utils::GroundPlaneOptions,
+    ) -> Result<(), Box<dyn Error>> {
+        let path = crate::path::checked(path.as_ref())?;
+        let (plane, grid) = crate::utils::ground_plane(size, options)?;
+        self.set_object(format!("{}/plane", path), plane)?;
+        match grid {
+            Some(grid) => self.set_object(format!("{}/grid", path), grid)?,
+            None => self.delete(format!("{}/grid", path))?,
+        }
+        self.set_property_raw("/Lights/DirectionalLight/<object>", "castShadow", true)
+    }
+
+    // Publishes the ruler's lines under {path}/line and its length label under {path}/label
+    pub fn set_ruler(
+        &self,
+        path: impl AsRef<str>,
+        p1: Vector3<f64>,
+        p2: Vector3<f64>,
+        tick_spacing: f64,
+    ) -> Result<(), Box<dyn Error>> {
+        let path = crate::path::checked(path.as_ref())?;
+        let (line, label) = crate::utils::ruler(p1, p2, tick_spacing)?;
+        self.set_object(format!("{}/line", path), line)?;
+        self.set_object(format!("{}/label", path), label)
+    }
+
+    // Publishes the arc's lines under {path}/line and its angle label under {path}/label
+    pub fn set_arc(
+        &self,
+        path: impl AsRef<str>,
+        center: Vector3<f64>,
+        axis: Vector3<f64>,
+        from: Vector3<f64>,
+        to: Vector3<f64>,
+        radius: f64,
+    ) -> Result<(), Box<dyn Error>> {
+        let path = crate::path::checked(path.as_ref())?;
+        let (line, label) = crate::utils::arc(center, axis, from, to, radius)?;
+        self.set_object(format!("{}/line", path), line)?;
+        self.set_object(format!("{}/label", path), label)
+    }
+
+    // Shows a frame of the sequence on a plane of the given size (See utils::screen), the caller
+    // paces the frames, e.g. sending the frame of ImageSequence::frame_at(start.elapsed()) when
+    // it changes between its other updates
+    pub fn set_image_frame(
+        &self,
+        path: impl AsRef<str>,
+        sequence: &ImageSequence,
+        index: usize,
+        width: f64,
+        height: f64,
+    ) -> Result<(), Box<dyn Error>> {
+        let frame = sequence.frames.get(index).ok_or_else(|| {
+            format!(
+                "Frame {} is out of the {} frames of the sequence",
+                index,
+                sequence.frames.len()
+            )
+        })?;
+        self.set_object(path, crate::utils::screen(frame.clone(), width, height))
+    }
+
+    // Replaces the animation of the viewer, the clips animate the object at the path or its
+    // descendants (See AnimationClip::path)
+    pub fn set_animation(
+        &self,
+        path: impl AsRef<str>,
+        clips: &[animation::AnimationClip],
+        options: &animation::AnimationOptions,
+    ) -> Result<(), Box<dyn Error>> {
+        let path = crate::path::checked(path.as_ref())?;
+        let data = animation::SetAnimationData::new(path, clips, options);
+        let buf = self.encoding.to_vec(&data)?;
+        self.transport.request(data.request_type, path, &buf)
+    }
+
+    pub fn set_property(
+        &self,
+        path: impl AsRef<str>,
+        value: PropertyType,
+    ) -> Result<(), Box<dyn Error>> {
+        let path = crate::path::checked(path.as_ref())?;
+        let data = SetPropertyData::new(path, value);
+        self.send_small_request(data.request_type, path, &data)
+    }
+
+    pub fn set_grid_visible(&self, visible: bool) -> Result<(), Box<dyn Error>> {
+        self.set_property("/Grid", PropertyType::Visible(visible))
+    }
+
+    pub fn set_axes_visible(&self, visible: bool) -> Result<(), Box<dyn Error>> {
+        self.set_property("/Axes", PropertyType::Visible(visible))
+    }
+
+    // The background is a vertical gradient between the two 0xRRGGBB colors
+    pub fn set_background(&self, top: u32, bottom: u32) -> Result<(), Box<dyn Error>> {
+        let top = crate::utils::hex_to_rgb(top);
+        let bottom = crate::utils::hex_to_rgb(bottom);
+        self.set_property("/Background", PropertyType::TopColor(top.into()))?;
+        self.set_property("/Background", PropertyType::BottomColor(bottom.into()))
+    }
+
+    // A 360° equirectangular photo (e.g. of the site a robot was scanned in) as the background
+    pub fn set_panorama_background(&self, image: &Image) -> Result<(), Box<dyn Error>> {
+        self.set_property(
+            "/Background",
+            PropertyType::EnvironmentMap(image.url.clone()),
+        )?;
+        self.set_property(
+            "/Background",
+            PropertyType::BackgroundOnEnvironmentMap(true),
+        )
+    }
+
+    // Goes back to the gradient background
+    pub fn clear_panorama_background(&self) -> Result<(), Box<dyn Error>> {
+        self.set_property(
+            "/Background",
+            PropertyType::BackgroundOnEnvironmentMap(false),
+        )?;
+        self.set_property("/Background", PropertyType::EnvironmentMap(String::new()))
+    }
+
+    // The position of the default camera in the scene frame
+    pub fn set_camera_position(&self, position: Vector3<f64>) -> Result<(), Box<dyn Error>> {
+        self.set_property(
+            "/Cameras/default/rotated/<object>",
+            PropertyType::Position(to_camera_frame(position)),
+        )
+    }
+
+    // The point the camera looks at (and orbits around)
+    pub fn set_camera_target(&self, target: Vector3<f64>) -> Result<(), Box<dyn Error>> {
+        let data = SetTargetData::new(target);
+        self.send_small_request(data.request_type, data.path, &data)
+    }
+
+    // Replaces the camera of the viewer, e.g. Camera::perspective(30.0) for a narrower view
+    // than the default one. Its pose is kept (See set_camera_pose)
+    pub fn set_camera(&self, camera: Camera) -> Result<(), Box<dyn Error>> {
+        let data = SetCameraData::new("/Cameras/default/rotated", camera);
+        let buf = self.encoding.to_vec(&data)?;
+        self.transport.request(data.request_type, data.path, &buf)
+    }
+
+    // Places the camera at position looking at target, the orbit controls keep looking at the
+    // target so the position is set relative to an untransformed /Cameras/default
+    pub fn set_camera_pose(
+        &self,
+        position: Vector3<f64>,
+        target: Vector3<f64>,
+    ) -> Result<(), Box<dyn Error>> {
+        self.set_transform("/Cameras/default", Isometry3::identity())?;
+        self.set_camera_position(position)?;
+        self.set_camera_target(target)
+    }
+
+    // Looks down at the xy plane with an orthographic camera showing the given bounds, the
+    // camera is slightly tilted towards -y so the orbit controls keep +y up on the screen
+    pub fn set_2d_view(
+        &self,
+        x_min: f64,
+        x_max: f64,
+        y_min: f64,
+        y_max: f64,
+    ) -> Result<(), Box<dyn Error>> {
+        if x_min >= x_max || y_min >= y_max {
+            return Err(format!(
+                "Invalid bounds x: [{}, {}] y: [{}, {}]",
+                x_min, x_max, y_min, y_max
+            )
+            .into());
+        }
+        let (width, height) = (x_max - x_min, y_max - y_min);
+        let distance = width.max(height);
+        self.set_camera(Camera::orthographic(width, height))?;
+        let center = Vector3::new((x_min + x_max) / 2.0, (y_min + y_max) / 2.0, 0.0);
+        self.set_camera_pose(
+            center + Vector3::new(0.0, -1e-3 * distance, distance),
+            center,
+        )
+    }
+
+    // For objects with a utils::clipping_material
+    pub fn set_clipping_enabled(
+        &self,
+        path: impl AsRef<str>,
+        enabled: bool,
+    ) -> Result<(), Box<dyn Error>> {
+        let path = crate::path::checked(path.as_ref())?;
+        self.set_property_raw(
+            path,
+            "material.uniforms.clipping_enabled.value",
+            if enabled { 1.0 } else { 0.0 },
+        )
+    }
+
+    // See SetPropertyData::with_name
+    pub fn set_nested_property(
+        &self,
+        path: impl AsRef<str>,
+        property: &str,
+        value: impl Serialize,
+    ) -> Result<(), Box<dyn Error>> {
+        let path = crate::path::checked(path.as_ref())?;
+        let data = SetPropertyData::with_name(path, property, value)?;
+        self.send_small_request(data.request_type, path, &data)
+    }
+
+    // Any property with any value, e.g.
+    // set_property_raw("/Cameras/default/rotated/<object>", "zoom", 2.0)
+    pub fn set_property_raw(
+        &self,
+        path: impl AsRef<str>,
+        property: &str,
+        value: impl Serialize,
+    ) -> Result<(), Box<dyn Error>> {
+        self.set_nested_property(path, property, value)
+    }
+
+    pub fn delete(&self, path: impl AsRef<str>) -> Result<(), Box<dyn Error>> {
+        let path = crate::path::checked(path.as_ref())?;
+        let data = DeleteData {
+            path,
+            request_type: "delete",
+        };
+        self.send_small_request(data.request_type, path, &data)?;
+        self.tree.borrow_mut().apply(path, TreeUpdate::Delete);
+        Ok(())
+    }
+
+    // Publishes below the path, e.g. meshcat.at("/robot1")?.set_transform("/base", pose) sets the
+    // transform of /robot1/base. The path is rejected if it isn't a valid ScenePath (with or
+    // without a trailing /)
+    pub fn at(&self, path: impl AsRef<str>) -> Result<ScopedMeshcat<'_>, Box<dyn Error>> {
+        let path = path.as_ref();
+        let prefix = path.trim_end_matches('/');
+        // The root is kept as an empty prefix
+        crate::path::checked(if prefix.is_empty() { path } else { prefix })?;
+        Ok(ScopedMeshcat {
+            meshcat: self,
+            prefix: prefix.to_string(),
+        })
+    }
+
+    // Accumulates requests to send them back to back (See Batch). With the default ZmqTransport
+    // each request still waits for the reply to the previous one (a REQ socket can't pipeline),
+    // so a batch is only faster with a PipelinedZmqTransport
+    pub fn batch(&self) -> Batch<'_> {
+        Batch {
+            meshcat: self,
+            requests: Vec::new(),
+        }
+    }
+}
+
+// Requests encoded as they're added and sent by Batch::send, then their replies are waited for,
+// e.g. the links of a robot
+//     meshcat.batch().set_object("/a", a)?.set_transform("/a", pose)?.send()?
+// With a PipelinedZmqTransport they're in flight together instead of one round trip each
+pub struct Batch<'a> {
+    meshcat: &'a Meshcat,
+    // The type, path and encoded data of each request, and how it changes Meshcat::tree
+    requests: Vec<(&'static str, String, Vec<u8>, Option<TreeUpdate>)>,
+}
+
+impl Batch<'_> {
+    fn push(
+        &mut self,
+        request_type: &'static str,
+        path: &str,
+        data: &impl Serialize,
+        update: Option<TreeUpdate>,
+    ) -> Result<&mut Self, Box<dyn Error>> {
+        let buf = self.meshcat.encoding.to_vec(data)?;
+        Ok(self.push_encoded(request_type, path, buf, update))
+    }
+
+    fn push_encoded(
+        &mut self,
+        request_type: &'static str,
+        path: &str,
+        buf: Vec<u8>,
+        update: Option<TreeUpdate>,
+    ) -> &mut Self {
+        self.requests
+            .push((request_type, path.to_string(), buf, update));
+        self
+    }
+
+    pub fn set_object(
+        &mut self,
+        path: impl AsRef<str>,
+        object: impl Borrow<LumpedObject>,
+    ) -> Result<&mut Self, Box<dyn Error>> {
+        let path = crate::path::checked(path.as_ref())?;
+        let data = SetObjectData {
+            object: object.borrow(),
+            path: path.to_string(),
+            request_type: "set_object".to_string(),
+        };
+        let update = TreeUpdate::Object(data.object.object.uuid);
+        let buf = self.meshcat.encoding.object_to_vec(&data)?;
+        Ok(self.push_encoded("set_object", path, buf, Some(update)))
+    }
+
+    pub fn set_transform(
+        &mut self,
+        path: impl AsRef<str>,
+        matrix: Isometry3<f64>,
+    ) -> Result<&mut Self, Box<dyn Error>> {
+        let path = crate::path::checked(path.as_ref())?;
+        let data = SetTransformData::new(matrix, path);
+        self.push(
+            "set_transform",
+            path,
+            &data,
+            Some(TreeUpdate::Transform(matrix)),
+        )
+    }
+
+    pub fn set_property(
+        &mut self,
+        path: impl AsRef<str>,
+        value: PropertyType,
+    ) -> Result<&mut Self, Box<dyn Error>> {
+        let path = crate::path::checked(path.as_ref())?;
+        self.push(
+            "set_property",
+            path,
+            &SetPropertyData::new(path, value),
+            None,
+        )
+    }
+
+    pub fn delete(&mut self, path: impl AsRef<str>) -> Result<&mut Self, Box<dyn Error>> {
+        let path = crate::path::checked(path.as_ref())?;
+        let data = DeleteData {
+            path,
+            request_type: "delete",
+        };
+        self.push("delete", path, &data, Some(TreeUpdate::Delete))
+    }
+
+    pub fn len(&self) -> usize {
+        self.requests.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.requests.is_empty()
+    }
+
+    // Sends the requests in the order they were added, the batch is emptied even if a request
+    // fails
+    pub fn send(&mut self) -> Result<(), Box<dyn Error>> {
+        for (request_type, path, data, update) in self.requests.drain(..) {
+            self.meshcat.transport.request(request_type, &path, &data)?;
+            if let Some(update) = update {
+                self.meshcat.tree.borrow_mut().apply(&path, update);
+            }
+        }
+        self.meshcat.flush()
+    }
+}
+
+// A Meshcat whose paths are relative to a prefix (See Meshcat::at), e.g. the namespace handed to
+// a library. "/base" and "base" are both below the prefix, "" and "/" are the prefix itself
+pub struct ScopedMeshcat<'a> {
+    meshcat: &'a Meshcat,
+    prefix: String,
+}
+
+impl ScopedMeshcat<'_> {
+    // The full path of a relative path
+    pub fn path(&self, path: impl AsRef<str>) -> String {
+        let path = path.as_ref().trim_matches('/');
+        match (path.is_empty(), self.prefix.is_empty()) {
+            (true, true) => "/".to_string(),
+            (true, false) => self.prefix.clone(),
+            (false, _) => format!("{}/{}", self.prefix, path),
+        }
+    }
+
+    pub fn at(&self, path: impl AsRef<str>) -> Result<ScopedMeshcat<'_>, Box<dyn Error>> {
+        self.meshcat.at(self.path(path))
+    }
+
+    pub fn set_object(
+        &self,
+        path: impl AsRef<str>,
+        object: impl Borrow<LumpedObject>,
+    ) -> Result<(), Box<dyn Error>> {
+        self.meshcat.set_object(self.path(path), object)
+    }
+
+    pub fn set_shared_object(
+        &self,
+        path: impl AsRef<str>,
+        object: &Arc<LumpedObject>,
+    ) -> Result<(), Box<dyn Error>> {
+        self.meshcat.set_shared_object(self.path(path), object)
+    }
+
+    pub fn set_transform(
+        &self,
+        path: impl AsRef<str>,
+        matrix: Isometry3<f64>,
+    ) -> Result<(), Box<dyn Error>> {
+        self.meshcat.set_transform(self.path(path), matrix)
+    }
+
+    pub fn set_property(
+        &self,
+        path: impl AsRef<str>,
+        value: PropertyType,
+    ) -> Result<(), Box<dyn Error>> {
+        self.meshcat.set_property(self.path(path), value)
+    }
+
+    pub fn delete(&self, path: impl AsRef<str>) -> Result<(), Box<dyn Error>> {
+        self.meshcat.delete(self.path(path))
+    }
+}
+
+// Same layout as meshcat-python's notebook iframe
+#[cfg(feature = "evcxr")]
+fn viewer_iframe(url: &str) -> String {
+    format!(
+        "<div style=\"height: 400px; width: 100%; overflow-x: auto; overflow-y: hidden; resize: both\">\
+         <iframe src=\"{}\" style=\"width: 100%; height: 100%; border: none\"></iframe>\
+         </div>",
+        url
+    )
+}
+
+// https://github.com/evcxr/evcxr/blob/main/evcxr_jupyter/samples/evcxr_jupyter_tour.ipynb
+#[cfg(feature = "evcxr")]
+impl Meshcat {
+    // Connects to a meshcat server running with the default settings and shows the viewer in the
+    // notebook cell, fails instead of blocking the kernel when there's no server (See
+    // MeshcatOptions::connect_timeout)
+    pub fn jupyter() -> Result<Self, crate::Error> {
+        let meshcat = Self::try_new(
+            DEFAULT_ZMQ_URL,
+            &crate::transport::MeshcatOptions::default(),
+        )?;
+        meshcat.evcxr_display();
+        Ok(meshcat)
+    }
+
+    // Called by evcxr when a Meshcat is the value of a cell
+    pub fn evcxr_display(&self) {
+        match self.url() {
+            Ok(url) => println!(
+                "EVCXR_BEGIN_CONTENT text/html\n{}\nEVCXR_END_CONTENT",
+                viewer_iframe(&url)
+            ),
+            Err(err) => eprintln!("Failed to get the viewer url: {}", err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::Recorder;
+
+    #[test]
+    fn test_lumped_object() {
+        let lumped_object = LumpedObject::builder()
+            .geometries(vec![Geometry::new(GeometryType::Box {
+                width: 1.0,
+                height: 1.0,
+                depth: 1.0,
+            })])
+            .build();
+        assert_eq!(lumped_object.geometries.len(), 1);
+        assert!(lumped_object.texture.is_none());
+        assert!(lumped_object.image.is_none());
+        // We only use this field for the children (The geometries the object is composed of)
+        assert!(lumped_object.object.geometry.is_none());
         assert_eq!(lumped_object.object.children.len(), 1);
         assert!(lumped_object.object.children[0].geometry.is_some());
         assert_eq!(
@@ -691,6 +2915,40 @@ mod tests {
         assert!(lumped_object.material.map.is_none());
     }
 
+    #[test]
+    fn test_instanced_mesh() {
+        let poses = [
+            Isometry3::translation(1.0, 0.0, 0.0),
+            Isometry3::translation(-1.0, 2.0, 0.0),
+        ];
+        let colors = Matrix3xX::from_columns(&[Vector3::x(), Vector3::y()]);
+        let object_type = ObjectType::instanced_mesh(&poses, Some(&colors)).unwrap();
+        let lumped_object = LumpedObject::builder()
+            .geometries(vec![Geometry::new(GeometryType::Box {
+                width: 1.0,
+                height: 1.0,
+                depth: 1.0,
+            })])
+            .object(Object::new(Isometry3::identity(), object_type))
+            .build();
+        assert!(matches!(lumped_object.object.object_type, ObjectType::Mesh));
+        let value = serde_json::to_value(&lumped_object.object.children[0]).unwrap();
+        assert_eq!(value["type"], "InstancedMesh");
+        assert_eq!(value["count"], 2);
+        assert_eq!(value["instanceMatrix"]["itemSize"], 16);
+        assert_eq!(
+            value["instanceMatrix"]["array"].as_array().unwrap().len(),
+            32
+        );
+        assert_eq!(value["instanceMatrix"]["array"][28], -1.0);
+        assert_eq!(value["instanceColor"]["array"][4], 1.0);
+        let bounding_box = lumped_object.bounding_box().unwrap();
+        assert_eq!(bounding_box.min, Point3::new(-1.5, -0.5, -0.5));
+        assert_eq!(bounding_box.max, Point3::new(1.5, 2.5, 0.5));
+
+        assert!(ObjectType::instanced_mesh(&poses, Some(&Matrix3xX::zeros(1))).is_err());
+    }
+
     #[test]
     fn test_multiple_geometries() {
         let lumped_object = LumpedObject::builder()
@@ -729,6 +2987,106 @@ mod tests {
         assert!(lumped_object.material.map.is_none());
     }
 
+    #[test]
+    fn test_ellipsoid() {
+        let origin = Isometry3::rotation(Vector3::z() * std::f64::consts::FRAC_PI_2);
+        let lumped_object = LumpedObject::builder()
+            .geometries(vec![Geometry::ellipsoid(
+                Vector3::new(1.0, 2.0, 3.0),
+                origin,
+            )])
+            .build();
+        let bounding_box = lumped_object.bounding_box().unwrap();
+        // The x radius is along y once rotated
+        assert!(bounding_box
+            .size()
+            .relative_eq(&Vector3::new(4.0, 2.0, 6.0), 1e-9, 1e-9));
+    }
+
+    #[test]
+    fn test_extra_material_properties() {
+        let serde_json::Value::Object(extra) =
+            serde_json::json!({"depthTest": false, "fog": false})
+        else {
+            unreachable!()
+        };
+        let material = Material::builder().color(0xff0000).extra(extra).build();
+        let value = serde_json::to_value(&material).unwrap();
+        assert_eq!(value["depthTest"], false);
+        assert_eq!(value["fog"], false);
+        assert_eq!(value["color"], 0xff0000);
+        let buf = rmp_serde::to_vec_named(&material).unwrap();
+        assert!(buf.windows(9).any(|window| window == b"depthTest"));
+    }
+
+    #[test]
+    fn test_pbr_materials() {
+        let material = Material::builder()
+            .material_type(MaterialType::standard(0.5, 1.0))
+            .color(0xff0000)
+            .build();
+        let value = serde_json::to_value(&material).unwrap();
+        assert_eq!(value["type"], "MeshStandardMaterial");
+        assert_eq!(value["roughness"], 0.5);
+        assert_eq!(value["metalness"], 1.0);
+        assert_eq!(value["emissiveIntensity"], 1.0);
+        let material = Material::builder()
+            .material_type(MaterialType::physical(0.2, 0.0, 1.0))
+            .build();
+        let value = serde_json::to_value(&material).unwrap();
+        assert_eq!(value["type"], "MeshPhysicalMaterial");
+        assert_eq!(value["clearcoat"], 1.0);
+        assert_eq!(value["clearcoatRoughness"], 0.0);
+    }
+
+    #[test]
+    fn test_axis_correction() {
+        let sphere = |theta_length: f64| GeometryType::Sphere {
+            radius: 1.0,
+            width_segments: 16,
+            height_segments: 8,
+            phi_start: 0.0,
+            phi_length: 2.0 * std::f64::consts::PI,
+            theta_start: 0.0,
+            theta_length,
+        };
+        let cone = GeometryType::Cone {
+            radius: 1.0,
+            height: 2.0,
+            radial_segments: 16,
+            height_segments: 1,
+            theta_start: 0.0,
+            theta_length: 2.0 * std::f64::consts::PI,
+        };
+        let lumped_object = LumpedObject::builder()
+            .geometries(vec![
+                Geometry::new(cone.clone()),
+                Geometry::new(cone).with_axis_correction(Isometry3::identity()),
+                Geometry::new(GeometryType::Box {
+                    width: 1.0,
+                    height: 1.0,
+                    depth: 1.0,
+                }),
+                Geometry::new(sphere(std::f64::consts::FRAC_PI_2)),
+                Geometry::new(sphere(std::f64::consts::PI)),
+            ])
+            .build();
+        let children = &lumped_object.object.children;
+        // The apex (+y in three.js) points along z
+        let apex = children[0]
+            .matrix
+            .transform_point(&Point3::new(0.0, 1.0, 0.0));
+        assert!((apex - Point3::new(0.0, 0.0, 1.0)).norm() < 1e-9);
+        assert_eq!(children[1].matrix, Matrix4::identity());
+        assert_eq!(children[2].matrix, Matrix4::identity());
+        // The pole of the hemisphere points along z, the full sphere isn't rotated
+        let pole = children[3]
+            .matrix
+            .transform_point(&Point3::new(0.0, 1.0, 0.0));
+        assert!((pole - Point3::new(0.0, 0.0, 1.0)).norm() < 1e-9);
+        assert_eq!(children[4].matrix, Matrix4::identity());
+    }
+
     #[test]
     fn test_object_with_texture() {
         let lumped_object = LumpedObject::builder()
@@ -768,7 +3126,7 @@ mod tests {
                 height: 1.0,
                 depth: 1.0,
             })])
-            .image(Image::new("examples/data/HeadTextureMultisense.png"))
+            .image(Image::new("examples/data/HeadTextureMultisense.png").unwrap())
             .texture(Texture::new(TextureType::new_image()))
             .build();
         assert_eq!(lumped_object.geometries.len(), 1);
@@ -786,4 +3144,888 @@ mod tests {
             }
         );
     }
+
+    #[test]
+    fn test_texture_filters() {
+        let texture = Texture::builder()
+            .texture_type(TextureType::new_image())
+            .min_filter(TextureFilter::LinearMipmapLinear)
+            .mag_filter(TextureFilter::Linear)
+            .generate_mipmaps(true)
+            .build();
+        let value = serde_json::to_value(&texture).unwrap();
+        assert_eq!(value["minFilter"], 1008);
+        assert_eq!(value["magFilter"], 1006);
+        assert_eq!(value["generateMipmaps"], true);
+        let value = serde_json::to_value(Texture::new(TextureType::new_image())).unwrap();
+        assert!(value.get("minFilter").is_none());
+        assert!(value.get("magFilter").is_none());
+        assert!(value.get("generateMipmaps").is_none());
+        assert!(value.get("anisotropy").is_none());
+    }
+
+    #[test]
+    fn test_texture_anisotropy() {
+        let texture = Texture::builder()
+            .texture_type(TextureType::new_image())
+            .anisotropy(16)
+            .build();
+        assert_eq!(serde_json::to_value(&texture).unwrap()["anisotropy"], 16);
+    }
+
+    #[test]
+    fn test_debug_materials() {
+        let material = Material::builder()
+            .material_type(MaterialType::MeshNormal)
+            .build();
+        let value = serde_json::to_value(&material).unwrap();
+        assert_eq!(value["type"], "MeshNormalMaterial");
+        let material = Material::builder()
+            .material_type(MaterialType::MeshDepth)
+            .build();
+        let value = serde_json::to_value(&material).unwrap();
+        assert_eq!(value["type"], "MeshDepthMaterial");
+    }
+
+    #[test]
+    fn test_shader_material() {
+        let material = Material::builder()
+            .material_type(MaterialType::Shader {
+                vertex_shader: "void main() {}".to_string(),
+                fragment_shader: "uniform float time; void main() {}".to_string(),
+                uniforms: BTreeMap::from([
+                    ("time".to_string(), Uniform::Float(1.5)),
+                    ("tint".to_string(), Uniform::Color(0xff0000)),
+                    ("plane".to_string(), Uniform::Vector4([0.0, 0.0, 1.0, 0.0])),
+                ]),
+            })
+            .build();
+        let value = serde_json::to_value(&material).unwrap();
+        assert_eq!(value["type"], "ShaderMaterial");
+        assert_eq!(value["vertexShader"], "void main() {}");
+        assert_eq!(
+            value["uniforms"]["time"],
+            serde_json::json!({"type": "f", "value": 1.5})
+        );
+        assert_eq!(
+            value["uniforms"]["tint"],
+            serde_json::json!({"type": "c", "value": 0xff0000})
+        );
+        assert_eq!(value["uniforms"]["plane"]["type"], "v4");
+    }
+
+    #[test]
+    fn test_partial_sphere_and_torus() {
+        let cap = GeometryType::Sphere {
+            radius: 1.0,
+            width_segments: 16,
+            height_segments: 8,
+            phi_start: 0.0,
+            phi_length: std::f64::consts::PI,
+            theta_start: 0.0,
+            theta_length: std::f64::consts::FRAC_PI_4,
+        };
+        let value = serde_json::to_value(Geometry::new(cap)).unwrap();
+        assert_eq!(value["type"], "SphereGeometry");
+        assert_eq!(value["phiLength"], std::f64::consts::PI);
+        assert_eq!(value["thetaLength"], std::f64::consts::FRAC_PI_4);
+        let arc = GeometryType::Torus {
+            radius: 1.0,
+            tube: 0.1,
+            radial_segments: 8,
+            tubular_segments: 32,
+            arc: std::f64::consts::FRAC_PI_2,
+        };
+        let value = serde_json::to_value(Geometry::new(arc)).unwrap();
+        assert_eq!(value["arc"], std::f64::consts::FRAC_PI_2);
+    }
+
+    #[test]
+    fn test_quantized_attributes() {
+        let colors = Matrix3xX::from_column_slice(&[0.0, 0.5, 1.0, 1.5, -0.5, 0.25]);
+        let attribute =
+            BufferGeometryAttribute::quantized(colors.clone(), ArrayType::Uint8).unwrap();
+        let value = serde_json::to_value(&attribute).unwrap();
+        assert_eq!(value["array"], serde_json::json!([0, 128, 255, 255, 0, 64]));
+        assert_eq!(value["normalized"], true);
+        let normals = BufferGeometryAttribute::quantized(colors, ArrayType::Int16).unwrap();
+        let value = serde_json::to_value(&normals).unwrap();
+        assert_eq!(value["array"][1], 16384);
+        assert_eq!(value["array"][4], -16384);
+        assert!(
+            BufferGeometryAttribute::quantized(Matrix3xX::zeros(1), ArrayType::Float32).is_err()
+        );
+        assert!(
+            BufferGeometryAttribute::quantized(Matrix3xX::zeros(1), ArrayType::Uint32).is_err()
+        );
+
+        // 1 byte per value instead of 4 for the floats
+        let points = Matrix3xX::from_element(100, 0.3);
+        let floats = BufferGeometryAttribute::new(points.clone());
+        let bytes = BufferGeometryAttribute::quantized(points, ArrayType::Uint8).unwrap();
+        assert!(matches!(&bytes.array, AttributeArray::Uint8(values) if values.len() == 300));
+        assert_eq!(
+            rmp_serde::to_vec_named(&floats).unwrap().len()
+                - rmp_serde::to_vec_named(&bytes).unwrap().len(),
+            300 * 3 + "Float32Array".len() - "Uint8Array".len()
+        );
+        assert!((bytes.to_matrix()[(0, 0)] - 0.3).abs() < 1.0 / 255.0);
+    }
+
+    #[test]
+    fn test_typed_attributes() {
+        let points = Matrix3xX::from_column_slice(&[0.1, 2.6, -1.0]);
+        let size =
+            |attribute: &BufferGeometryAttribute| rmp_serde::to_vec_named(attribute).unwrap().len();
+        let floats = BufferGeometryAttribute::new(points.clone());
+        let doubles = BufferGeometryAttribute::with_type(points.clone(), ArrayType::Float64);
+        // 4 bytes per value instead of 8
+        assert_eq!(size(&doubles) - size(&floats), 3 * 4);
+        let value = serde_json::to_value(&floats).unwrap();
+        assert_eq!(value["array"][0].as_f64().unwrap() as f32, 0.1);
+        let value = serde_json::to_value(&doubles).unwrap();
+        assert_eq!(value["type"], "Float64Array");
+        assert_eq!(value["array"][0], 0.1);
+        let integers = BufferGeometryAttribute::with_type(points, ArrayType::Uint32);
+        let value = serde_json::to_value(&integers).unwrap();
+        assert_eq!(value["type"], "Uint32Array");
+        assert_eq!(value["array"], serde_json::json!([0, 3, 0]));
+        assert_eq!(value["normalized"], false);
+        // A msgpack extension of the little endian values (like meshcat-python's numpy arrays)
+        let encoded = rmp_serde::to_vec_named(&integers).unwrap();
+        let ext = [0xc7, 12, 0x16, 0, 0, 0, 0, 3, 0, 0, 0, 0, 0, 0, 0];
+        assert!(encoded.windows(ext.len()).any(|window| window == ext));
+    }
+
+    #[test]
+    fn test_indexed_buffer_geometry() {
+        // A square made of two triangles sharing a diagonal
+        let vertices = Matrix3xX::from_columns(&[
+            Vector3::zeros(),
+            Vector3::x(),
+            Vector3::new(1.0, 1.0, 0.0),
+            Vector3::y(),
+        ]);
+        let data =
+            BufferGeometryData::triangles(vertices.clone(), &[[0, 1, 2], [0, 2, 3]]).unwrap();
+        let normals = &data.attributes.normal.as_ref().unwrap().to_matrix();
+        for normal in normals.column_iter() {
+            assert!(normal.relative_eq(&Vector3::z(), 1e-9, 1e-9));
+        }
+        let value = serde_json::to_value(Geometry::new(GeometryType::Buffer {
+            data: Box::new(data),
+        }))
+        .unwrap();
+        assert_eq!(
+            value["data"]["index"],
+            serde_json::json!({"type": "Uint32Array", "array": [0, 1, 2, 0, 2, 3]})
+        );
+        assert!(BufferGeometryData::triangles(vertices, &[[0, 1, 4]]).is_err());
+    }
+
+    #[test]
+    fn test_triangular_mesh() {
+        let vertices = Matrix3xX::from_columns(&[Vector3::zeros(), Vector3::x(), Vector3::y()]);
+        let faces = Matrix3xX::from_column_slice(&[0, 1, 2]);
+        let object = TriangularMesh::builder()
+            .vertices(vertices.clone())
+            .faces(faces.clone())
+            .colors(Matrix3xX::from_element(3, 0.5))
+            .build()
+            .into_object(0xffffff)
+            .unwrap();
+        assert_eq!(object.material.vertex_colors, Some(true));
+        let GeometryType::Buffer { data } = &object.geometries[0].geometry else {
+            panic!("Expected a buffer geometry");
+        };
+        assert_eq!(data.attributes.color.to_matrix()[(0, 2)], 0.5);
+        assert_eq!(data.index.as_ref().unwrap().array, [0, 1, 2]);
+        let mesh = TriangularMesh::builder()
+            .vertices(vertices)
+            .faces(faces)
+            .normals(Matrix3xX::zeros(2))
+            .build();
+        assert!(GeometryType::try_from(mesh).is_err());
+    }
+
+    #[test]
+    fn test_interleaved_buffer() {
+        let positions = Matrix3xX::from_columns(&[Vector3::zeros(), Vector3::new(1.0, 2.0, 3.0)]);
+        let colors = Matrix3xX::from_element(2, 0.5);
+        let data = InterleavedBufferData::new(&positions, Some(&colors), None).unwrap();
+        let value = serde_json::to_value(Geometry::new(GeometryType::InterleavedBuffer {
+            data: Box::new(data),
+        }))
+        .unwrap();
+        assert_eq!(value["type"], "BufferGeometry");
+        let data = &value["data"];
+        assert_eq!(data["attributes"]["color"]["offset"], 3);
+        let buffer_uuid = data["attributes"]["position"]["data"].as_str().unwrap();
+        let buffer = &data["interleavedBuffers"][buffer_uuid];
+        assert_eq!(buffer["stride"], 6);
+        let words = &data["arrayBuffers"][buffer["buffer"].as_str().unwrap()];
+        assert_eq!(words.as_array().unwrap().len(), 12);
+        assert_eq!(words[7], 2.0f32.to_bits());
+        assert_eq!(words[9], 0.5f32.to_bits());
+
+        assert!(InterleavedBufferData::new(&positions, None, Some(&Matrix3xX::zeros(1))).is_err());
+    }
+
+    #[test]
+    fn test_encode_set_object() {
+        let object = LumpedObject::builder()
+            .geometries(vec![Geometry::new(GeometryType::Sphere {
+                radius: 1.0,
+                width_segments: 8,
+                height_segments: 8,
+                phi_start: 0.0,
+                phi_length: 2.0 * std::f64::consts::PI,
+                theta_start: 0.0,
+                theta_length: std::f64::consts::PI,
+            })])
+            .build();
+        let data = SetObjectData {
+            object: &object,
+            path: "/sphere".to_string(),
+            request_type: "set_object".to_string(),
+        };
+        assert_eq!(
+            encode_set_object(
+                crate::transport::Encoding::MessagePack,
+                "/sphere",
+                &rmp_serde::encode::to_vec_named(&object).unwrap()
+            )
+            .unwrap(),
+            rmp_serde::encode::to_vec_named(&data).unwrap()
+        );
+        assert_eq!(
+            encode_set_object(
+                crate::transport::Encoding::Json,
+                "/sphere",
+                &serde_json::to_vec(&object).unwrap()
+            )
+            .unwrap(),
+            serde_json::to_vec(&data).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_uuid_seed() {
+        let object = || {
+            LumpedObject::builder()
+                .geometries(vec![Geometry::new(GeometryType::Sphere {
+                    radius: 1.0,
+                    width_segments: 8,
+                    height_segments: 8,
+                    phi_start: 0.0,
+                    phi_length: 2.0 * std::f64::consts::PI,
+                    theta_start: 0.0,
+                    theta_length: std::f64::consts::PI,
+                })])
+                .build()
+        };
+        set_uuid_seed(Some(42));
+        let first = rmp_serde::encode::to_vec_named(&object()).unwrap();
+        set_uuid_seed(Some(42));
+        let second = object();
+        assert_eq!(first, rmp_serde::encode::to_vec_named(&second).unwrap());
+        assert_ne!(second.material.uuid, second.object.uuid);
+        assert_eq!(second.object.uuid.get_version_num(), 4);
+        set_uuid_seed(None);
+        assert_ne!(first, rmp_serde::encode::to_vec_named(&object()).unwrap());
+    }
+
+    #[test]
+    fn test_image_sequence() {
+        let sequence = ImageSequence::new(
+            &[
+                "examples/data/HeadTextureMultisense.png",
+                "examples/data/HeadTextureMultisense.png",
+            ],
+            4.0,
+        )
+        .unwrap();
+        assert_eq!(sequence.frames.len(), 2);
+        assert_ne!(sequence.frames[0].uuid, sequence.frames[1].uuid);
+        assert_eq!(
+            sequence.frame_duration(),
+            std::time::Duration::from_millis(250)
+        );
+        assert_eq!(sequence.frame_at(std::time::Duration::ZERO), Some(0));
+        assert_eq!(
+            sequence.frame_at(std::time::Duration::from_millis(300)),
+            Some(1)
+        );
+        assert_eq!(
+            sequence.frame_at(std::time::Duration::from_millis(500)),
+            None
+        );
+
+        let recorder = Recorder::default();
+        let meshcat = Meshcat::with_transport(recorder.clone());
+        meshcat
+            .set_image_frame("/screen", &sequence, 1, 1.0, 1.0)
+            .unwrap();
+        assert_eq!(recorder.take().len(), 1);
+        assert!(meshcat
+            .set_image_frame("/screen", &sequence, 2, 1.0, 1.0)
+            .is_err());
+    }
+
+    #[test]
+    fn test_image_formats() {
+        let image = Image::from_bytes(&[1, 2], "image/jpeg");
+        assert_eq!(image.url, "data:image/jpeg;base64,AQI=");
+        let directory = std::env::temp_dir().join(format!("meshcat-image-{}", new_uuid()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("frame.JPG");
+        std::fs::write(&path, [1, 2]).unwrap();
+        assert_eq!(
+            Image::new(path.to_str().unwrap()).unwrap().url,
+            "data:image/jpeg;base64,AQI="
+        );
+        std::fs::remove_dir_all(&directory).unwrap();
+        #[cfg(feature = "image")]
+        {
+            let image = Image::from_rgba(2, 1, &[255, 0, 0, 255, 0, 255, 0, 128]).unwrap();
+            assert!(image.url.starts_with("data:image/png;base64,iVBORw0KGgo"));
+            assert!(Image::from_rgba(2, 2, &[0; 4]).is_err());
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(matches!(
+            Image::new("examples/data/missing.png"),
+            Err(crate::Error::Io { .. })
+        ));
+        assert!(matches!(
+            Image::new("examples/data/head_multisense.obj"),
+            Err(crate::Error::UnsupportedFormat(_))
+        ));
+        assert!(matches!(
+            ImageSequence::new(&["examples/data/missing.png"], 1.0),
+            Err(crate::Error::Io { .. })
+        ));
+        for frame_rate in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            assert!(matches!(
+                ImageSequence::new(&[], frame_rate),
+                Err(crate::Error::InvalidFrameRate(_))
+            ));
+        }
+        let err = crate::utils::load_mesh("examples/data/missing.obj").unwrap_err();
+        assert!(matches!(err, crate::Error::Io { .. }));
+        assert!(err.to_string().contains("examples/data/missing.obj"));
+        assert!(matches!(
+            crate::utils::load_mesh("examples/data/mesh"),
+            Err(crate::Error::UnsupportedFormat(_))
+        ));
+    }
+
+    #[cfg(feature = "evcxr")]
+    #[test]
+    fn test_viewer_iframe() {
+        let html = viewer_iframe("http://127.0.0.1:7000/static/");
+        assert!(html.starts_with("<div"));
+        assert!(html.contains("<iframe src=\"http://127.0.0.1:7000/static/\""));
+        assert!(html.ends_with("</div>"));
+    }
+
+    #[test]
+    fn test_set_property_data() {
+        let data = SetPropertyData::new("/box", PropertyType::Visible(false));
+        let value = serde_json::to_value(&data).unwrap();
+        assert_eq!(value["type"], "set_property");
+        assert_eq!(value["path"], "/box");
+        assert_eq!(value["property"], "visible");
+        assert_eq!(value["value"], false);
+        let data = SetPropertyData::new("/box", PropertyType::Color([1.0, 0.0, 0.0, 0.5]));
+        let value = serde_json::to_value(&data).unwrap();
+        assert_eq!(value["property"], "color");
+        assert_eq!(value["value"], serde_json::json!([1.0, 0.0, 0.0, 0.5]));
+    }
+
+    #[test]
+    fn test_background_colors() {
+        let data = SetPropertyData::new("/Background", PropertyType::TopColor([1.0, 0.0, 0.0]));
+        let value = serde_json::to_value(&data).unwrap();
+        assert_eq!(value["property"], "top_color");
+        assert_eq!(value["value"], serde_json::json!([1.0, 0.0, 0.0]));
+        assert_eq!(
+            PropertyType::BottomColor([0.0, 0.0, 1.0]).name(),
+            "bottom_color"
+        );
+        let data = SetPropertyData::new(
+            "/Background",
+            PropertyType::EnvironmentMap("data:image/png;base64,".to_string()),
+        );
+        let value = serde_json::to_value(&data).unwrap();
+        assert_eq!(value["property"], "environment_map");
+        assert_eq!(value["value"], "data:image/png;base64,");
+    }
+
+    #[test]
+    fn test_set_target_data() {
+        let data = SetTargetData::new(Vector3::new(1.0, 2.0, 3.0));
+        let value = serde_json::to_value(&data).unwrap();
+        assert_eq!(value["type"], "set_target");
+        assert_eq!(value["path"], "");
+        assert_eq!(value["value"], serde_json::json!([1.0, 3.0, -2.0]));
+    }
+
+    #[test]
+    fn test_set_camera_data() {
+        let camera = Camera::new(CameraType::Orthographic {
+            left: -1.0,
+            right: 1.0,
+            top: 2.0,
+            bottom: -2.0,
+            near: -10.0,
+            far: 10.0,
+            zoom: 1.0,
+        });
+        let data = SetCameraData::new("/Cameras/default/rotated", camera);
+        let value = serde_json::to_value(&data).unwrap();
+        assert_eq!(value["type"], "set_object");
+        assert_eq!(value["object"]["metadata"]["type"], "Object");
+        assert_eq!(value["object"]["object"]["type"], "OrthographicCamera");
+        assert_eq!(value["object"]["object"]["top"], 2.0);
+
+        let recorder = Recorder::default();
+        let meshcat = Meshcat::with_transport(recorder.clone())
+            .with_encoding(crate::transport::Encoding::Json);
+        meshcat.set_camera(Camera::perspective(30.0)).unwrap();
+        let requests = recorder.take();
+        assert_eq!(requests[0].0, "set_object");
+        assert_eq!(requests[0].1, "/Cameras/default/rotated");
+        let value: serde_json::Value = serde_json::from_slice(&requests[0].2).unwrap();
+        assert_eq!(value["object"]["object"]["type"], "PerspectiveCamera");
+        assert_eq!(value["object"]["object"]["fov"], 30.0);
+    }
+
+    #[test]
+    fn test_nested_property_names() {
+        let data = SetPropertyData::with_name("/box", "material.opacity", 0.5).unwrap();
+        let value = serde_json::to_value(&data).unwrap();
+        assert_eq!(value["property"], "material.opacity");
+        assert_eq!(value["value"], 0.5);
+        for valid in [
+            "visible",
+            "material.wireframe",
+            "children[0].visible",
+            "a[1][2]._b",
+        ] {
+            assert!(is_valid_property_name(valid), "{}", valid);
+        }
+        for invalid in [
+            "",
+            "material.",
+            ".visible",
+            "children[].visible",
+            "children[0",
+            "0a",
+        ] {
+            assert!(!is_valid_property_name(invalid), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_scene_tree_tracking() {
+        let recorder = Recorder::default();
+        let meshcat = Meshcat::with_transport(recorder.clone());
+        let object = crate::utils::triad(Isometry3::identity());
+        let uuid = object.object.uuid;
+        meshcat.set_object("/robot/base", object).unwrap();
+        meshcat
+            .set_transform("/robot/arm", Isometry3::translation(0.0, 1.0, 0.0))
+            .unwrap();
+        let frame = Arc::new(crate::utils::triad(Isometry3::identity()));
+        meshcat
+            .set_shared_object("/robot/base/frame", &frame)
+            .unwrap();
+        meshcat
+            .set_camera_pose(Vector3::x(), Vector3::zeros())
+            .unwrap();
+        let robot = crate::path::ScenePath::new("/robot").unwrap();
+        assert!(meshcat.exists(&robot));
+        assert!(!meshcat.exists(robot.join("base/link").unwrap()));
+        assert_eq!(meshcat.tree().len(), 4);
+        assert_eq!(
+            meshcat.tree().get("/robot/base").unwrap().object,
+            Some(uuid)
+        );
+        assert_eq!(
+            meshcat.get_transform("/robot/arm"),
+            Some(Isometry3::translation(0.0, 1.0, 0.0))
+        );
+        recorder.take();
+        meshcat.clear().unwrap();
+        let deleted = recorder
+            .take()
+            .into_iter()
+            .map(|(_, path, _)| path)
+            .collect::<Vec<_>>();
+        assert_eq!(deleted, ["/robot/arm", "/robot/base"]);
+        assert!(!meshcat.exists("/robot"));
+        assert!(meshcat.exists("/Cameras/default"));
+    }
+
+    #[test]
+    fn test_reset() {
+        let recorder = Recorder::default();
+        let meshcat = Meshcat::with_transport(recorder.clone())
+            .with_encoding(crate::transport::Encoding::Json);
+        meshcat
+            .set_object("/box", crate::utils::triad(Isometry3::identity()))
+            .unwrap();
+        meshcat.set_background(0xffffff, 0xffffff).unwrap();
+        recorder.take();
+        meshcat.reset().unwrap();
+        let requests = recorder.take();
+        assert_eq!(requests[0].0, "delete");
+        assert_eq!(requests[0].1, "/box");
+        assert!(!meshcat.exists("/box"));
+        let top_color = requests
+            .iter()
+            .map(|(_, _, data)| serde_json::from_slice::<serde_json::Value>(data).unwrap())
+            .find(|value| value["property"] == "top_color")
+            .unwrap();
+        assert!((top_color["value"][2].as_f64().unwrap() - 0xfa as f64 / 255.0).abs() < 1e-12);
+        let (request_type, path, _) = requests.last().unwrap();
+        assert_eq!(request_type, "set_target");
+        assert_eq!(path, "");
+    }
+
+    #[test]
+    fn test_set_triad() {
+        let recorder = Recorder::default();
+        let meshcat = Meshcat::with_transport(recorder.clone());
+        let options = crate::utils::TriadOptions::builder().arrows(true).build();
+        meshcat
+            .set_triad("/frame", Isometry3::translation(1.0, 0.0, 0.0), &options)
+            .unwrap();
+        let requests = recorder
+            .take()
+            .into_iter()
+            .map(|(request_type, path, _)| format!("{} {}", request_type, path))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            requests,
+            [
+                "delete /frame",
+                "set_object /frame/x",
+                "set_object /frame/y",
+                "set_object /frame/z",
+                "set_transform /frame"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_scoped_meshcat() {
+        let recorder = Recorder::default();
+        let meshcat = Meshcat::with_transport(recorder.clone());
+        let robot = meshcat.at("/robot1/").unwrap();
+        robot
+            .set_object("/base", crate::utils::triad(Isometry3::identity()))
+            .unwrap();
+        robot
+            .at("arm")
+            .unwrap()
+            .set_transform("link3", Isometry3::identity())
+            .unwrap();
+        robot
+            .set_property("", PropertyType::Visible(false))
+            .unwrap();
+        robot.delete("/").unwrap();
+        let paths = recorder
+            .take()
+            .into_iter()
+            .map(|(_, path, _)| path)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            ["/robot1/base", "/robot1/arm/link3", "/robot1", "/robot1"]
+        );
+
+        let root = meshcat.at("/").unwrap();
+        assert_eq!(root.path(""), "/");
+        assert_eq!(root.path("base"), "/base");
+        root.delete("").unwrap();
+        assert!(meshcat.at("robot1").is_err());
+        assert!(meshcat.at("/robot1//arm").is_err());
+    }
+
+    #[test]
+    fn test_invalid_paths() {
+        let recorder = Recorder::default();
+        let meshcat = Meshcat::with_transport(recorder.clone());
+        let object = crate::utils::triad(Isometry3::identity());
+        assert!(meshcat.set_object("robot/arm", object.clone()).is_err());
+        assert!(meshcat.set_object("/robot//arm", object).is_err());
+        assert!(meshcat
+            .set_transform("/robot/", Isometry3::identity())
+            .is_err());
+        assert!(meshcat.batch().delete("").is_err());
+        assert!(recorder.take().is_empty());
+        assert!(!meshcat.exists("/robot"));
+    }
+
+    #[test]
+    fn test_batch() {
+        let recorder = Recorder::default();
+        let meshcat = Meshcat::with_transport(recorder.clone());
+        let mut batch = meshcat.batch();
+        batch
+            .set_object("/a", crate::utils::triad(Isometry3::identity()))
+            .unwrap()
+            .set_transform("/a", Isometry3::translation(1.0, 0.0, 0.0))
+            .unwrap()
+            .set_property("/a", PropertyType::Visible(false))
+            .unwrap()
+            .delete("/b")
+            .unwrap();
+        assert_eq!(batch.len(), 4);
+        // Nothing is sent until the batch is
+        assert!(recorder.take().is_empty());
+        batch.send().unwrap();
+        assert!(batch.is_empty());
+        let requests = recorder.take();
+        let types = requests
+            .iter()
+            .map(|(request_type, path, _)| format!("{} {}", request_type, path))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            types,
+            [
+                "set_object /a",
+                "set_transform /a",
+                "set_property /a",
+                "delete /b"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_set_property_raw() {
+        let recorder = Recorder::default();
+        let meshcat = Meshcat::with_transport(recorder.clone())
+            .with_encoding(crate::transport::Encoding::Json);
+        meshcat
+            .set_property_raw("/Cameras/default/rotated/<object>", "zoom", 2.0)
+            .unwrap();
+        meshcat
+            .set_property_raw("/box", "material.emissive", [1.0, 0.0, 0.0])
+            .unwrap();
+        assert!(meshcat.set_property_raw("/box", "material.", 1).is_err());
+        let requests = recorder.take();
+        assert_eq!(requests.len(), 2);
+        let value: serde_json::Value = serde_json::from_slice(&requests[0].2).unwrap();
+        assert_eq!(value["property"], "zoom");
+        assert_eq!(value["value"], 2.0);
+        let value: serde_json::Value = serde_json::from_slice(&requests[1].2).unwrap();
+        assert_eq!(value["value"], serde_json::json!([1.0, 0.0, 0.0]));
+    }
+
+    #[test]
+    fn test_bounding_box() {
+        let lumped_object = LumpedObject::builder()
+            .geometries(vec![
+                Geometry::new(GeometryType::Box {
+                    width: 1.0,
+                    height: 2.0,
+                    depth: 3.0,
+                }),
+                // Cylinders are rotated to have their long axis in z
+                Geometry::new_with_origin(
+                    GeometryType::Cylinder {
+                        radius_top: 0.5,
+                        radius_bottom: 0.5,
+                        height: 4.0,
+                        radial_segments: 32,
+                        height_segments: 1,
+                        theta_start: 0.0,
+                        theta_length: 2.0 * std::f64::consts::PI,
+                    },
+                    Isometry3::translation(1.0, 0.0, 0.0),
+                ),
+            ])
+            .object(Object::new(
+                Isometry3::translation(0.0, 0.0, 1.0),
+                ObjectType::Mesh,
+            ))
+            .build();
+        let bounding_box = lumped_object.bounding_box().unwrap();
+        assert!(bounding_box
+            .min
+            .coords
+            .relative_eq(&Vector3::new(-0.5, -1.0, -1.0), 1e-9, 1e-9));
+        assert!(bounding_box
+            .max
+            .coords
+            .relative_eq(&Vector3::new(1.5, 1.0, 3.0), 1e-9, 1e-9));
+
+        let mesh = crate::utils::load_mesh("examples/data/mesh_0_convex_piece_0.obj").unwrap();
+        let bounding_box = mesh.bounding_box().unwrap();
+        assert!(bounding_box.min < bounding_box.max);
+        let mesh = crate::utils::load_mesh("examples/data/mesh_0_convex_piece_0.dae").unwrap();
+        assert!(mesh.bounding_box().is_none());
+        assert!(LumpedObject::builder()
+            .geometries(vec![])
+            .build()
+            .bounding_box()
+            .is_none());
+    }
+
+    #[test]
+    fn test_stl_meshes() {
+        let load = |path| match crate::utils::load_mesh(path).unwrap() {
+            GeometryType::Mesh { format, data } => (format, data),
+            geometry => panic!("Expected a mesh, got {:?}", geometry),
+        };
+        let (format, ascii) = load("examples/data/mesh_0_convex_piece_0.stl");
+        assert_eq!(format, "stl");
+        assert!(matches!(ascii, MeshData::Binary(_)));
+        assert!(!ascii.is_binary_stl());
+        assert!(!mesh_vertices(&format, &ascii).is_empty());
+
+        let (format, binary) = load("examples/data/panda_description/meshes/collision/finger.stl");
+        assert!(binary.is_binary_stl());
+        let vertices = mesh_vertices(&format, &binary);
+        assert_eq!(vertices.len(), 96);
+        let bounding_box = BoundingBox::from_points(&vertices).unwrap();
+        assert!(bounding_box.max.coords.relative_eq(
+            &Vector3::new(0.010494818, 0.026403382, 0.053849034),
+            1e-6,
+            1e-6
+        ));
+
+        let (_, obj) = load("examples/data/mesh_0_convex_piece_0.obj");
+        assert!(matches!(obj, MeshData::Text(_)));
+        // The bytes are sent as a msgpack bin
+        assert_eq!(
+            rmp_serde::to_vec(&MeshData::Binary(vec![1, 2])).unwrap(),
+            [0xc4, 2, 1, 2]
+        );
+    }
+
+    #[test]
+    fn test_urdf_mesh_scale() {
+        let mesh = urdf_rs::Geometry::Mesh {
+            filename: format!(
+                "file://{}/examples/data/mesh_0_convex_piece_0.stl",
+                env!("CARGO_MANIFEST_DIR")
+            ),
+            scale: Some(urdf_rs::Vec3([0.001, 0.001, 0.002])),
+        };
+        let geometries = urdf_geometries(&mesh, Isometry3::identity()).unwrap();
+        assert_eq!(geometries[0].scale, Vector3::new(0.001, 0.001, 0.002));
+        let object = LumpedObject::builder().geometries(geometries).build();
+        let value = serde_json::to_value(&object).unwrap();
+        let matrix = &value["object"]["children"][0]["matrix"];
+        assert_eq!(
+            (&matrix[0], &matrix[5], &matrix[10]),
+            (&0.001.into(), &0.001.into(), &0.002.into())
+        );
+    }
+
+    #[test]
+    fn test_urdf_capsule() {
+        let capsule = urdf_rs::Geometry::Capsule {
+            radius: 0.5,
+            length: 2.0,
+        };
+        let origin = Isometry3::translation(1.0, 0.0, 0.0);
+        let geometries = urdf_geometries(&capsule, origin).unwrap();
+        assert_eq!(geometries.len(), 3);
+        assert!(matches!(
+            geometries[0].geometry,
+            GeometryType::Cylinder { height: 2.0, .. }
+        ));
+        assert_eq!(geometries[2].origin, Isometry3::translation(1.0, 0.0, -1.0));
+        let object = LumpedObject::builder().geometries(geometries).build();
+        let bounding_box = object.bounding_box().unwrap();
+        assert!(bounding_box
+            .min
+            .coords
+            .relative_eq(&Vector3::new(0.5, -0.5, -1.5), 1e-9, 1e-9));
+        assert!(bounding_box
+            .max
+            .coords
+            .relative_eq(&Vector3::new(1.5, 0.5, 1.5), 1e-9, 1e-9));
+
+        let sphere = urdf_rs::Geometry::Sphere { radius: 1.0 };
+        assert_eq!(urdf_geometries(&sphere, origin).unwrap().len(), 1);
+        assert!(matches!(
+            GeometryType::try_from(&capsule),
+            Err(crate::Error::UnsupportedGeometry(_))
+        ));
+
+        let mesh = urdf_rs::Geometry::Mesh {
+            filename: "package://missing/mesh.stl".to_string(),
+            scale: None,
+        };
+        assert!(matches!(
+            GeometryType::try_from(&mesh),
+            Err(crate::Error::PackageNotFound { .. })
+        ));
+        let mesh = urdf_rs::Geometry::Mesh {
+            filename: "/missing/mesh.stl".to_string(),
+            scale: None,
+        };
+        assert!(matches!(
+            GeometryType::try_from(&mesh),
+            Err(crate::Error::Io { .. })
+        ));
+
+        let visual = urdf_rs::Visual {
+            name: None,
+            origin: urdf_rs::Pose {
+                xyz: urdf_rs::Vec3([1.0, 0.0, 0.0]),
+                rpy: urdf_rs::Vec3([0.0, 0.0, 0.0]),
+            },
+            geometry: capsule,
+            material: None,
+        };
+        let geometries = urdf_visual_geometries(&visual).unwrap();
+        assert_eq!(geometries.len(), 3);
+        assert_eq!(geometries[0].origin, origin);
+    }
+
+    #[test]
+    fn test_send_raw() {
+        let recorder = Recorder::default();
+        let meshcat = Meshcat::with_transport(recorder.clone());
+        let data = rmp_serde::to_vec_named(&serde_json::json!({
+            "type": "set_control",
+            "path": "",
+            "name": "x",
+        }))
+        .unwrap();
+        meshcat.send_raw("set_control", "", &data).unwrap();
+        assert_eq!(
+            recorder.take(),
+            [("set_control".to_string(), String::new(), data)]
+        );
+    }
+
+    #[test]
+    fn test_json_encoding() {
+        let recorder = Recorder::default();
+        let meshcat = Meshcat::with_transport(recorder.clone())
+            .with_encoding(crate::transport::Encoding::Json);
+        meshcat
+            .set_transform("/box", Isometry3::translation(1.0, 2.0, 3.0))
+            .unwrap();
+        meshcat.delete("/box").unwrap();
+        let requests = recorder.take();
+        let transform: serde_json::Value = serde_json::from_slice(&requests[0].2).unwrap();
+        assert_eq!(transform["type"], "set_transform");
+        assert_eq!(transform["matrix"][12], 1.0);
+        let delete: serde_json::Value = serde_json::from_slice(&requests[1].2).unwrap();
+        assert_eq!(
+            delete,
+            serde_json::json!({"type": "delete", "path": "/box"})
+        );
+    }
 }
@@ -0,0 +1,220 @@
+use nalgebra::{Isometry3, UnitQuaternion, Vector3};
+use serde::{Serialize, Serializer};
+use typed_builder::TypedBuilder;
+
+// https://threejs.org/docs/index.html#api/en/animation/KeyframeTrack
+// The keys are (time, value) with the time in frames (See AnimationClip::fps)
+#[derive(Clone, Debug, PartialEq)]
+pub enum KeyframeTrack {
+    Position(Vec<(f64, Vector3<f64>)>),
+    Quaternion(Vec<(f64, UnitQuaternion<f64>)>),
+    Scale(Vec<(f64, Vector3<f64>)>),
+    // A number property of the object, e.g. "material.opacity"
+    Number {
+        property: String,
+        keys: Vec<(f64, f64)>,
+    },
+}
+
+impl KeyframeTrack {
+    // The position and quaternion tracks following the poses, e.g. of a trajectory
+    pub fn poses(keys: &[(f64, Isometry3<f64>)]) -> [KeyframeTrack; 2] {
+        [
+            KeyframeTrack::Position(
+                keys.iter()
+                    .map(|(time, pose)| (*time, pose.translation.vector))
+                    .collect(),
+            ),
+            KeyframeTrack::Quaternion(
+                keys.iter()
+                    .map(|(time, pose)| (*time, pose.rotation))
+                    .collect(),
+            ),
+        ]
+    }
+}
+
+#[derive(Serialize)]
+struct Key<T> {
+    time: f64,
+    value: T,
+}
+
+#[derive(Serialize)]
+struct Track<'a, T> {
+    // The three.js property, relative to the animated object
+    name: String,
+    #[serde(rename = "type")]
+    track_type: &'a str,
+    keys: Vec<Key<T>>,
+}
+
+fn track<'a, T, U>(
+    name: &str,
+    track_type: &'a str,
+    keys: &[(f64, T)],
+    value: impl Fn(&T) -> U,
+) -> Track<'a, U> {
+    Track {
+        name: format!(".{}", name),
+        track_type,
+        keys: keys
+            .iter()
+            .map(|(time, key)| Key {
+                time: *time,
+                value: value(key),
+            })
+            .collect(),
+    }
+}
+
+impl Serialize for KeyframeTrack {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let vector = |vector: &Vector3<f64>| [vector.x, vector.y, vector.z];
+        match self {
+            KeyframeTrack::Position(keys) => {
+                track("position", "vector3", keys, vector).serialize(serializer)
+            }
+            // three.js quaternions are [x, y, z, w] like nalgebra's coordinates
+            KeyframeTrack::Quaternion(keys) => {
+                track("quaternion", "quaternion", keys, |rotation| {
+                    let coords = rotation.coords;
+                    [coords.x, coords.y, coords.z, coords.w]
+                })
+                .serialize(serializer)
+            }
+            KeyframeTrack::Scale(keys) => {
+                track("scale", "vector3", keys, vector).serialize(serializer)
+            }
+            KeyframeTrack::Number { property, keys } => {
+                track(property, "number", keys, |value| *value).serialize(serializer)
+            }
+        }
+    }
+}
+
+// https://threejs.org/docs/index.html#api/en/animation/AnimationClip
+#[derive(Clone, Debug, TypedBuilder, Serialize)]
+pub struct AnimationClip {
+    #[builder(default = "default".to_string(), setter(into))]
+    pub name: String,
+    // The number of frames per second, the times of the keys are in frames
+    #[builder(default = 30.0)]
+    pub fps: f64,
+    #[builder(default)]
+    pub tracks: Vec<KeyframeTrack>,
+    // The animated object relative to the path of Meshcat::set_animation, "" for the object at
+    // the path
+    #[builder(default, setter(into))]
+    #[serde(skip)]
+    pub path: String,
+}
+
+#[derive(Clone, Debug, TypedBuilder, Serialize)]
+pub struct AnimationOptions {
+    // Starts playing once loaded, otherwise the animation waits for the play button of the
+    // viewer's Animations controls
+    #[builder(default = true)]
+    pub play: bool,
+    #[builder(default = 1)]
+    pub repetitions: u32,
+}
+
+impl Default for AnimationOptions {
+    fn default() -> Self {
+        AnimationOptions::builder().build()
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct Animation<'a> {
+    path: String,
+    clip: &'a AnimationClip,
+}
+
+// Replaces the animation of the viewer with the clips
+// https://github.com/meshcat-dev/meshcat#api (set_animation)
+#[derive(Debug, Serialize)]
+pub struct SetAnimationData<'a> {
+    animations: Vec<Animation<'a>>,
+    options: &'a AnimationOptions,
+    pub path: &'a str,
+    #[serde(rename = "type")]
+    pub request_type: &'a str,
+}
+
+impl<'a> SetAnimationData<'a> {
+    pub fn new(path: &'a str, clips: &'a [AnimationClip], options: &'a AnimationOptions) -> Self {
+        SetAnimationData {
+            animations: clips
+                .iter()
+                .map(|clip| Animation {
+                    path: match clip.path.trim_matches('/') {
+                        "" => path.to_string(),
+                        child => format!("{}/{}", path.trim_end_matches('/'), child),
+                    },
+                    clip,
+                })
+                .collect(),
+            options,
+            path,
+            request_type: "set_animation",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_animation_data() {
+        let poses = [
+            (0.0, Isometry3::identity()),
+            (30.0, Isometry3::translation(1.0, 2.0, 3.0)),
+        ];
+        let opacity = KeyframeTrack::Number {
+            property: "material.opacity".to_string(),
+            keys: vec![(0.0, 1.0), (30.0, 0.0)],
+        };
+        let clips = [
+            AnimationClip::builder()
+                .tracks(KeyframeTrack::poses(&poses).to_vec())
+                .build(),
+            AnimationClip::builder()
+                .tracks(vec![opacity])
+                .path("gripper")
+                .build(),
+        ];
+        let options = AnimationOptions::default();
+        let data =
+            serde_json::to_value(SetAnimationData::new("/robot/", &clips, &options)).unwrap();
+        assert_eq!(data["type"], "set_animation");
+        assert_eq!(
+            data["options"],
+            serde_json::json!({"play": true, "repetitions": 1})
+        );
+        let animations = data["animations"].as_array().unwrap();
+        assert_eq!(animations[0]["path"], "/robot/");
+        assert_eq!(animations[1]["path"], "/robot/gripper");
+        let tracks = &animations[0]["clip"]["tracks"];
+        assert_eq!(animations[0]["clip"]["fps"], 30.0);
+        assert_eq!(tracks[0]["name"], ".position");
+        assert_eq!(tracks[0]["type"], "vector3");
+        assert_eq!(
+            tracks[0]["keys"][1],
+            serde_json::json!({"time": 30.0, "value": [1.0, 2.0, 3.0]})
+        );
+        assert_eq!(tracks[1]["type"], "quaternion");
+        assert_eq!(
+            tracks[1]["keys"][0]["value"],
+            serde_json::json!([0.0, 0.0, 0.0, 1.0])
+        );
+        let track = &animations[1]["clip"]["tracks"][0];
+        assert_eq!(track["name"], ".material.opacity");
+        assert_eq!(track["type"], "number");
+    }
+}
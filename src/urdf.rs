@@ -0,0 +1,578 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use itertools::Itertools;
+use nalgebra::{Isometry3, Translation3, Unit, UnitQuaternion, Vector3};
+
+use super::types::*;
+
+pub(crate) fn isometry(pose: &urdf_rs::Pose) -> Isometry3<f64> {
+    Isometry3::from_parts(
+        Translation3::new(pose.xyz[0], pose.xyz[1], pose.xyz[2]),
+        UnitQuaternion::from_euler_angles(pose.rpy[0], pose.rpy[1], pose.rpy[2]),
+    )
+}
+
+// The transform from the joint's parent link to its child link for the given joint position
+// (radians for revolute joints, meters for prismatic ones)
+pub fn joint_transform(
+    joint: &urdf_rs::Joint,
+    position: f64,
+) -> Result<Isometry3<f64>, Box<dyn Error>> {
+    let axis = Unit::try_new(Vector3::from(*joint.axis.xyz), f64::EPSILON)
+        .ok_or_else(|| format!("Joint '{}' has a zero axis", joint.name))?;
+    let motion = match joint.joint_type {
+        urdf_rs::JointType::Revolute | urdf_rs::JointType::Continuous => Isometry3::from_parts(
+            Translation3::identity(),
+            UnitQuaternion::from_axis_angle(&axis, position),
+        ),
+        urdf_rs::JointType::Prismatic => Isometry3::from_parts(
+            Translation3::from(axis.into_inner() * position),
+            UnitQuaternion::identity(),
+        ),
+        _ => {
+            return Err(format!(
+                "Joint '{}' of type {:?} can't be set from a single position",
+                joint.name, joint.joint_type
+            )
+            .into())
+        }
+    };
+    Ok(isometry(&joint.origin) * motion)
+}
+
+// The directories package:// uris are looked up in, from ROS_PACKAGE_PATH
+pub fn ros_package_paths() -> Vec<PathBuf> {
+    std::env::var_os("ROS_PACKAGE_PATH")
+        .map(|paths| std::env::split_paths(&paths).collect())
+        .unwrap_or_default()
+}
+
+// A package is a directory named like it in one of the package paths, or below one of them
+// with a package.xml (like in ROS workspaces)
+fn find_package(name: &str, directory: &Path) -> Option<PathBuf> {
+    let package = directory.join(name);
+    if package.is_dir() {
+        return Some(package);
+    }
+    find_package_below(name, directory)
+}
+
+fn find_package_below(name: &str, directory: &Path) -> Option<PathBuf> {
+    if directory.join("package.xml").is_file() {
+        // Packages aren't nested
+        return directory
+            .file_name()
+            .is_some_and(|file_name| file_name == name)
+            .then(|| directory.to_path_buf());
+    }
+    std::fs::read_dir(directory)
+        .ok()?
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.is_dir()
+                && !path
+                    .file_name()
+                    .is_some_and(|file_name| file_name.to_string_lossy().starts_with('.'))
+        })
+        .find_map(|path| find_package_below(name, &path))
+}
+
+// The file a mesh uri of a URDF refers to, package://name/file is looked up in the package
+// paths (See ros_package_paths), file:// is removed and other paths are kept as is
+pub fn resolve_uri(uri: &str, package_paths: &[PathBuf]) -> Result<String, crate::Error> {
+    if let Some(path) = uri.strip_prefix("file://") {
+        return Ok(path.to_string());
+    }
+    let Some(path) = uri.strip_prefix("package://") else {
+        return Ok(uri.to_string());
+    };
+    let (name, file) = path.split_once('/').unwrap_or((path, ""));
+    let package = package_paths
+        .iter()
+        .find_map(|directory| find_package(name, directory))
+        .ok_or_else(|| crate::Error::PackageNotFound {
+            package: name.to_string(),
+            uri: uri.to_string(),
+            package_paths: package_paths.to_vec(),
+        })?;
+    Ok(package.join(file).to_string_lossy().to_string())
+}
+
+// Runs the xacro preprocessor (from ROS) on the file, args are passed as name:=value
+pub fn expand_xacro(path: &str, args: &[(&str, &str)]) -> Result<String, Box<dyn Error>> {
+    let output = Command::new("xacro")
+        .arg(path)
+        .args(
+            args.iter()
+                .map(|(name, value)| format!("{}:={}", name, value)),
+        )
+        .output()
+        .map_err(|err| format!("Failed to run xacro on '{}': {}", path, err))?;
+    if !output.status.success() {
+        return Err(format!(
+            "xacro failed on '{}': {}",
+            path,
+            String::from_utf8_lossy(&output.stderr)
+        )
+        .into());
+    }
+    Ok(String::from_utf8(output.stdout)?)
+}
+
+// The joints from the link up to the root link, fails if the joints form a cycle
+fn parent_chain<'a>(
+    joints: &'a [urdf_rs::Joint],
+    link: &'a str,
+) -> Result<Vec<&'a urdf_rs::Joint>, Box<dyn Error>> {
+    let mut chain = Vec::new();
+    let mut link_name = link;
+    let mut visited = std::collections::HashSet::from([link]);
+    while let Some(joint) = joints.iter().find(|joint| joint.child.link == link_name) {
+        chain.push(joint);
+        link_name = &joint.parent.link;
+        if !visited.insert(link_name) {
+            return Err(format!(
+                "The joints of link '{}' form a cycle through '{}'",
+                link, link_name
+            )
+            .into());
+        }
+    }
+    Ok(chain)
+}
+
+// Publishes a URDF to meshcat, each link is published under the path of its parent joint
+// which is under the path of its parent link, e.g. /base_link/joint1/link1/joint2/link2
+pub struct UrdfVisualizer {
+    robot: urdf_rs::Robot,
+    link_paths: HashMap<String, String>,
+    joint_paths: HashMap<String, String>,
+    // Where the package:// uris of the meshes are looked up (See resolve_uri)
+    package_paths: Vec<PathBuf>,
+}
+
+impl UrdfVisualizer {
+    // Fails if the joints form a cycle
+    pub fn new(robot: urdf_rs::Robot) -> Result<Self, Box<dyn Error>> {
+        let link_paths = robot
+            .links
+            .iter()
+            .map(|link| {
+                let mut path = String::new();
+                let mut link_name = link.name.as_str();
+                for joint in parent_chain(&robot.joints, &link.name)? {
+                    path = format!("/{}/{}{}", joint.name, link_name, path);
+                    link_name = &joint.parent.link;
+                }
+                Ok((link.name.clone(), format!("/{}{}", link_name, path)))
+            })
+            .collect::<Result<HashMap<_, _>, Box<dyn Error>>>()?;
+        let joint_paths = robot
+            .joints
+            .iter()
+            .map(|joint| {
+                let parent_path = link_paths
+                    .get(&joint.parent.link)
+                    .cloned()
+                    .unwrap_or_else(|| format!("/{}", joint.parent.link));
+                (
+                    joint.name.clone(),
+                    format!("{}/{}", parent_path, joint.name),
+                )
+            })
+            .collect();
+        Ok(Self {
+            robot,
+            link_paths,
+            joint_paths,
+            package_paths: ros_package_paths(),
+        })
+    }
+
+    // Replaces the package paths from ROS_PACKAGE_PATH
+    pub fn with_package_paths(mut self, package_paths: Vec<PathBuf>) -> Self {
+        self.package_paths = package_paths;
+        self
+    }
+
+    // The geometry with the uri of its mesh (if any) resolved
+    fn resolve(&self, geometry: &urdf_rs::Geometry) -> Result<urdf_rs::Geometry, Box<dyn Error>> {
+        Ok(match geometry {
+            urdf_rs::Geometry::Mesh { filename, scale } => urdf_rs::Geometry::Mesh {
+                filename: resolve_uri(filename, &self.package_paths)?,
+                scale: *scale,
+            },
+            _ => geometry.clone(),
+        })
+    }
+
+    // Reads a .urdf or a .xacro (See expand_xacro) file
+    pub fn from_file(path: &str) -> Result<Self, Box<dyn Error>> {
+        if path.ends_with(".xacro") {
+            return Self::from_xacro(path, &[]);
+        }
+        Self::new(urdf_rs::read_file(path)?)
+    }
+
+    pub fn from_string(xml: &str) -> Result<Self, Box<dyn Error>> {
+        Self::new(urdf_rs::read_from_string(xml)?)
+    }
+
+    pub fn from_xacro(path: &str, args: &[(&str, &str)]) -> Result<Self, Box<dyn Error>> {
+        Self::from_string(&expand_xacro(path, args)?)
+    }
+
+    pub fn robot(&self) -> &urdf_rs::Robot {
+        &self.robot
+    }
+
+    pub fn link_path(&self, link: &str) -> Option<&str> {
+        self.link_paths.get(link).map(String::as_str)
+    }
+
+    pub fn joint_path(&self, joint: &str) -> Option<&str> {
+        self.joint_paths.get(joint).map(String::as_str)
+    }
+
+    // Adds the positions of the joints mimicking the given ones (multiplier * position + offset),
+    // positions given explicitly for mimic joints are kept as is
+    pub fn with_mimic_joints(&self, positions: &HashMap<String, f64>) -> HashMap<String, f64> {
+        let mut positions = positions.clone();
+        // Repeat until nothing changes to handle joints mimicking mimic joints
+        loop {
+            let mimic_positions = self
+                .robot
+                .joints
+                .iter()
+                .filter(|joint| !positions.contains_key(&joint.name))
+                .filter_map(|joint| {
+                    let mimic = joint.mimic.as_ref()?;
+                    let position = positions.get(&mimic.joint)?;
+                    Some((
+                        joint.name.clone(),
+                        mimic.multiplier.unwrap_or(1.0) * position + mimic.offset.unwrap_or(0.0),
+                    ))
+                })
+                .collect_vec();
+            if mimic_positions.is_empty() {
+                return positions;
+            }
+            positions.extend(mimic_positions);
+        }
+    }
+
+    // Only sends the transforms of the given joints and the joints mimicking them, the links
+    // below them follow since they're nested under the joints' paths
+    pub fn set_joint_positions(
+        &self,
+        meshcat: &Meshcat,
+        positions: &HashMap<String, f64>,
+    ) -> Result<(), Box<dyn Error>> {
+        let transforms = self
+            .with_mimic_joints(positions)
+            .iter()
+            .map(|(name, position)| {
+                let joint = self
+                    .robot
+                    .joints
+                    .iter()
+                    .find(|joint| &joint.name == name)
+                    .ok_or_else(|| format!("Unknown joint '{}'", name))?;
+                Ok((
+                    self.joint_paths[name].as_str(),
+                    joint_transform(joint, *position)?,
+                ))
+            })
+            .collect::<Result<Vec<_>, Box<dyn Error>>>()?;
+        for (path, transform) in transforms {
+            meshcat.set_transform(path, transform)?;
+        }
+        Ok(())
+    }
+
+    // The poses of the links in the frame of the root link (forward kinematics) for the given
+    // joint positions and the joints mimicking them, the other joints are at their origin
+    pub fn link_poses(
+        &self,
+        positions: &HashMap<String, f64>,
+    ) -> Result<HashMap<String, Isometry3<f64>>, Box<dyn Error>> {
+        let positions = self.with_mimic_joints(positions);
+        if let Some(name) = positions
+            .keys()
+            .find(|name| !self.joint_paths.contains_key(*name))
+        {
+            return Err(format!("Unknown joint '{}'", name).into());
+        }
+        self.robot
+            .links
+            .iter()
+            .map(|link| {
+                let mut pose = Isometry3::identity();
+                for joint in parent_chain(&self.robot.joints, &link.name)? {
+                    let transform = match positions.get(&joint.name) {
+                        Some(position) => joint_transform(joint, *position)?,
+                        None => isometry(&joint.origin),
+                    };
+                    pose = transform * pose;
+                }
+                Ok((link.name.clone(), pose))
+            })
+            .collect()
+    }
+
+    // Deletes any previously published version of the robot, then publishes the visual
+    // geometries of all the links and the origins of all the joints
+    pub fn load(&self, meshcat: &Meshcat) -> Result<(), Box<dyn Error>> {
+        for path in self.link_paths.values().chain(self.joint_paths.values()) {
+            meshcat.delete(path)?;
+        }
+        for link in &self.robot.links {
+            if !link.visual.is_empty() {
+                let mut geometries = Vec::new();
+                for visual in &link.visual {
+                    geometries.extend(urdf_geometries(
+                        &self.resolve(&visual.geometry)?,
+                        isometry(&visual.origin),
+                    )?);
+                }
+                meshcat.set_object(
+                    &self.link_paths[&link.name],
+                    LumpedObject::builder().geometries(geometries).build(),
+                )?;
+            }
+        }
+        for joint in &self.robot.joints {
+            meshcat.set_transform(&self.joint_paths[&joint.name], isometry(&joint.origin))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const URDF: &str = r#"
+        <robot name="robot">
+          <link name="link2"/>
+          <link name="base_link"/>
+          <link name="link1"/>
+          <joint name="joint2" type="fixed">
+            <parent link="link1"/>
+            <child link="link2"/>
+          </joint>
+          <joint name="joint1" type="revolute">
+            <parent link="base_link"/>
+            <child link="link1"/>
+            <axis xyz="0 0 1"/>
+            <limit lower="-1" upper="1" effort="1" velocity="1"/>
+          </joint>
+        </robot>
+    "#;
+
+    #[test]
+    fn test_from_string() {
+        let visualizer = UrdfVisualizer::from_string(URDF).unwrap();
+        assert_eq!(visualizer.robot().name, "robot");
+        assert_eq!(visualizer.link_path("base_link"), Some("/base_link"));
+        assert_eq!(
+            visualizer.link_path("link1"),
+            Some("/base_link/joint1/link1")
+        );
+        // Joints listed before their parent joint still get the full path
+        assert_eq!(
+            visualizer.link_path("link2"),
+            Some("/base_link/joint1/link1/joint2/link2")
+        );
+        assert_eq!(
+            visualizer.joint_path("joint2"),
+            Some("/base_link/joint1/link1/joint2")
+        );
+        assert!(visualizer.link_path("link3").is_none());
+        assert!(UrdfVisualizer::from_string("<robot>").is_err());
+        let cycle = UrdfVisualizer::from_string(
+            r#"
+            <robot name="cycle">
+              <link name="a"/>
+              <link name="b"/>
+              <joint name="ab" type="fixed">
+                <parent link="a"/>
+                <child link="b"/>
+              </joint>
+              <joint name="ba" type="fixed">
+                <parent link="b"/>
+                <child link="a"/>
+              </joint>
+            </robot>
+            "#,
+        );
+        let Err(err) = cycle else {
+            panic!("Expected the cycle to be rejected");
+        };
+        assert!(err.to_string().contains("cycle"));
+    }
+
+    #[test]
+    fn test_mimic_joints() {
+        let visualizer = UrdfVisualizer::from_string(
+            r#"
+            <robot name="gripper">
+              <link name="hand"/>
+              <link name="left_finger"/>
+              <link name="right_finger"/>
+              <link name="tip"/>
+              <joint name="left" type="prismatic">
+                <parent link="hand"/>
+                <child link="left_finger"/>
+                <limit lower="0" upper="0.04" effort="1" velocity="1"/>
+              </joint>
+              <joint name="right" type="prismatic">
+                <parent link="hand"/>
+                <child link="right_finger"/>
+                <limit lower="0" upper="0.04" effort="1" velocity="1"/>
+                <mimic joint="left" multiplier="-1" offset="0.01"/>
+              </joint>
+              <joint name="tip" type="prismatic">
+                <parent link="right_finger"/>
+                <child link="tip"/>
+                <limit lower="0" upper="0.04" effort="1" velocity="1"/>
+                <mimic joint="right"/>
+              </joint>
+            </robot>
+            "#,
+        )
+        .unwrap();
+        let positions = visualizer.with_mimic_joints(&HashMap::from([("left".to_string(), 0.02)]));
+        assert_eq!(positions.len(), 3);
+        assert!((positions["right"] + 0.01).abs() < 1e-9);
+        assert!((positions["tip"] + 0.01).abs() < 1e-9);
+        let positions = visualizer.with_mimic_joints(&HashMap::from([
+            ("left".to_string(), 0.02),
+            ("right".to_string(), 0.03),
+        ]));
+        assert_eq!(positions["right"], 0.03);
+        assert_eq!(positions["tip"], 0.03);
+        assert!(visualizer.with_mimic_joints(&HashMap::new()).is_empty());
+    }
+
+    #[test]
+    fn test_joint_transform() {
+        let visualizer = UrdfVisualizer::from_file("examples/data/sample.urdf").unwrap();
+        let robot = visualizer.robot();
+        for joint in &robot.joints {
+            let transform = joint_transform(joint, 0.0);
+            match joint.joint_type {
+                urdf_rs::JointType::Fixed => assert!(transform.is_err()),
+                _ => assert!(transform.unwrap().translation.vector.relative_eq(
+                    &Vector3::from(*joint.origin.xyz),
+                    1e-9,
+                    1e-9
+                )),
+            }
+        }
+
+        let mut joint = robot.joints[0].clone();
+        joint.origin = urdf_rs::Pose::default();
+        joint.axis.xyz = urdf_rs::Vec3([0.0, 0.0, 2.0]);
+        joint.joint_type = urdf_rs::JointType::Revolute;
+        let transform = joint_transform(&joint, std::f64::consts::FRAC_PI_2).unwrap();
+        assert!((transform * Vector3::x()).relative_eq(&Vector3::y(), 1e-9, 1e-9));
+        joint.joint_type = urdf_rs::JointType::Prismatic;
+        let transform = joint_transform(&joint, 0.5).unwrap();
+        assert!(transform
+            .translation
+            .vector
+            .relative_eq(&Vector3::new(0.0, 0.0, 0.5), 1e-9, 1e-9));
+        joint.axis.xyz = urdf_rs::Vec3([0.0, 0.0, 0.0]);
+        assert!(joint_transform(&joint, 0.5).is_err());
+    }
+
+    #[test]
+    fn test_link_poses() {
+        let visualizer = UrdfVisualizer::from_string(
+            r#"
+            <robot name="arm">
+              <link name="base"/>
+              <link name="upper"/>
+              <link name="lower"/>
+              <joint name="shoulder" type="revolute">
+                <origin xyz="0 0 1"/>
+                <parent link="base"/>
+                <child link="upper"/>
+                <axis xyz="0 0 1"/>
+                <limit lower="-3" upper="3" effort="1" velocity="1"/>
+              </joint>
+              <joint name="elbow" type="fixed">
+                <origin xyz="1 0 0"/>
+                <parent link="upper"/>
+                <child link="lower"/>
+              </joint>
+            </robot>
+            "#,
+        )
+        .unwrap();
+        let poses = visualizer
+            .link_poses(&HashMap::from([(
+                "shoulder".to_string(),
+                std::f64::consts::FRAC_PI_2,
+            )]))
+            .unwrap();
+        assert_eq!(poses["base"], Isometry3::identity());
+        assert!(poses["lower"].translation.vector.relative_eq(
+            &Vector3::new(0.0, 1.0, 1.0),
+            1e-9,
+            1e-9
+        ));
+        let poses = visualizer.link_poses(&HashMap::new()).unwrap();
+        assert!(poses["lower"].translation.vector.relative_eq(
+            &Vector3::new(1.0, 0.0, 1.0),
+            1e-9,
+            1e-9
+        ));
+        assert!(visualizer
+            .link_poses(&HashMap::from([("wrist".to_string(), 0.0)]))
+            .is_err());
+    }
+
+    #[test]
+    fn test_resolve_uri() {
+        let directory =
+            std::env::temp_dir().join(format!("meshcat-urdf-{}", crate::types::new_uuid()));
+        let nested = directory.join("src/robots/arm_description");
+        std::fs::create_dir_all(&nested).unwrap();
+        std::fs::create_dir_all(directory.join("gripper")).unwrap();
+        std::fs::write(nested.join("package.xml"), "<package/>").unwrap();
+        let package_paths = [directory.clone()];
+        assert_eq!(
+            resolve_uri("package://gripper/meshes/hand.stl", &package_paths).unwrap(),
+            directory.join("gripper/meshes/hand.stl").to_string_lossy()
+        );
+        assert_eq!(
+            resolve_uri("package://arm_description/meshes/link.dae", &package_paths).unwrap(),
+            nested.join("meshes/link.dae").to_string_lossy()
+        );
+        assert!(matches!(
+            resolve_uri("package://missing/mesh.obj", &package_paths),
+            Err(crate::Error::PackageNotFound { .. })
+        ));
+        assert_eq!(
+            resolve_uri("file:///meshes/mesh.obj", &package_paths).unwrap(),
+            "/meshes/mesh.obj"
+        );
+        assert_eq!(
+            resolve_uri("meshes/mesh.obj", &package_paths).unwrap(),
+            "meshes/mesh.obj"
+        );
+    }
+
+    #[test]
+    fn test_from_file() {
+        let visualizer = UrdfVisualizer::from_file("examples/data/sample.urdf").unwrap();
+        assert_eq!(visualizer.link_path("root"), Some("/root"));
+        assert_eq!(visualizer.robot().links.len(), visualizer.link_paths.len());
+    }
+}
@@ -1,6 +1,10 @@
-use nalgebra::{Isometry3, Matrix3xX, Vector3};
+use nalgebra::{
+    DMatrix, Isometry3, Matrix3, Matrix3xX, Rotation3, Translation3, UnitQuaternion, Vector3,
+};
+use typed_builder::TypedBuilder;
 
 use super::types::*;
+use std::collections::BTreeMap;
 use std::error::Error;
 
 pub fn file_extension(path: &str) -> Result<&str, Box<dyn Error>> {
@@ -12,19 +16,271 @@ pub fn file_extension(path: &str) -> Result<&str, Box<dyn Error>> {
 }
 
 // TODO: https://github.com/rdeits/MeshCat.jl/blob/master/src/mesh_files.jl
-pub fn load_mesh(path: &str) -> Result<GeometryType, Box<dyn Error>> {
-    Ok(GeometryType::Mesh {
-        format: file_extension(path)?.to_string(),
-        data: std::fs::read_to_string(path)?,
+// The stl files are sent as bytes (See MeshData), the other formats as text unless they aren't
+// valid utf-8. The viewer only loads gltf and glb files as objects, they're rejected (See
+// mesh_file)
+pub fn load_mesh(path: &str) -> Result<GeometryType, crate::Error> {
+    // Rejected before reading the file and its buffers
+    if matches!(file_extension(path), Ok("gltf" | "glb")) {
+        return Err(crate::Error::UnsupportedFormat(path.to_string()));
+    }
+    let (format, data) = mesh_data(path)?;
+    Ok(GeometryType::Mesh { format, data })
+}
+
+// The format and content of a mesh file, gltf and glb files are sent as self contained gltf
+fn mesh_data(path: &str) -> Result<(String, MeshData), crate::Error> {
+    let format =
+        file_extension(path).map_err(|_| crate::Error::UnsupportedFormat(path.to_string()))?;
+    let bytes = std::fs::read(path).map_err(|source| crate::Error::Io {
+        path: path.to_string(),
+        source,
+    })?;
+    let data = match format {
+        "gltf" | "glb" => {
+            return Ok((
+                "gltf".to_string(),
+                MeshData::Text(embedded_gltf(path, &bytes)?),
+            ))
+        }
+        "stl" => MeshData::Binary(bytes),
+        _ => String::from_utf8(bytes)
+            .map(MeshData::Text)
+            .unwrap_or_else(|err| MeshData::Binary(err.into_bytes())),
+    };
+    Ok((format.to_string(), data))
+}
+
+// Reads a file referenced by a glTF file as a data uri
+fn data_uri(path: &std::path::Path, mime_type: &str) -> Result<String, crate::Error> {
+    use base64::Engine;
+    let bytes = std::fs::read(path).map_err(|source| crate::Error::Io {
+        path: path.display().to_string(),
+        source,
+    })?;
+    Ok(format!(
+        "data:{};base64,{}",
+        mime_type,
+        base64::engine::general_purpose::STANDARD.encode(bytes)
+    ))
+}
+
+pub(crate) fn image_mime_type(path: &str) -> Option<&'static str> {
+    match file_extension(path).ok()?.to_lowercase().as_str() {
+        "png" => Some("image/png"),
+        "jpg" | "jpeg" => Some("image/jpeg"),
+        "bmp" => Some("image/bmp"),
+        "gif" => Some("image/gif"),
+        "webp" => Some("image/webp"),
+        "tga" => Some("image/x-tga"),
+        _ => None,
+    }
+}
+
+fn invalid_gltf(path: &str, message: impl Into<String>) -> crate::Error {
+    crate::Error::Io {
+        path: path.to_string(),
+        source: std::io::Error::new(std::io::ErrorKind::InvalidData, message.into()),
+    }
+}
+
+// The JSON and binary chunks of a glb file
+// https://registry.khronos.org/glTF/specs/2.0/glTF-2.0.html#binary-gltf-layout
+fn glb_chunks<'a>(
+    path: &str,
+    bytes: &'a [u8],
+) -> Result<(&'a [u8], Option<&'a [u8]>), crate::Error> {
+    let word = |offset: usize| {
+        bytes
+            .get(offset..offset + 4)
+            .map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]]) as usize)
+    };
+    if bytes.get(0..4) != Some(b"glTF") {
+        return Err(invalid_gltf(path, "Not a glb file"));
+    }
+    let mut chunks = Vec::new();
+    let mut offset = 12;
+    while let (Some(length), Some(chunk_type)) = (word(offset), word(offset + 4)) {
+        let chunk = bytes
+            .get(offset + 8..offset + 8 + length)
+            .ok_or_else(|| invalid_gltf(path, "Truncated chunk"))?;
+        chunks.push((chunk_type, chunk));
+        offset += 8 + length;
+    }
+    const JSON: usize = 0x4E4F534A;
+    const BIN: usize = 0x004E4942;
+    match chunks.as_slice() {
+        [(JSON, json), rest @ ..] => Ok((
+            json,
+            rest.iter()
+                .find(|(chunk_type, _)| *chunk_type == BIN)
+                .map(|(_, bin)| *bin),
+        )),
+        _ => Err(invalid_gltf(path, "The first chunk isn't JSON")),
+    }
+}
+
+// A self contained glTF file (the JSON with its buffers and images embedded as data uris) from a
+// gltf file referencing other files or from a glb file
+fn embedded_gltf(path: &str, bytes: &[u8]) -> Result<String, crate::Error> {
+    let (json, bin) = match file_extension(path) {
+        Ok("glb") => glb_chunks(path, bytes)?,
+        _ => (bytes, None),
+    };
+    let mut gltf = serde_json::from_slice::<serde_json::Value>(json)
+        .map_err(|err| invalid_gltf(path, err.to_string()))?;
+    let directory = std::path::Path::new(path)
+        .parent()
+        .unwrap_or(std::path::Path::new(""));
+    if let Some(buffers) = gltf["buffers"].as_array_mut() {
+        for (index, buffer) in buffers.iter_mut().enumerate() {
+            let uri = match (buffer["uri"].as_str(), bin) {
+                (Some(uri), _) if uri.starts_with("data:") => continue,
+                (Some(uri), _) => data_uri(&directory.join(uri), "application/octet-stream")?,
+                // The first buffer of a glb file without uri is its binary chunk
+                (None, Some(bin)) if index == 0 => {
+                    use base64::Engine;
+                    format!(
+                        "data:application/octet-stream;base64,{}",
+                        base64::engine::general_purpose::STANDARD.encode(bin)
+                    )
+                }
+                (None, _) => return Err(invalid_gltf(path, "A buffer has no uri")),
+            };
+            buffer["uri"] = uri.into();
+        }
+    }
+    if let Some(images) = gltf["images"].as_array_mut() {
+        for image in images {
+            let Some(uri) = image["uri"].as_str() else {
+                // Stored in a buffer view
+                continue;
+            };
+            if uri.starts_with("data:") {
+                continue;
+            }
+            let mime_type = image_mime_type(uri).unwrap_or(
+                image["mimeType"]
+                    .as_str()
+                    .unwrap_or("application/octet-stream"),
+            );
+            image["uri"] = data_uri(&directory.join(uri), mime_type)?.into();
+        }
+    }
+    Ok(gltf.to_string())
+}
+
+// The arguments of the lines starting with the keyword, e.g. the files of the mtllib lines
+fn statements<'a>(text: &'a str, keyword: &'a str) -> impl Iterator<Item = &'a str> {
+    text.lines().filter_map(move |line| {
+        let (first, rest) = line.trim().split_once(char::is_whitespace)?;
+        (first == keyword).then(|| rest.trim())
     })
 }
 
+// The .mtl files of the obj file and the textures they refer to (as data uris)
+fn obj_materials(
+    path: &str,
+    obj: &str,
+) -> Result<(Option<String>, BTreeMap<String, String>), crate::Error> {
+    let directory = std::path::Path::new(path)
+        .parent()
+        .unwrap_or(std::path::Path::new(""));
+    let mut mtl_library: Option<String> = None;
+    let mut resources = BTreeMap::new();
+    for name in statements(obj, "mtllib") {
+        let mtl_path = directory.join(name);
+        let mtl = std::fs::read_to_string(&mtl_path).map_err(|source| crate::Error::Io {
+            path: mtl_path.display().to_string(),
+            source,
+        })?;
+        let mtl_directory = mtl_path.parent().unwrap_or(std::path::Path::new(""));
+        for keyword in [
+            "map_Ka", "map_Kd", "map_Ks", "map_Ke", "map_d", "map_bump", "bump",
+        ] {
+            // The options of the texture come before its file
+            for texture in statements(&mtl, keyword).filter_map(|map| map.split_whitespace().last())
+            {
+                let mime_type = image_mime_type(texture).unwrap_or("application/octet-stream");
+                resources.insert(
+                    texture.to_string(),
+                    data_uri(&mtl_directory.join(texture), mime_type)?,
+                );
+            }
+        }
+        mtl_library = Some(mtl_library.unwrap_or_default() + &mtl + "\n");
+    }
+    Ok((mtl_library, resources))
+}
+
+// A mesh file loaded by the viewer as an object with its own materials and textures, e.g. for
+// gltf and glb files (which meshcat doesn't load as geometries) or obj files with .mtl files.
+// The external files of gltf files are embedded and glb files are sent as gltf, the .mtl files
+// of obj files and their textures are sent with them
+pub fn mesh_file(path: &str) -> Result<LumpedObject, crate::Error> {
+    let (format, data) = mesh_data(path)?;
+    let (mtl_library, resources) = match (format.as_str(), &data) {
+        ("obj", MeshData::Text(obj)) => obj_materials(path, obj)?,
+        _ => (None, BTreeMap::new()),
+    };
+    Ok(LumpedObject::builder()
+        .geometries(Vec::new())
+        .object(Object::new(
+            Isometry3::identity(),
+            ObjectType::MeshFile {
+                format,
+                data,
+                mtl_library,
+                resources,
+            },
+        ))
+        .build())
+}
+
+// Loads each mesh file once, the geometries returned for a file share its uuid so an object
+// using it several times (with different origins) only sends its data once
+#[derive(Default)]
+pub struct GeometryRegistry {
+    meshes: std::collections::HashMap<String, Geometry>,
+}
+
+impl GeometryRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn mesh(&mut self, path: &str) -> Result<Geometry, Box<dyn Error>> {
+        self.mesh_with_origin(path, Isometry3::identity())
+    }
+
+    pub fn mesh_with_origin(
+        &mut self,
+        path: &str,
+        origin: Isometry3<f64>,
+    ) -> Result<Geometry, Box<dyn Error>> {
+        if !self.meshes.contains_key(path) {
+            self.meshes
+                .insert(path.to_string(), Geometry::new(load_mesh(path)?));
+        }
+        let mut geometry = self.meshes[path].clone();
+        geometry.origin = origin;
+        Ok(geometry)
+    }
+}
+
+// The glyphs of a text texture are about 1 tall (See text), an image texture is on a 10x10 plane
 pub fn scene_text(texture: TextureType) -> LumpedObject {
+    let size = match &texture {
+        TextureType::Text {
+            text, font_size, ..
+        } => text_plane_size(text, *font_size, 1.0),
+        TextureType::Image { .. } => 10.0,
+    };
     LumpedObject::builder()
         .texture(Texture::new(texture))
         .geometries(vec![Geometry::new(GeometryType::Plane {
-            width: 10.0,
-            height: 10.0,
+            width: size,
+            height: size,
             width_segments: 1,
             height_segments: 1,
         })])
@@ -37,8 +293,572 @@ pub fn scene_text(texture: TextureType) -> LumpedObject {
         .build()
 }
 
+// Meshcat draws the text centered on a 256x256 canvas
+const TEXT_CANVAS_SIZE: f64 = 256.0;
+// The font size of the texture of text
+const TEXT_FONT_SIZE: u32 = 64;
+
+// The estimated width and height in pixels of a text texture's text, meshcat shrinks the font
+// until the text fits in the canvas and a character is roughly 0.6 of the font size wide
+fn text_extents(text: &str, font_size: u32) -> (f64, f64) {
+    let characters = text.chars().count().max(1) as f64;
+    let font_size = (font_size as f64).min(TEXT_CANVAS_SIZE / (0.6 * characters));
+    (0.6 * font_size * characters, font_size)
+}
+
+// The side of the square plane a text texture is drawn on for the glyphs to be height tall
+fn text_plane_size(text: &str, font_size: u32, height: f64) -> f64 {
+    height * TEXT_CANVAS_SIZE / text_extents(text, font_size).1
+}
+
+#[derive(Clone, Debug, TypedBuilder)]
+pub struct TextOptions {
+    // The color of the glyphs (black by default). The viewer draws the text textures in black, a
+    // color is the emissive color of a lit material, which the sprites of billboards don't have
+    #[builder(default, setter(strip_option))]
+    pub color: Option<u32>,
+    // Always faces the camera (See billboard)
+    #[builder(default = false)]
+    pub billboard: bool,
+}
+
+impl Default for TextOptions {
+    fn default() -> Self {
+        TextOptions::builder().build()
+    }
+}
+
+// A plane showing the text with its glyphs about height tall, e.g. to name a frame. The plane
+// is larger than the text, its transparent part isn't drawn so it doesn't hide what's behind it.
+// Use label for a background. Fails for a colored billboard (See TextOptions::color)
+pub fn text(
+    text: &str,
+    height: f64,
+    options: &TextOptions,
+) -> Result<LumpedObject, Box<dyn Error>> {
+    if options.color.is_some() && options.billboard {
+        return Err("The text of a billboard can only be black".into());
+    }
+    let object = text_plane(text, height, options.color);
+    match options.billboard {
+        true => billboard(object),
+        false => Ok(object),
+    }
+}
+
+fn text_plane(text: &str, height: f64, color: Option<u32>) -> LumpedObject {
+    let size = text_plane_size(text, TEXT_FONT_SIZE, height);
+    // The glyphs are black whatever the material color, only an emissive color adds to them
+    let (material_type, extra) = match color {
+        None => (
+            MaterialType::MeshBasic,
+            serde_json::json!({"alphaTest": 0.5}),
+        ),
+        Some(color) => (
+            MaterialType::MeshLambert,
+            serde_json::json!({"alphaTest": 0.5, "emissive": color}),
+        ),
+    };
+    let serde_json::Value::Object(extra) = extra else {
+        unreachable!()
+    };
+    LumpedObject::builder()
+        .texture(Texture::new(TextureType::new_text(
+            text,
+            TEXT_FONT_SIZE,
+            "sans-serif",
+        )))
+        .geometries(vec![Geometry::new(GeometryType::Plane {
+            width: size,
+            height: size,
+            width_segments: 1,
+            height_segments: 1,
+        })])
+        .material(
+            Material::builder()
+                .material_type(material_type)
+                .transparent(true)
+                .extra(extra)
+                .build(),
+        )
+        .build()
+}
+
+#[derive(Clone, Debug, TypedBuilder)]
+pub struct LabelOptions {
+    // Side length of the square plane the text is drawn on
+    #[builder(default = 1.0)]
+    pub size: f64,
+    #[builder(default = 48)]
+    pub font_size: u32,
+    #[builder(default = "sans-serif".to_string(), setter(into))]
+    pub font_face: String,
+    #[builder(default, setter(strip_option))]
+    pub background_color: Option<u32>,
+    #[builder(default = 1.0)]
+    pub background_opacity: f64,
+    // Space between the text and the edges of the background
+    #[builder(default = 0.0)]
+    pub padding: f64,
+    // Offset of the label from the origin of its path, e.g. to place it above an object
+    #[builder(default = Vector3::zeros())]
+    pub offset: Vector3<f64>,
+    // Always faces the camera (See billboard)
+    #[builder(default = false)]
+    pub billboard: bool,
+}
+
+impl Default for LabelOptions {
+    fn default() -> Self {
+        LabelOptions::builder().build()
+    }
+}
+
+// Returns the text object and, if a background color is set, a background box sized to the
+// (estimated) text extents. They need different materials so they're published as two objects
+// (See Meshcat::set_label)
+pub fn label(text: &str, options: &LabelOptions) -> (LumpedObject, Option<LumpedObject>) {
+    let text_object = LumpedObject::builder()
+        .texture(Texture::new(TextureType::new_text(
+            text,
+            options.font_size,
+            &options.font_face,
+        )))
+        .geometries(vec![Geometry::new(GeometryType::Plane {
+            width: options.size,
+            height: options.size,
+            width_segments: 1,
+            height_segments: 1,
+        })])
+        .material(
+            Material::builder()
+                .material_type(MaterialType::MeshBasic)
+                .transparent(true)
+                .build(),
+        )
+        .object(Object::new(
+            Isometry3::from_parts(
+                Translation3::from(options.offset + Vector3::new(0.0, 0.0, 1e-3)),
+                Default::default(),
+            ),
+            ObjectType::Mesh,
+        ))
+        .build();
+    let background = options.background_color.map(|color| {
+        let (text_width, text_height) = text_extents(text, options.font_size);
+        let scale = options.size / TEXT_CANVAS_SIZE;
+        LumpedObject::builder()
+            .geometries(vec![Geometry::new(GeometryType::Plane {
+                width: text_width * scale + 2.0 * options.padding,
+                height: text_height * scale + 2.0 * options.padding,
+                width_segments: 1,
+                height_segments: 1,
+            })])
+            .material(
+                Material::builder()
+                    .material_type(MaterialType::MeshBasic)
+                    .color(color)
+                    .opacity(options.background_opacity)
+                    .transparent(options.background_opacity < 1.0)
+                    .build(),
+            )
+            .object(Object::new(
+                Isometry3::from_parts(Translation3::from(options.offset), Default::default()),
+                ObjectType::Mesh,
+            ))
+            .build()
+    });
+    if options.billboard {
+        let size = options.size;
+        let mut text_sprite = plane_sprite(text_object, size, size, Isometry3::identity());
+        if background.is_some() {
+            // Both sprites face the camera so the text is about as far as its background, drawn
+            // without depth test it isn't hidden by it
+            let serde_json::Value::Object(extra) = serde_json::json!({"depthTest": false}) else {
+                unreachable!()
+            };
+            text_sprite.material.extra = Some(extra);
+        }
+        return (
+            text_sprite,
+            background.map(|background| {
+                let GeometryType::Plane { width, height, .. } = background.geometries[0].geometry
+                else {
+                    unreachable!()
+                };
+                plane_sprite(background, width, height, Isometry3::identity())
+            }),
+        );
+    }
+    (text_object, background)
+}
+
+// The plane of the object as a sprite, keeping its texture and color
+fn plane_sprite(
+    object: LumpedObject,
+    width: f64,
+    height: f64,
+    origin: Isometry3<f64>,
+) -> LumpedObject {
+    let mut material = object.material;
+    material.material_type = MaterialType::Sprite {
+        size_attenuation: true,
+    };
+    // Sprites have no geometry, the object itself is the sprite and is scaled to the plane
+    let mut sprite = Object::new(Isometry3::identity(), ObjectType::Sprite);
+    sprite.matrix = object.object.matrix
+        * origin.to_homogeneous()
+        * nalgebra::Matrix4::new_nonuniform_scaling(&Vector3::new(width, height, 1.0));
+    LumpedObject {
+        geometries: Vec::new(),
+        material,
+        object: sprite,
+        ..object
+    }
+}
+
+// A textured plane (e.g. of text or screen) as a sprite of the same size which always faces the
+// camera, e.g. for the names of frames to stay readable as the camera orbits
+pub fn billboard(object: LumpedObject) -> Result<LumpedObject, Box<dyn Error>> {
+    let [Geometry {
+        geometry: GeometryType::Plane { width, height, .. },
+        origin,
+        ..
+    }] = object.geometries.as_slice()
+    else {
+        return Err("Only an object with one plane can be a billboard".into());
+    };
+    let (width, height, origin) = (*width, *height, *origin);
+    Ok(plane_sprite(object, width, height, origin))
+}
+
+// A plane showing the image on both sides, unaffected by the scene lights
+pub fn screen(image: Image, width: f64, height: f64) -> LumpedObject {
+    LumpedObject::builder()
+        .image(image)
+        .texture(Texture::new(TextureType::new_image()))
+        .geometries(vec![Geometry::new(GeometryType::Plane {
+            width,
+            height,
+            width_segments: 1,
+            height_segments: 1,
+        })])
+        .material(
+            Material::builder()
+                .material_type(MaterialType::MeshBasic)
+                .build(),
+        )
+        .build()
+}
+
+// A camera facing square of side size at the origin of its path (e.g. for landmarks), drawn with
+// the image if given and tinted by color
+pub fn sprite(image: Option<Image>, color: u32, size: f64) -> LumpedObject {
+    let material = Material::builder()
+        .material_type(MaterialType::Sprite {
+            size_attenuation: true,
+        })
+        .color(color)
+        .transparent(true)
+        .build();
+    // Sprites have no geometry, the object itself is the sprite and is scaled to its size
+    let mut object = Object::new(Isometry3::identity(), ObjectType::Sprite);
+    object.matrix = nalgebra::Matrix4::new_scaling(size);
+    match image {
+        Some(image) => LumpedObject::builder()
+            .image(image)
+            .texture(Texture::new(TextureType::new_image()))
+            .geometries(vec![])
+            .material(material)
+            .object(object)
+            .build(),
+        None => LumpedObject::builder()
+            .geometries(vec![])
+            .material(material)
+            .object(object)
+            .build(),
+    }
+}
+
+// Keeps the points p with normal.dot(p) + constant >= 0 (three.js' convention), in world
+// coordinates
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ClippingPlane {
+    pub normal: Vector3<f64>,
+    pub constant: f64,
+}
+
+impl ClippingPlane {
+    pub fn new(normal: Vector3<f64>, constant: f64) -> Self {
+        let norm = normal.norm();
+        ClippingPlane {
+            normal: normal / norm,
+            constant: constant / norm,
+        }
+    }
+
+    // The plane through point, keeping the side normal points to
+    pub fn from_point(normal: Vector3<f64>, point: Vector3<f64>) -> Self {
+        let normal = normal.normalize();
+        ClippingPlane {
+            normal,
+            constant: -normal.dot(&point),
+        }
+    }
+
+    fn uniform(&self) -> Uniform {
+        Uniform::Vector4([self.normal.x, self.normal.y, self.normal.z, self.constant])
+    }
+}
+
+pub const MAX_CLIPPING_PLANES: usize = 4;
+
+const CLIPPING_VERTEX_SHADER: &str = "
+varying vec3 world_position;
+varying vec3 view_normal;
+void main() {
+    world_position = (modelMatrix * vec4(position, 1.0)).xyz;
+    view_normal = normalMatrix * normal;
+    gl_Position = projectionMatrix * modelViewMatrix * vec4(position, 1.0);
+}";
+
+const CLIPPING_FRAGMENT_SHADER: &str = "
+uniform vec3 color;
+uniform float opacity;
+uniform float clipping_enabled;
+uniform vec4 clipping_plane0;
+uniform vec4 clipping_plane1;
+uniform vec4 clipping_plane2;
+uniform vec4 clipping_plane3;
+varying vec3 world_position;
+varying vec3 view_normal;
+bool is_clipped(vec4 plane) {
+    return dot(plane.xyz, world_position) + plane.w < 0.0;
+}
+void main() {
+    if (clipping_enabled > 0.5 && (is_clipped(clipping_plane0) || is_clipped(clipping_plane1)
+        || is_clipped(clipping_plane2) || is_clipped(clipping_plane3))) {
+        discard;
+    }
+    // Lit from the camera, so the insides of a cut open mesh are shaded too
+    float light = 0.4 + 0.6 * abs(normalize(view_normal).z);
+    gl_FragColor = vec4(color * light, opacity);
+}";
+
+// A material cutting away the parts of its object outside of the planes (e.g. to look inside a
+// robot or a building), meshcat's viewer doesn't load the clippingPlanes of three.js materials
+// so the clipping is done by a shader. The planes can be turned off and on again with
+// Meshcat::set_clipping_enabled
+pub fn clipping_material(
+    color: u32,
+    opacity: f64,
+    planes: &[ClippingPlane],
+) -> Result<Material, Box<dyn Error>> {
+    if planes.len() > MAX_CLIPPING_PLANES {
+        return Err(format!(
+            "Got {} clipping planes, at most {} are supported",
+            planes.len(),
+            MAX_CLIPPING_PLANES
+        )
+        .into());
+    }
+    let mut uniforms = std::collections::BTreeMap::from([
+        ("color".to_string(), Uniform::Color(color)),
+        ("opacity".to_string(), Uniform::Float(opacity)),
+        ("clipping_enabled".to_string(), Uniform::Float(1.0)),
+    ]);
+    // The unused planes keep everything
+    let keep_all = ClippingPlane::new(Vector3::z(), 1.0);
+    for index in 0..MAX_CLIPPING_PLANES {
+        let plane = planes.get(index).unwrap_or(&keep_all);
+        uniforms.insert(format!("clipping_plane{}", index), plane.uniform());
+    }
+    Ok(Material::builder()
+        .material_type(MaterialType::Shader {
+            vertex_shader: CLIPPING_VERTEX_SHADER.to_string(),
+            fragment_shader: CLIPPING_FRAGMENT_SHADER.to_string(),
+            uniforms,
+        })
+        .transparent(opacity < 1.0)
+        .build())
+}
+
+fn buffer_geometry(points: Matrix3xX<f64>, colors: Matrix3xX<f64>) -> Geometry {
+    Geometry::new(GeometryType::Buffer {
+        data: Box::new(BufferGeometryData {
+            attributes: BufferGeometryAttributes {
+                position: BufferGeometryAttribute::new(points),
+                color: BufferGeometryAttribute::new(colors),
+                normal: None,
+                uv: None,
+            },
+            index: None,
+        }),
+    })
+}
+
+// Vertex colored line segments, each pair of consecutive points is a segment
+pub fn line_segments(
+    points: Matrix3xX<f64>,
+    colors: Matrix3xX<f64>,
+    pose: Isometry3<f64>,
+) -> LumpedObject {
+    LumpedObject::builder()
+        .geometries(vec![buffer_geometry(points, colors)])
+        .material(
+            Material::builder()
+                .vertex_colors(true)
+                .material_type(MaterialType::LineBasic)
+                .build(),
+        )
+        .object(Object::new(pose, ObjectType::LineSegments))
+        .build()
+}
+
+// Extends each end of a segment sideways by half the width in pixels, the end's normal is the
+// other end and its color's red channel the side (-1 or 1)
+const FAT_LINE_VERTEX_SHADER: &str = "
+uniform float width;
+uniform float viewport_height;
+void main() {
+    vec4 clip = projectionMatrix * modelViewMatrix * vec4(position, 1.0);
+    vec4 other = projectionMatrix * modelViewMatrix * vec4(normal, 1.0);
+    float aspect = projectionMatrix[1][1] / projectionMatrix[0][0];
+    vec2 screen = clip.xy / clip.w * vec2(aspect, 1.0);
+    vec2 other_screen = other.xy / other.w * vec2(aspect, 1.0);
+    vec2 direction = normalize(other_screen - screen);
+    vec2 offset = vec2(-direction.y, direction.x) * color.r * width / viewport_height;
+    offset.x /= aspect;
+    clip.xy += offset * clip.w;
+    gl_Position = clip;
+}";
+
+const FAT_LINE_FRAGMENT_SHADER: &str = "
+uniform vec3 line_color;
+void main() {
+    gl_FragColor = vec4(line_color, 1.0);
+}";
+
+fn line(
+    points: Matrix3xX<f64>,
+    color: u32,
+    linewidth: f64,
+    object_type: ObjectType,
+) -> LumpedObject {
+    let colors = Matrix3xX::from_element(points.ncols(), 1.0);
+    LumpedObject::builder()
+        .geometries(vec![buffer_geometry(points, colors)])
+        .material(
+            Material::builder()
+                .material_type(MaterialType::LineBasic)
+                .color(color)
+                .linewidth(linewidth)
+                .build(),
+        )
+        .object(Object::new(Isometry3::identity(), object_type))
+        .build()
+}
+
+// The points connected one after the other, e.g. a trajectory. Like line_segments most WebGL
+// implementations ignore the linewidth (See fat_line)
+pub fn polyline(points: Matrix3xX<f64>, color: u32, linewidth: f64) -> LumpedObject {
+    line(points, color, linewidth, ObjectType::Line)
+}
+
+// A closed polyline, the last point is connected back to the first one
+pub fn polygon(points: Matrix3xX<f64>, color: u32, linewidth: f64) -> LumpedObject {
+    line(points, color, linewidth, ObjectType::LineLoop)
+}
+
+// The viewer's height in pixels assumed by fat_line, the widths are only exact for viewers of
+// this height (set the material.uniforms.viewport_height.value property for others)
+pub const FAT_LINE_VIEWPORT_HEIGHT: f64 = 800.0;
+
+// A polyline width pixels wide whatever its distance to the camera, unlike line_segments whose
+// linewidth is ignored by most WebGL implementations. Each segment is a quad (there are no
+// joins between them)
+pub fn fat_line(points: &Matrix3xX<f64>, color: u32, width: f64) -> LumpedObject {
+    let mut ends = Vec::new();
+    let mut others = Vec::new();
+    let mut sides = Vec::new();
+    for segment in points.column_iter().collect::<Vec<_>>().windows(2) {
+        let (start, end) = (segment[0].into_owned(), segment[1].into_owned());
+        // The two triangles of the quad, a side of the start is the other side of the end as
+        // they're extended in opposite directions
+        for (point, side) in [
+            (start, 1.0),
+            (start, -1.0),
+            (end, -1.0),
+            (end, -1.0),
+            (start, -1.0),
+            (end, 1.0),
+        ] {
+            ends.push(point);
+            others.push(if point == start { end } else { start });
+            sides.push(Vector3::new(side, 0.0, 0.0));
+        }
+    }
+    let attribute = |columns: &[Vector3<f64>]| {
+        // from_columns panics without columns
+        BufferGeometryAttribute::new(Matrix3xX::from_iterator(
+            columns.len(),
+            columns.iter().flatten().copied(),
+        ))
+    };
+    let geometry = GeometryType::Buffer {
+        data: Box::new(BufferGeometryData {
+            attributes: BufferGeometryAttributes {
+                position: attribute(&ends),
+                color: attribute(&sides),
+                normal: Some(attribute(&others)),
+                uv: None,
+            },
+            index: None,
+        }),
+    };
+    let uniforms = std::collections::BTreeMap::from([
+        ("line_color".to_string(), Uniform::Color(color)),
+        ("width".to_string(), Uniform::Float(width)),
+        (
+            "viewport_height".to_string(),
+            Uniform::Float(FAT_LINE_VIEWPORT_HEIGHT),
+        ),
+    ]);
+    LumpedObject::builder()
+        .geometries(vec![Geometry::new(geometry)])
+        .material(
+            Material::builder()
+                .material_type(MaterialType::Shader {
+                    vertex_shader: FAT_LINE_VERTEX_SHADER.to_string(),
+                    fragment_shader: FAT_LINE_FRAGMENT_SHADER.to_string(),
+                    uniforms,
+                })
+                // Declares the color attribute in the shaders
+                .vertex_colors(true)
+                .build(),
+        )
+        .build()
+}
+
+// Vertex colored points of the given size (in meters)
+pub fn point_cloud(points: Matrix3xX<f64>, colors: Matrix3xX<f64>, size: f64) -> LumpedObject {
+    LumpedObject::builder()
+        .geometries(vec![buffer_geometry(points, colors)])
+        .material(
+            Material::builder()
+                .vertex_colors(true)
+                .material_type(MaterialType::Points { size })
+                .build(),
+        )
+        .object(Object::new(Isometry3::identity(), ObjectType::Points))
+        .build()
+}
+
+// The length of the axes of triad
+pub const TRIAD_LENGTH: f64 = 0.5;
+
 pub fn triad(pose: Isometry3<f64>) -> LumpedObject {
-    let scale = 0.5;
+    let scale = TRIAD_LENGTH;
     let points = Matrix3xX::<f64>::from_columns(&[
         Vector3::new(0.0, 0.0, 0.0),
         Vector3::new(scale, 0.0, 0.0),
@@ -55,35 +875,844 @@ pub fn triad(pose: Isometry3<f64>) -> LumpedObject {
         Vector3::new(0.0, 0.0, 1.0),
         Vector3::new(0.0, 0.6, 1.0),
     ]);
-    LumpedObject::builder()
-        .geometries(vec![Geometry::new(GeometryType::Buffer {
-            data: Box::new(BufferGeometryData {
-                attributes: BufferGeometryAttributes {
-                    position: BufferGeometryAttribute {
-                        item_size: 3,
-                        array: points,
-                        attribute_type: "Float32Array".to_string(),
-                        normalized: false,
-                    },
-                    color: BufferGeometryAttribute {
-                        item_size: 3,
-                        array: colors,
-                        attribute_type: "Float32Array".to_string(),
-                        normalized: false,
-                    },
-                    normal: None,
-                    uv: None,
-                },
-            }),
+    line_segments(points, colors, pose)
+}
+
+#[derive(Clone, Debug, TypedBuilder)]
+pub struct TriadOptions {
+    #[builder(default = 0.5)]
+    pub length: f64,
+    // In pixels, wider than 1 the axes are fat lines (See fat_line)
+    #[builder(default = 1.0)]
+    pub line_width: f64,
+    // Names the axes X, Y and Z with texts of this height past their ends
+    #[builder(default, setter(strip_option))]
+    pub label_height: Option<f64>,
+    // Draws each axis as a cylinder ending with a cone instead of a line, which stays visible
+    // from far away
+    #[builder(default = false)]
+    pub arrows: bool,
+}
+
+impl Default for TriadOptions {
+    fn default() -> Self {
+        TriadOptions::builder().build()
+    }
+}
+
+// The objects of a triad at the origin and their names (See Meshcat::set_triad), the axes are
+// red, green and blue
+pub fn triad_objects(options: &TriadOptions) -> Vec<(&'static str, LumpedObject)> {
+    let length = options.length;
+    let axes = [
+        ("x", "x_label", "X", Vector3::x(), 0xff0000),
+        ("y", "y_label", "Y", Vector3::y(), 0x00ff00),
+        ("z", "z_label", "Z", Vector3::z(), 0x0000ff),
+    ];
+    let mut objects = Vec::new();
+    if !options.arrows && options.line_width <= 1.0 {
+        let mut axes = triad(Isometry3::identity());
+        if let GeometryType::Buffer { data } = &mut axes.geometries[0].geometry {
+            data.attributes.position = BufferGeometryAttribute::new(
+                data.attributes.position.to_matrix() * (length / TRIAD_LENGTH),
+            );
+        }
+        objects.push(("axes", axes));
+    }
+    for (name, _, _, axis, color) in axes {
+        if options.arrows {
+            // The cylinders and cones are along z
+            let rotation = UnitQuaternion::rotation_between(&Vector3::z(), &axis)
+                .unwrap_or_else(UnitQuaternion::identity);
+            let radius = length / 40.0;
+            let shaft = 0.8 * length;
+            let at = |distance: f64| {
+                Isometry3::from_parts(Translation3::from(axis * distance), rotation)
+            };
+            let arrow = LumpedObject::builder()
+                .geometries(vec![
+                    Geometry::new_with_origin(
+                        GeometryType::Cylinder {
+                            radius_top: radius,
+                            radius_bottom: radius,
+                            height: shaft,
+                            radial_segments: 16,
+                            height_segments: 1,
+                            theta_start: 0.0,
+                            theta_length: 2.0 * std::f64::consts::PI,
+                        },
+                        at(shaft / 2.0),
+                    ),
+                    Geometry::new_with_origin(
+                        GeometryType::Cone {
+                            radius: 2.0 * radius,
+                            height: length - shaft,
+                            radial_segments: 16,
+                            height_segments: 1,
+                            theta_start: 0.0,
+                            theta_length: 2.0 * std::f64::consts::PI,
+                        },
+                        at((shaft + length) / 2.0),
+                    ),
+                ])
+                .material(Material::builder().color(color).build())
+                .build();
+            objects.push((name, arrow));
+        } else if options.line_width > 1.0 {
+            let points = Matrix3xX::from_columns(&[Vector3::zeros(), axis * length]);
+            objects.push((name, fat_line(&points, color, options.line_width)));
+        }
+    }
+    if let Some(height) = options.label_height {
+        for (_, name, label, axis, _) in axes {
+            let text = text_plane(label, height, None);
+            let size = text_plane_size(label, TEXT_FONT_SIZE, height);
+            let position = Isometry3::from_parts(
+                Translation3::from(axis * (length + height)),
+                UnitQuaternion::identity(),
+            );
+            objects.push((name, plane_sprite(text, size, size, position)));
+        }
+    }
+    objects
+}
+
+// The number of grid lines along each axis of a ground plane
+pub const MAX_GRID_LINES: usize = 10_000;
+
+#[derive(Clone, Debug, TypedBuilder)]
+pub struct GroundPlaneOptions {
+    // Opacity of the shadows on the otherwise invisible plane
+    #[builder(default = 0.3)]
+    pub shadow_opacity: f64,
+    // Draws a matte plane of this color instead of only the shadows
+    #[builder(default, setter(strip_option))]
+    pub matte_color: Option<u32>,
+    // Distance between the lines of a grid drawn on the plane
+    #[builder(default, setter(strip_option))]
+    pub grid_spacing: Option<f64>,
+    #[builder(default = 0xb0b0b0)]
+    pub grid_color: u32,
+}
+
+impl Default for GroundPlaneOptions {
+    fn default() -> Self {
+        GroundPlaneOptions::builder().build()
+    }
+}
+
+// A square of side size on the z = 0 plane receiving the shadows of the objects above it (made
+// large it looks infinite), and its grid lines if a grid spacing is set. The objects need
+// cast_shadow set to cast shadows on it (See Meshcat::set_ground_plane). The size has to be
+// finite and the grid spacing positive, with at most MAX_GRID_LINES lines along each axis
+pub fn ground_plane(
+    size: f64,
+    options: &GroundPlaneOptions,
+) -> Result<(LumpedObject, Option<LumpedObject>), Box<dyn Error>> {
+    if !size.is_finite() || size < 0.0 {
+        return Err(format!("Invalid ground plane size {}", size).into());
+    }
+    if let Some(spacing) = options.grid_spacing {
+        if !spacing.is_finite() || spacing <= 0.0 {
+            return Err(format!("Invalid grid spacing {}", spacing).into());
+        }
+        if size / spacing > MAX_GRID_LINES as f64 {
+            return Err(format!(
+                "A grid spacing of {} gives more than {} lines on a plane of size {}",
+                spacing, MAX_GRID_LINES, size
+            )
+            .into());
+        }
+    }
+    let material = match options.matte_color {
+        Some(color) => Material::builder()
+            .material_type(MaterialType::MeshLambert)
+            .color(color)
+            .build(),
+        None => Material::builder()
+            .material_type(MaterialType::Shadow)
+            .opacity(options.shadow_opacity)
+            .transparent(true)
+            .build(),
+    };
+    let mut object = Object::new(Isometry3::identity(), ObjectType::Mesh);
+    object.receive_shadow = true;
+    let plane = LumpedObject::builder()
+        .geometries(vec![Geometry::new(GeometryType::Plane {
+            width: size,
+            height: size,
+            width_segments: 1,
+            height_segments: 1,
         })])
+        .material(material)
+        .object(object)
+        .build();
+    let grid = options.grid_spacing.map(|spacing| {
+        let lines = (size / 2.0 / spacing).floor() as i64;
+        let offsets = (-lines..=lines).map(|i| i as f64 * spacing);
+        // Slightly above the plane so it isn't hidden by it
+        grid_lines(offsets, size / 2.0, 1e-3, options.grid_color)
+    });
+    Ok((plane, grid))
+}
+
+// The lines parallel to the x and y axes at the offsets, from -half_size to half_size
+fn grid_lines(
+    offsets: impl Iterator<Item = f64>,
+    half_size: f64,
+    height: f64,
+    color: u32,
+) -> LumpedObject {
+    let points = offsets
+        .flat_map(|offset| {
+            [
+                Vector3::new(offset, -half_size, height),
+                Vector3::new(offset, half_size, height),
+                Vector3::new(-half_size, offset, height),
+                Vector3::new(half_size, offset, height),
+            ]
+        })
+        .collect::<Vec<_>>();
+    let color = hex_to_rgb(color);
+    let colors = Matrix3xX::from_fn(points.len(), |row, _| color[row]);
+    line_segments(
+        Matrix3xX::from_columns(&points),
+        colors,
+        Isometry3::identity(),
+    )
+}
+
+// A square of side size on the z = 0 plane divided in divisions x divisions cells, like three.js'
+// GridHelper (and the viewer's /Grid) but in the xy plane, e.g. as a reference plane when /Grid
+// is hidden
+pub fn grid(size: f64, divisions: u32, color: u32) -> LumpedObject {
+    let divisions = divisions.max(1);
+    let spacing = size / divisions as f64;
+    let offsets = (0..=divisions).map(|i| -size / 2.0 + i as f64 * spacing);
+    grid_lines(offsets, size / 2.0, 0.0, color)
+}
+
+// How the points of a trajectory are colored
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TrajectoryColoring {
+    // All the points have the trajectory's color
+    Uniform,
+    // From blue (first pose) to red (last pose)
+    Time,
+    // From blue (slowest) to red (fastest), assuming the poses are evenly sampled in time
+    Speed,
+}
+
+#[derive(Clone, Debug, TypedBuilder)]
+pub struct TrajectoryOptions {
+    #[builder(default = 0xffffff)]
+    pub color: u32,
+    #[builder(default = TrajectoryColoring::Uniform)]
+    pub coloring: TrajectoryColoring,
+    // Draws the triad of every n-th pose (including the first one)
+    #[builder(default, setter(strip_option))]
+    pub triad_every: Option<usize>,
+    #[builder(default = 0.1)]
+    pub triad_length: f64,
+}
+
+impl Default for TrajectoryOptions {
+    fn default() -> Self {
+        TrajectoryOptions::builder().build()
+    }
+}
+
+// The positions of the poses connected one after the other, and the triads of the poses if
+// triad_every is set (merged in a single object)
+pub fn trajectory(
+    poses: &[Isometry3<f64>],
+    options: &TrajectoryOptions,
+) -> (LumpedObject, Option<LumpedObject>) {
+    let points = poses
+        .iter()
+        .map(|pose| pose.translation.vector)
+        .collect::<Vec<_>>();
+    let t = match options.coloring {
+        TrajectoryColoring::Uniform => vec![None; points.len()],
+        TrajectoryColoring::Time => (0..points.len())
+            .map(|i| Some(i as f64 / (points.len().max(2) - 1) as f64))
+            .collect(),
+        TrajectoryColoring::Speed => {
+            // The speed at a point is the length of the segment reaching it (leaving it for the
+            // first point)
+            let speeds = (0..points.len())
+                .map(|i| {
+                    let (from, to) = if i == 0 { (0, 1) } else { (i - 1, i) };
+                    points.get(to).map_or(0.0, |to| (to - points[from]).norm())
+                })
+                .collect::<Vec<_>>();
+            let max_speed = speeds.iter().copied().fold(0.0, f64::max);
+            speeds
+                .iter()
+                .map(|speed| {
+                    Some(if max_speed > 0.0 {
+                        speed / max_speed
+                    } else {
+                        0.0
+                    })
+                })
+                .collect()
+        }
+    };
+    let color = hex_to_rgb(options.color);
+    let colors = t
+        .into_iter()
+        .map(|t| t.map_or(color, gradient_color))
+        .collect::<Vec<_>>();
+    let path = LumpedObject::builder()
+        .geometries(vec![buffer_geometry(
+            to_matrix(&points),
+            to_matrix(&colors),
+        )])
         .material(
             Material::builder()
                 .vertex_colors(true)
                 .material_type(MaterialType::LineBasic)
                 .build(),
         )
-        .object(Object::new(pose, ObjectType::LineSegments))
-        .build()
+        .object(Object::new(Isometry3::identity(), ObjectType::Line))
+        .build();
+    let triads = options.triad_every.map(|every| {
+        let GeometryType::Buffer { data } =
+            triad(Isometry3::identity()).geometries.remove(0).geometry
+        else {
+            unreachable!("triad is a buffer geometry");
+        };
+        let axes = data.attributes.position.to_matrix() * (options.triad_length / TRIAD_LENGTH);
+        let axes_colors = data.attributes.color.to_matrix();
+        let mut points = Vec::new();
+        let mut colors = Vec::new();
+        for pose in poses.iter().step_by(every.max(1)) {
+            for (point, color) in axes.column_iter().zip(axes_colors.column_iter()) {
+                points.push(pose.transform_point(&point.into_owned().into()).coords);
+                colors.push(color.into_owned());
+            }
+        }
+        line_segments(
+            to_matrix(&points),
+            to_matrix(&colors),
+            Isometry3::identity(),
+        )
+    });
+    (path, triads)
+}
+
+// A terrain (or costmap) mesh with the height of the vertex at (row, column) at x = column *
+// cell_size and y = row * cell_size, in color or colored from blue (lowest) to red (highest)
+pub fn heightmap(
+    heights: &DMatrix<f64>,
+    cell_size: f64,
+    color: u32,
+    color_by_height: bool,
+) -> Result<LumpedObject, Box<dyn Error>> {
+    let (rows, columns) = heights.shape();
+    if rows < 2 || columns < 2 {
+        return Err(format!("Need at least 2x2 heights but got {}x{}", rows, columns).into());
+    }
+    let vertex = |row: usize, column: usize| (row * columns + column) as u32;
+    let vertices = Matrix3xX::from_fn(rows * columns, |axis, i| {
+        let (row, column) = (i / columns, i % columns);
+        match axis {
+            0 => column as f64 * cell_size,
+            1 => row as f64 * cell_size,
+            _ => heights[(row, column)],
+        }
+    });
+    // Two counterclockwise (seen from above) triangles per cell
+    let faces = (0..rows - 1)
+        .flat_map(|row| (0..columns - 1).map(move |column| (row, column)))
+        .flat_map(|(row, column)| {
+            let (a, b) = (vertex(row, column), vertex(row, column + 1));
+            let (c, d) = (vertex(row + 1, column + 1), vertex(row + 1, column));
+            [[a, b, c], [a, c, d]]
+        })
+        .collect::<Vec<_>>();
+    let mesh = TriangularMesh::builder()
+        .vertices(vertices)
+        .faces(Matrix3xX::from_iterator(
+            faces.len(),
+            faces.into_iter().flatten(),
+        ));
+    if !color_by_height {
+        return mesh.build().into_object(color);
+    }
+    let (min, max) = (heights.min(), heights.max());
+    let colors = to_matrix(
+        &heights
+            .transpose()
+            .iter()
+            .map(|height| gradient_color((height - min) / (max - min).max(f64::EPSILON)))
+            .collect::<Vec<_>>(),
+    );
+    mesh.colors(colors).build().into_object(0xffffff)
+}
+
+// A box of the given size centered at the origin with its edges and corners rounded with the
+// radius, each rounded edge has segments segments (rounded up to an even number). Built like a
+// cube sphere: each face is a grid of directions, split in the middle where the flat part is
+fn rounded_box_triangles(
+    size: Vector3<f64>,
+    radius: f64,
+    segments: u32,
+) -> (Vec<Vector3<f64>>, Vec<Vector3<f64>>) {
+    let half_segments = segments.div_ceil(2).max(1);
+    // The angles from the face normal along a face axis, and which side of the flat part
+    let steps = (0..=half_segments)
+        .map(|step| {
+            let angle = std::f64::consts::FRAC_PI_4 * step as f64 / half_segments as f64;
+            (angle - std::f64::consts::FRAC_PI_4, -1.0)
+        })
+        .chain((0..=half_segments).map(|step| {
+            let angle = std::f64::consts::FRAC_PI_4 * step as f64 / half_segments as f64;
+            (angle, 1.0)
+        }))
+        .collect::<Vec<_>>();
+    let inner = size / 2.0 - Vector3::repeat(radius);
+    let mut positions = Vec::new();
+    let mut normals = Vec::new();
+    for axis in 0..3 {
+        for sign in [-1.0, 1.0] {
+            let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+            let vertex = |i: usize, j: usize| {
+                let ((u_angle, u_side), (v_angle, v_side)) = (steps[i], steps[j]);
+                let mut normal = Vector3::zeros();
+                normal[axis] = sign;
+                normal[u] = u_angle.tan();
+                normal[v] = v_angle.tan();
+                let normal = normal.normalize();
+                let mut side = Vector3::zeros();
+                side[axis] = sign;
+                side[u] = u_side;
+                side[v] = v_side;
+                (side.component_mul(&inner) + radius * normal, normal)
+            };
+            for i in 0..steps.len() - 1 {
+                for j in 0..steps.len() - 1 {
+                    let (a, b, c, d) = (
+                        vertex(i, j),
+                        vertex(i + 1, j),
+                        vertex(i + 1, j + 1),
+                        vertex(i, j + 1),
+                    );
+                    // Counter clockwise seen from outside
+                    let quad = if sign > 0.0 {
+                        [a, b, c, a, c, d]
+                    } else {
+                        [a, c, b, a, d, c]
+                    };
+                    for (position, normal) in quad {
+                        positions.push(position);
+                        normals.push(normal);
+                    }
+                }
+            }
+        }
+    }
+    (positions, normals)
+}
+
+// Fails if the radius is larger than half the smallest side
+pub fn rounded_box(
+    size: Vector3<f64>,
+    radius: f64,
+    segments: u32,
+) -> Result<GeometryType, Box<dyn Error>> {
+    if radius < 0.0 || 2.0 * radius > size.min() {
+        return Err(format!(
+            "The radius {} doesn't fit in a box of size {:?}",
+            radius,
+            size.as_slice()
+        )
+        .into());
+    }
+    let (positions, normals) = rounded_box_triangles(size, radius, segments);
+    let attribute = BufferGeometryAttribute::new;
+    Ok(GeometryType::Buffer {
+        data: Box::new(BufferGeometryData {
+            attributes: BufferGeometryAttributes {
+                // The material color is used, not the vertex colors
+                color: attribute(Matrix3xX::from_element(positions.len(), 1.0)),
+                position: attribute(Matrix3xX::from_columns(&positions)),
+                normal: Some(attribute(Matrix3xX::from_columns(&normals))),
+                uv: None,
+            },
+            index: None,
+        }),
+    })
+}
+
+// The 12 edges (as pairs of points) of a box centered at the origin
+fn box_edges(half_extents: &Vector3<f64>) -> Matrix3xX<f64> {
+    let corner = |i: usize| {
+        Vector3::new(
+            if i & 1 == 0 { -1.0 } else { 1.0 },
+            if i & 2 == 0 { -1.0 } else { 1.0 },
+            if i & 4 == 0 { -1.0 } else { 1.0 },
+        )
+        .component_mul(half_extents)
+    };
+    // Corners differing by one bit are connected
+    let edges = (0..8)
+        .flat_map(|i| [1, 2, 4].map(|bit| (i, i ^ bit)))
+        .filter(|(i, j)| i < j)
+        .flat_map(|(i, j)| [corner(i), corner(j)])
+        .collect::<Vec<_>>();
+    Matrix3xX::from_columns(&edges)
+}
+
+// The 12 edges of a box centered at the pose
+pub fn oriented_box(pose: Isometry3<f64>, half_extents: Vector3<f64>, color: u32) -> LumpedObject {
+    let points = box_edges(&half_extents);
+    let color = hex_to_rgb(color);
+    let colors = Matrix3xX::from_fn(points.ncols(), |row, _| color[row]);
+    line_segments(points, colors, pose)
+}
+
+// The edges of the axis-aligned box from min to max, e.g. a collision object's AABB
+pub fn wireframe_box(min: Vector3<f64>, max: Vector3<f64>, color: u32) -> LumpedObject {
+    oriented_box(
+        Isometry3::from_parts(
+            Translation3::from((min + max) / 2.0),
+            UnitQuaternion::identity(),
+        ),
+        (max - min).abs() / 2.0,
+        color,
+    )
+}
+
+// The view frustum of a camera at the pose looking along its z axis with x to the right and y
+// down (the optical frame convention), fov_y being the vertical field of view in radians. The
+// rays from the camera to the far plane's corners and the near and far planes' edges
+pub fn frustum(
+    pose: Isometry3<f64>,
+    fov_y: f64,
+    aspect: f64,
+    near: f64,
+    far: f64,
+    color: u32,
+) -> LumpedObject {
+    let corners = |distance: f64| {
+        let half_height = distance * (fov_y / 2.0).tan();
+        let half_width = half_height * aspect;
+        [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)]
+            .map(|(x, y)| Vector3::new(x * half_width, y * half_height, distance))
+    };
+    let far_corners = corners(far);
+    let mut points = far_corners
+        .iter()
+        .flat_map(|corner| [Vector3::zeros(), *corner])
+        .collect::<Vec<_>>();
+    let planes = if near > 0.0 {
+        vec![corners(near), far_corners]
+    } else {
+        vec![far_corners]
+    };
+    for plane in planes {
+        points.extend((0..4).flat_map(|i| [plane[i], plane[(i + 1) % 4]]));
+    }
+    let color = hex_to_rgb(color);
+    let colors = Matrix3xX::from_fn(points.len(), |row, _| color[row]);
+    line_segments(to_matrix(&points), colors, pose)
+}
+
+// Green wireframe of an oriented bounding box
+pub fn obb(
+    center: Vector3<f64>,
+    half_extents: Vector3<f64>,
+    rotation: UnitQuaternion<f64>,
+) -> LumpedObject {
+    oriented_box(
+        Isometry3::from_parts(Translation3::from(center), rotation),
+        half_extents,
+        0x00ff00,
+    )
+}
+
+// Fits an oriented bounding box to the points using the principal axes of their covariance,
+// returns its pose and half extents
+pub fn fit_obb(points: &Matrix3xX<f64>) -> Result<(Isometry3<f64>, Vector3<f64>), Box<dyn Error>> {
+    if points.ncols() == 0 {
+        return Err("Can't fit an oriented bounding box to an empty point set".into());
+    }
+    let mean = points.column_mean();
+    let centered = points.map_with_location(|row, _, value| value - mean[row]);
+    let covariance: Matrix3<f64> = &centered * centered.transpose() / points.ncols() as f64;
+    let mut axes = covariance.symmetric_eigen().eigenvectors;
+    // Make sure the axes form a right-handed frame
+    if axes.determinant() < 0.0 {
+        axes.set_column(2, &-axes.column(2));
+    }
+    let rotation = UnitQuaternion::from_rotation_matrix(&Rotation3::from_matrix_unchecked(axes));
+    let local = axes.transpose() * centered;
+    let min = Vector3::from_fn(|row, _| local.row(row).min());
+    let max = Vector3::from_fn(|row, _| local.row(row).max());
+    Ok((
+        Isometry3::from_parts(
+            Translation3::from(mean + rotation * ((min + max) / 2.0)),
+            rotation,
+        ),
+        (max - min) / 2.0,
+    ))
+}
+
+pub fn obb_of_points(points: &Matrix3xX<f64>) -> Result<LumpedObject, Box<dyn Error>> {
+    let (pose, half_extents) = fit_obb(points)?;
+    Ok(obb(pose.translation.vector, half_extents, pose.rotation))
+}
+
+// Unlike Matrix3xX::from_columns, this handles empty slices
+fn to_matrix(columns: &[Vector3<f64>]) -> Matrix3xX<f64> {
+    Matrix3xX::from_iterator(
+        columns.len(),
+        columns.iter().flat_map(|column| column.iter().copied()),
+    )
+}
+
+// 0xRRGGBB to an rgb vector in [0, 1]
+pub(crate) fn hex_to_rgb(color: u32) -> Vector3<f64> {
+    Vector3::new(
+        ((color >> 16) & 0xff) as f64,
+        ((color >> 8) & 0xff) as f64,
+        (color & 0xff) as f64,
+    ) / 255.0
+}
+
+// Linear blue (0.0) to red (1.0) gradient
+fn gradient_color(t: f64) -> Vector3<f64> {
+    let t = t.clamp(0.0, 1.0);
+    Vector3::new(t, 0.0, 1.0 - t)
+}
+
+// The 36 vertices (2 counterclockwise triangles per face) and normals of a unit cube centered
+// at the origin
+fn cube_triangles() -> [(Vector3<f64>, Vector3<f64>); 36] {
+    let mut vertices = [(Vector3::zeros(), Vector3::zeros()); 36];
+    for axis in 0..3 {
+        for (side, sign) in [-1.0, 1.0].into_iter().enumerate() {
+            let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+            let mut normal = Vector3::zeros();
+            normal[axis] = sign;
+            let corner = |a: f64, b: f64| {
+                let mut corner = normal / 2.0;
+                corner[u] = a / 2.0;
+                corner[v] = b / 2.0;
+                (corner, normal)
+            };
+            let (a, b, c, d) = (
+                corner(-1.0, -1.0),
+                corner(1.0, -1.0),
+                corner(1.0, 1.0),
+                corner(-1.0, 1.0),
+            );
+            // u x v is the axis, so the quad is counterclockwise seen from the positive side
+            let quad = if sign > 0.0 {
+                [a, b, c, a, c, d]
+            } else {
+                [a, c, b, a, d, c]
+            };
+            let start = (2 * axis + side) * 6;
+            vertices[start..start + 6].copy_from_slice(&quad);
+        }
+    }
+    vertices
+}
+
+// Cubes of side size at the centers with the colors (in [0, 1]) merged in a single geometry, much
+// faster to send and draw than an object per voxel, e.g. for occupancy grids
+pub fn voxels(
+    centers: &Matrix3xX<f64>,
+    size: f64,
+    colors: &Matrix3xX<f64>,
+) -> Result<LumpedObject, Box<dyn Error>> {
+    if centers.ncols() != colors.ncols() {
+        return Err(format!(
+            "Got {} centers but {} colors",
+            centers.ncols(),
+            colors.ncols()
+        )
+        .into());
+    }
+    let cube = cube_triangles();
+    let vertices = cube.len() * centers.ncols();
+    let position = Matrix3xX::from_fn(vertices, |row, i| {
+        centers[(row, i / cube.len())] + size * cube[i % cube.len()].0[row]
+    });
+    let normal = Matrix3xX::from_fn(vertices, |row, i| cube[i % cube.len()].1[row]);
+    let color = Matrix3xX::from_fn(vertices, |row, i| colors[(row, i / cube.len())]);
+    let geometry = GeometryType::Buffer {
+        data: Box::new(BufferGeometryData {
+            attributes: BufferGeometryAttributes {
+                position: BufferGeometryAttribute::new(position),
+                color: BufferGeometryAttribute::new(color),
+                normal: Some(BufferGeometryAttribute::new(normal)),
+                uv: None,
+            },
+            index: None,
+        }),
+    };
+    Ok(LumpedObject::builder()
+        .geometries(vec![Geometry::new(geometry)])
+        .material(
+            Material::builder()
+                .material_type(MaterialType::MeshLambert)
+                .vertex_colors(true)
+                .build(),
+        )
+        .build())
+}
+
+// Merged wireframe boxes of octree nodes given as (center, depth), a node at depth d has a side
+// length of root_size / 2^d. Nodes are colored from blue (root) to red (deepest node)
+pub fn octree(nodes: &[(Vector3<f64>, u32)], root_size: f64) -> LumpedObject {
+    let max_depth = nodes.iter().map(|(_, depth)| *depth).max().unwrap_or(0);
+    let mut points = Vec::with_capacity(24 * nodes.len());
+    let mut colors = Vec::with_capacity(24 * nodes.len());
+    for (center, depth) in nodes {
+        let half_size = root_size / 2.0_f64.powi(*depth as i32 + 1);
+        let color = gradient_color(*depth as f64 / max_depth.max(1) as f64);
+        for edge_point in box_edges(&Vector3::repeat(half_size)).column_iter() {
+            points.push(center + edge_point);
+            colors.push(color);
+        }
+    }
+    line_segments(
+        to_matrix(&points),
+        to_matrix(&colors),
+        Isometry3::identity(),
+    )
+}
+
+// One segment of the given length per point along its normal (normalized first)
+pub fn normals(
+    points: &Matrix3xX<f64>,
+    normals: &Matrix3xX<f64>,
+    length: f64,
+    color: u32,
+) -> Result<LumpedObject, Box<dyn Error>> {
+    if points.ncols() != normals.ncols() {
+        return Err(format!(
+            "Got {} points but {} normals",
+            points.ncols(),
+            normals.ncols()
+        )
+        .into());
+    }
+    let segments = points
+        .column_iter()
+        .zip(normals.column_iter())
+        .flat_map(|(point, normal)| {
+            let normal = normal.try_normalize(f64::EPSILON).unwrap_or_default();
+            [point.into_owned(), point + normal * length]
+        })
+        .collect::<Vec<_>>();
+    let colors = vec![hex_to_rgb(color); segments.len()];
+    Ok(line_segments(
+        to_matrix(&segments),
+        to_matrix(&colors),
+        Isometry3::identity(),
+    ))
+}
+
+// A black line from p1 to p2 with a tick every tick_spacing meters (and one at p2), and a label
+// with its length at the middle of the line. Returns the lines and the label
+// (See Meshcat::set_ruler)
+pub fn ruler(
+    p1: Vector3<f64>,
+    p2: Vector3<f64>,
+    tick_spacing: f64,
+) -> Result<(LumpedObject, LumpedObject), Box<dyn Error>> {
+    if tick_spacing <= 0.0 {
+        return Err(format!("Invalid tick spacing: {}", tick_spacing).into());
+    }
+    let length = (p2 - p1).norm();
+    let direction = (p2 - p1)
+        .try_normalize(f64::EPSILON)
+        .unwrap_or(Vector3::x());
+    // Ticks are drawn perpendicular to the line, preferably in the plane spanned by the line
+    // and the z axis
+    let side = direction
+        .cross(&Vector3::z())
+        .try_normalize(1e-6)
+        .unwrap_or_else(|| direction.cross(&Vector3::x()).normalize());
+    let tick = side * 0.25 * tick_spacing;
+
+    let mut points = vec![p1, p2];
+    let tick_count = (length / tick_spacing).floor() as usize;
+    let mut positions = (0..=tick_count)
+        .map(|i| i as f64 * tick_spacing)
+        .collect::<Vec<_>>();
+    if length - tick_count as f64 * tick_spacing > 1e-9 {
+        positions.push(length);
+    }
+    for position in positions {
+        let center = p1 + direction * position;
+        points.push(center - tick);
+        points.push(center + tick);
+    }
+    let colors = Matrix3xX::<f64>::zeros(points.len());
+    let lines = line_segments(
+        Matrix3xX::from_columns(&points),
+        colors,
+        Isometry3::identity(),
+    );
+
+    let (label, _) = label(
+        &format!("{:.3} m", length),
+        &LabelOptions::builder()
+            .size(2.0 * tick_spacing)
+            .offset((p1 + p2) / 2.0 + 2.0 * tick)
+            .build(),
+    );
+    Ok((lines, label))
+}
+
+// A black circular sector about the axis going from the direction from to the direction to
+// (both projected on the plane orthogonal to the axis), with a label of the signed angle in
+// degrees. Returns the lines and the label (See Meshcat::set_arc)
+pub fn arc(
+    center: Vector3<f64>,
+    axis: Vector3<f64>,
+    from: Vector3<f64>,
+    to: Vector3<f64>,
+    radius: f64,
+) -> Result<(LumpedObject, LumpedObject), Box<dyn Error>> {
+    let axis = nalgebra::Unit::try_new(axis, f64::EPSILON).ok_or("The arc axis is zero")?;
+    let project = |direction: Vector3<f64>| {
+        (direction - axis.into_inner() * axis.dot(&direction))
+            .try_normalize(1e-9)
+            .ok_or_else(|| format!("Direction {:?} is parallel to the arc axis", direction))
+    };
+    let (from, to) = (project(from)?, project(to)?);
+    let angle = axis.dot(&from.cross(&to)).atan2(from.dot(&to));
+    let segment_count = ((angle.abs() / (std::f64::consts::PI / 32.0)).ceil() as usize).max(2);
+    let arc_point =
+        |t: f64| center + UnitQuaternion::from_axis_angle(&axis, t * angle) * from * radius;
+    let mut points = vec![center, arc_point(0.0)];
+    for i in 0..segment_count {
+        points.push(arc_point(i as f64 / segment_count as f64));
+        points.push(arc_point((i + 1) as f64 / segment_count as f64));
+    }
+    points.push(center);
+    points.push(arc_point(1.0));
+    let lines = line_segments(
+        to_matrix(&points),
+        Matrix3xX::zeros(points.len()),
+        Isometry3::identity(),
+    );
+
+    let (label, _) = label(
+        &format!("{:.1}°", angle.to_degrees()),
+        &LabelOptions::builder()
+            .size(radius)
+            .offset(center + (arc_point(0.5) - center) * 1.3)
+            .build(),
+    );
+    Ok((lines, label))
 }
 
 #[cfg(test)]
@@ -96,4 +1725,731 @@ mod tests {
         assert_eq!(file_extension("foo.obj.gz").unwrap(), "gz");
         assert!(file_extension("foo").is_err());
     }
+
+    #[test]
+    fn test_clipping_material() {
+        let plane = ClippingPlane::from_point(Vector3::new(0.0, 0.0, -2.0), Vector3::z());
+        assert_eq!(plane, ClippingPlane::new(-Vector3::z(), 1.0));
+        let material = clipping_material(0xff0000, 1.0, &[plane]).unwrap();
+        let MaterialType::Shader { uniforms, .. } = &material.material_type else {
+            panic!("Expected a shader material");
+        };
+        assert_eq!(uniforms["color"], Uniform::Color(0xff0000));
+        assert_eq!(
+            uniforms["clipping_plane0"],
+            Uniform::Vector4([0.0, 0.0, -1.0, 1.0])
+        );
+        assert_eq!(
+            uniforms["clipping_plane3"],
+            Uniform::Vector4([0.0, 0.0, 1.0, 1.0])
+        );
+        assert_eq!(material.transparent, Some(false));
+        assert!(clipping_material(0, 1.0, &[plane; MAX_CLIPPING_PLANES + 1]).is_err());
+    }
+
+    #[test]
+    fn test_ground_plane() {
+        let (plane, grid) = ground_plane(10.0, &GroundPlaneOptions::default()).unwrap();
+        assert!(grid.is_none());
+        assert!(matches!(plane.material.material_type, MaterialType::Shadow));
+        assert!(plane.object.children[0].receive_shadow);
+        let value = serde_json::to_value(&plane).unwrap();
+        assert_eq!(value["object"]["children"][0]["receiveShadow"], true);
+        assert!(value["object"]["children"][0].get("castShadow").is_none());
+
+        let options = GroundPlaneOptions::builder()
+            .matte_color(0xffffff)
+            .grid_spacing(1.0)
+            .build();
+        let (plane, grid) = ground_plane(10.0, &options).unwrap();
+        assert!(matches!(
+            plane.material.material_type,
+            MaterialType::MeshLambert
+        ));
+        if let GeometryType::Buffer { data } = &grid.unwrap().geometries[0].geometry {
+            // 11 lines along x and 11 along y
+            assert_eq!(data.attributes.position.to_matrix().ncols(), 44);
+        } else {
+            panic!("Expected a buffer geometry");
+        }
+        for spacing in [0.0, -1.0, f64::NAN, f64::INFINITY, 1e-9] {
+            let options = GroundPlaneOptions::builder().grid_spacing(spacing).build();
+            assert!(ground_plane(10.0, &options).is_err(), "{}", spacing);
+        }
+        assert!(ground_plane(f64::NAN, &GroundPlaneOptions::default()).is_err());
+    }
+
+    #[test]
+    fn test_grid() {
+        let grid = grid(2.0, 4, 0x888888);
+        let GeometryType::Buffer { data } = &grid.geometries[0].geometry else {
+            panic!("Expected a buffer geometry");
+        };
+        // 5 lines along x and 5 along y
+        let points = &data.attributes.position.to_matrix();
+        assert_eq!(points.ncols(), 20);
+        assert_eq!(points.column(0), Vector3::new(-1.0, -1.0, 0.0));
+        assert_eq!(points.column(19), Vector3::new(1.0, 1.0, 0.0));
+        assert!(matches!(grid.object.object_type, ObjectType::LineSegments));
+    }
+
+    #[test]
+    fn test_trajectory() {
+        let poses = [0.0, 1.0, 3.0]
+            .map(|x| Isometry3::translation(x, 0.0, 0.0))
+            .to_vec();
+        let options = TrajectoryOptions::builder()
+            .coloring(TrajectoryColoring::Speed)
+            .triad_every(2)
+            .build();
+        let (path, triads) = trajectory(&poses, &options);
+        let GeometryType::Buffer { data } = &path.geometries[0].geometry else {
+            panic!("Expected a buffer geometry");
+        };
+        assert_eq!(data.attributes.position.to_matrix().ncols(), 3);
+        let colors = &data.attributes.color.to_matrix();
+        assert_eq!(colors.column(0), Vector3::new(0.5, 0.0, 0.5));
+        assert_eq!(colors.column(2), Vector3::new(1.0, 0.0, 0.0));
+        assert!(matches!(path.object.object_type, ObjectType::Line));
+
+        // The triads of the first and last poses
+        let triads = triads.unwrap();
+        let GeometryType::Buffer { data } = &triads.geometries[0].geometry else {
+            panic!("Expected a buffer geometry");
+        };
+        let points = &data.attributes.position.to_matrix();
+        assert_eq!(points.ncols(), 12);
+        // Stored as 32 bits floats
+        assert!(points
+            .column(7)
+            .relative_eq(&Vector3::new(3.1, 0.0, 0.0), 1e-6, 1e-6));
+
+        let (path, triads) = trajectory(&[], &TrajectoryOptions::default());
+        assert!(triads.is_none());
+        assert_eq!(path.geometries.len(), 1);
+    }
+
+    #[test]
+    fn test_wireframe_box() {
+        let object = wireframe_box(
+            Vector3::new(1.0, 2.0, 3.0),
+            Vector3::new(3.0, 3.0, 3.0),
+            0xff0000,
+        );
+        let GeometryType::Buffer { data } = &object.geometries[0].geometry else {
+            panic!("Expected a buffer geometry");
+        };
+        let points = &data.attributes.position.to_matrix();
+        assert_eq!(points.ncols(), 24);
+        assert_eq!(points.column(0), Vector3::new(-1.0, -0.5, 0.0));
+        assert_eq!(
+            data.attributes.color.to_matrix().column(5),
+            Vector3::new(1.0, 0.0, 0.0)
+        );
+        assert_eq!(
+            object.object.matrix,
+            Isometry3::translation(2.0, 2.5, 3.0).to_homogeneous()
+        );
+        assert!(matches!(
+            object.object.object_type,
+            ObjectType::LineSegments
+        ));
+    }
+
+    #[test]
+    fn test_frustum() {
+        let object = frustum(
+            Isometry3::identity(),
+            std::f64::consts::FRAC_PI_2,
+            2.0,
+            0.5,
+            2.0,
+            0xffffff,
+        );
+        let GeometryType::Buffer { data } = &object.geometries[0].geometry else {
+            panic!("Expected a buffer geometry");
+        };
+        let points = &data.attributes.position.to_matrix();
+        assert_eq!(points.ncols(), 24);
+        assert_eq!(points.column(0), Vector3::zeros());
+        assert!((points.column(1) - Vector3::new(-4.0, -2.0, 2.0)).norm() < 1e-12);
+        assert!((points.column(8) - Vector3::new(-1.0, -0.5, 0.5)).norm() < 1e-12);
+
+        // Without a near plane
+        let object = frustum(Isometry3::identity(), 1.0, 1.0, 0.0, 1.0, 0xffffff);
+        let GeometryType::Buffer { data } = &object.geometries[0].geometry else {
+            panic!("Expected a buffer geometry");
+        };
+        assert_eq!(data.attributes.position.to_matrix().ncols(), 16);
+    }
+
+    #[test]
+    fn test_heightmap() {
+        let heights = DMatrix::from_row_slice(2, 3, &[0.0, 0.0, 0.0, 0.0, 0.0, 2.0]);
+        let object = heightmap(&heights, 0.5, 0xffffff, true).unwrap();
+        let GeometryType::Buffer { data } = &object.geometries[0].geometry else {
+            panic!("Expected a buffer geometry");
+        };
+        let vertices = &data.attributes.position.to_matrix();
+        assert_eq!(vertices.ncols(), 6);
+        assert_eq!(vertices.column(5), Vector3::new(1.0, 0.5, 2.0));
+        assert_eq!(data.index.as_ref().unwrap().array.len(), 4 * 3);
+        // The normals of the flat cell point up
+        let normals = &data.attributes.normal.as_ref().unwrap().to_matrix();
+        assert_eq!(normals.column(0), Vector3::z());
+        let colors = &data.attributes.color.to_matrix();
+        assert_eq!(colors.column(0), Vector3::new(0.0, 0.0, 1.0));
+        assert_eq!(colors.column(5), Vector3::new(1.0, 0.0, 0.0));
+        assert_eq!(object.material.vertex_colors, Some(true));
+
+        assert!(heightmap(&DMatrix::zeros(1, 3), 1.0, 0xffffff, false).is_err());
+    }
+
+    #[test]
+    fn test_triad_objects() {
+        let names = |options: &TriadOptions| {
+            triad_objects(options)
+                .into_iter()
+                .map(|(name, _)| name)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(&TriadOptions::default()), ["axes"]);
+        let objects = triad_objects(&TriadOptions::builder().length(2.0).build());
+        let GeometryType::Buffer { data } = &objects[0].1.geometries[0].geometry else {
+            panic!("Expected a buffer geometry");
+        };
+        assert_eq!(data.attributes.position.to_matrix().column(1)[0], 2.0);
+        assert_eq!(
+            names(&TriadOptions::builder().line_width(3.0).build()),
+            ["x", "y", "z"]
+        );
+        let options = TriadOptions::builder()
+            .arrows(true)
+            .label_height(0.1)
+            .build();
+        let objects = triad_objects(&options);
+        assert_eq!(
+            names(&options),
+            ["x", "y", "z", "x_label", "y_label", "z_label"]
+        );
+        // The cone of the x arrow ends at its length
+        let cone = &objects[0].1.geometries[1];
+        assert!((cone.origin.translation.x - 0.45).abs() < 1e-9);
+        assert!((cone.origin.rotation * Vector3::z() - Vector3::x()).norm() < 1e-9);
+        assert!(matches!(
+            objects[5].1.object.object_type,
+            ObjectType::Sprite
+        ));
+        assert!((objects[5].1.object.matrix[(2, 3)] - 0.6).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_polyline() {
+        let points = Matrix3xX::from_columns(&[Vector3::zeros(), Vector3::x(), Vector3::y()]);
+        let value = serde_json::to_value(polyline(points.clone(), 0xff0000, 2.0)).unwrap();
+        assert_eq!(value["object"]["children"][0]["type"], "Line");
+        assert_eq!(value["materials"][0]["type"], "LineBasicMaterial");
+        assert_eq!(value["materials"][0]["linewidth"], 2.0);
+        let value = serde_json::to_value(polygon(points, 0xff0000, 1.0)).unwrap();
+        assert_eq!(value["object"]["children"][0]["type"], "LineLoop");
+    }
+
+    #[test]
+    fn test_fat_line() {
+        let points = Matrix3xX::from_columns(&[
+            Vector3::new(0.0, 0.0, 0.0),
+            Vector3::new(1.0, 0.0, 0.0),
+            Vector3::new(1.0, 1.0, 0.0),
+        ]);
+        let line = fat_line(&points, 0xff0000, 4.0);
+        let GeometryType::Buffer { data } = &line.geometries[0].geometry else {
+            panic!("Expected a buffer geometry");
+        };
+        let attributes = &data.attributes;
+        assert_eq!(attributes.position.to_matrix().ncols(), 12);
+        let others = &attributes.normal.as_ref().unwrap().to_matrix();
+        for vertex in 0..12 {
+            let segment = vertex / 6;
+            let (start, end) = (points.column(segment), points.column(segment + 1));
+            let position = attributes.position.to_matrix().column(vertex).into_owned();
+            assert!(position == start || position == end);
+            assert_eq!(
+                others.column(vertex),
+                if position == start { end } else { start }
+            );
+        }
+        // A single point has no segments
+        let point = fat_line(&points.columns(0, 1).into_owned(), 0, 4.0);
+        assert!(point.geometries[0].geometry.bounding_box().is_none());
+    }
+
+    #[test]
+    fn test_rounded_box() {
+        let size = Vector3::new(1.0, 2.0, 3.0);
+        let (positions, normals) = rounded_box_triangles(size, 0.25, 3);
+        // 6 faces of 5 x 5 quads
+        assert_eq!(positions.len(), 6 * 25 * 6);
+        let bounding_box = BoundingBox::from_points(
+            &positions
+                .iter()
+                .map(|position| nalgebra::Point3::from(*position))
+                .collect::<Vec<_>>(),
+        )
+        .unwrap();
+        assert!((bounding_box.max.coords - size / 2.0).norm() < 1e-9);
+        assert!((bounding_box.min.coords + size / 2.0).norm() < 1e-9);
+        for triangle in positions.chunks(3).zip(normals.chunks(3)) {
+            let (points, normals) = triangle;
+            let face_normal = (points[1] - points[0]).cross(&(points[2] - points[0]));
+            // Outwards, with unit vertex normals
+            assert!(face_normal.dot(&normals[0]) >= -1e-12);
+            for normal in normals {
+                assert!((normal.norm() - 1.0).abs() < 1e-9);
+            }
+        }
+
+        assert!(rounded_box(size, 0.6, 4).is_err());
+        assert!(rounded_box(size, 0.5, 4).is_ok());
+    }
+
+    #[test]
+    fn test_label() {
+        let (text, background) = label("Hello", &LabelOptions::default());
+        assert!(text.texture.is_some());
+        assert!(background.is_none());
+
+        let options = LabelOptions::builder()
+            .size(2.56)
+            .font_size(20)
+            .background_color(0xffffff)
+            .padding(0.1)
+            .offset(Vector3::new(0.0, 0.0, 1.0))
+            .build();
+        let (text, background) = label("Hello", &options);
+        let background = background.unwrap();
+        assert!(background.texture.is_none());
+        assert_eq!(background.material.color, Some(0xffffff));
+        if let GeometryType::Plane { width, height, .. } = background.geometries[0].geometry {
+            assert!((width - 0.8).abs() < 1e-9);
+            assert!((height - 0.4).abs() < 1e-9);
+        } else {
+            panic!("Expected a plane geometry");
+        }
+        assert_eq!(background.object.matrix[(2, 3)], 1.0);
+        assert!(text.object.matrix[(2, 3)] > 1.0);
+    }
+
+    #[test]
+    fn test_billboard() {
+        let object = billboard(text_plane("base_link", 0.1, None)).unwrap();
+        assert!(object.geometries.is_empty());
+        assert!(matches!(object.object.object_type, ObjectType::Sprite));
+        assert!(matches!(
+            object.material.material_type,
+            MaterialType::Sprite { .. }
+        ));
+        let value = serde_json::to_value(&object).unwrap();
+        assert_eq!(value["materials"][0]["map"], value["textures"][0]["uuid"]);
+        assert_eq!(value["materials"][0]["alphaTest"], 0.5);
+        assert!(billboard(triad(Isometry3::identity())).is_err());
+
+        let options = LabelOptions::builder()
+            .background_color(0xffffff)
+            .offset(Vector3::new(0.0, 0.0, 1.0))
+            .billboard(true)
+            .build();
+        let (text, background) = label("Hello", &options);
+        let background = background.unwrap();
+        assert!(matches!(text.object.object_type, ObjectType::Sprite));
+        assert!(matches!(background.object.object_type, ObjectType::Sprite));
+        assert_eq!(background.object.matrix[(2, 3)], 1.0);
+        assert_eq!(text.object.matrix[(0, 0)], 1.0);
+        let value = serde_json::to_value(&text.material).unwrap();
+        assert_eq!(value["depthTest"], false);
+        let value = serde_json::to_value(&background.material).unwrap();
+        assert!(value.get("depthTest").is_none());
+    }
+
+    #[test]
+    fn test_text() {
+        let object = text("base_link", 0.1, &TextOptions::default()).unwrap();
+        let GeometryType::Plane { width, height, .. } = object.geometries[0].geometry else {
+            panic!("Expected a plane");
+        };
+        assert_eq!(width, height);
+        // 9 characters fit in the canvas at 47 px
+        assert!((width - 0.1 * 256.0 / (256.0 / 5.4)).abs() < 1e-9);
+        let value = serde_json::to_value(&object.material).unwrap();
+        assert_eq!(value["alphaTest"], 0.5);
+        // A short text keeps the font size
+        let GeometryType::Plane { width, .. } = text_plane("x", 0.1, None).geometries[0].geometry
+        else {
+            panic!("Expected a plane");
+        };
+        assert!((width - 0.1 * 256.0 / 64.0).abs() < 1e-9);
+
+        let options = TextOptions::builder().color(0xff0000).build();
+        let object = text("base_link", 0.1, &options).unwrap();
+        assert!(matches!(
+            object.material.material_type,
+            MaterialType::MeshLambert
+        ));
+        let value = serde_json::to_value(&object.material).unwrap();
+        assert_eq!(value["emissive"], 0xff0000);
+        let options = TextOptions::builder().billboard(true).build();
+        let object = text("base_link", 0.1, &options).unwrap();
+        assert!(matches!(object.object.object_type, ObjectType::Sprite));
+        let options = TextOptions::builder()
+            .color(0xff0000)
+            .billboard(true)
+            .build();
+        assert!(text("base_link", 0.1, &options).is_err());
+    }
+
+    #[test]
+    fn test_ruler() {
+        let (lines, label) = ruler(Vector3::zeros(), Vector3::new(1.05, 0.0, 0.0), 0.1).unwrap();
+        let GeometryType::Buffer { data } = &lines.geometries[0].geometry else {
+            panic!("Expected a buffer geometry");
+        };
+        // The line, 11 ticks and one at the end
+        assert_eq!(data.attributes.position.to_matrix().ncols(), 2 + 2 * 12);
+        let tick = data.attributes.position.to_matrix().column(3)
+            - data.attributes.position.to_matrix().column(2);
+        assert!(tick.dot(&Vector3::x()).abs() < 1e-9);
+        if let Some(Texture {
+            texture_type: TextureType::Text { text, .. },
+            ..
+        }) = label.texture
+        {
+            assert_eq!(text, "1.050 m");
+        } else {
+            panic!("Expected a text texture");
+        }
+        assert!(ruler(Vector3::zeros(), Vector3::z(), 0.0).is_err());
+        // Vertical lines get ticks along the y axis
+        let (lines, _) = ruler(Vector3::zeros(), Vector3::z(), 0.5).unwrap();
+        let GeometryType::Buffer { data } = &lines.geometries[0].geometry else {
+            panic!("Expected a buffer geometry");
+        };
+        assert_eq!(data.attributes.position.to_matrix().ncols(), 2 + 2 * 3);
+        let tick = data.attributes.position.to_matrix().column(3)
+            - data.attributes.position.to_matrix().column(2);
+        assert!(tick.norm() > 0.0);
+        assert!(tick.dot(&Vector3::z()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_obb() {
+        let object = obb(
+            Vector3::new(1.0, 2.0, 3.0),
+            Vector3::new(0.5, 1.0, 1.5),
+            UnitQuaternion::identity(),
+        );
+        let GeometryType::Buffer { data } = &object.geometries[0].geometry else {
+            panic!("Expected a buffer geometry");
+        };
+        let points = &data.attributes.position.to_matrix();
+        assert_eq!(points.ncols(), 24);
+        for edge in points.column_iter().collect::<Vec<_>>().chunks(2) {
+            let length = (edge[1] - edge[0]).norm();
+            assert!([1.0, 2.0, 3.0].iter().any(|l| (l - length).abs() < 1e-9));
+        }
+        assert_eq!(object.object.matrix[(1, 3)], 2.0);
+
+        let rotation = UnitQuaternion::from_euler_angles(0.3, -0.2, 0.7);
+        let center = Vector3::new(1.0, -1.0, 0.5);
+        let half_extents = Vector3::new(3.0, 2.0, 1.0);
+        let points = Matrix3xX::from_columns(
+            &box_edges(&half_extents)
+                .column_iter()
+                .map(|corner| center + rotation * corner)
+                .collect::<Vec<_>>(),
+        );
+        let (fitted_pose, fitted_half_extents) = fit_obb(&points).unwrap();
+        assert!(fitted_pose
+            .translation
+            .vector
+            .relative_eq(&center, 1e-6, 1e-6));
+        let mut sorted = fitted_half_extents.as_slice().to_vec();
+        sorted.sort_by(|a, b| b.partial_cmp(a).unwrap());
+        assert!(Vector3::from_vec(sorted).relative_eq(&half_extents, 1e-6, 1e-6));
+        for corner in points.column_iter() {
+            let local = fitted_pose.inverse_transform_vector(&(corner - center));
+            assert!(local.abs() <= fitted_half_extents.add_scalar(1e-6));
+        }
+        assert!(obb_of_points(&Matrix3xX::zeros(0)).is_err());
+    }
+
+    #[test]
+    fn test_voxels() {
+        let centers = Matrix3xX::from_columns(&[Vector3::zeros(), Vector3::new(1.0, 0.0, 0.0)]);
+        let colors = Matrix3xX::from_columns(&[Vector3::x(), Vector3::y()]);
+        let object = voxels(&centers, 0.5, &colors).unwrap();
+        let GeometryType::Buffer { data } = &object.geometries[0].geometry else {
+            panic!("Expected a buffer geometry");
+        };
+        let positions = &data.attributes.position.to_matrix();
+        let normals = &data.attributes.normal.as_ref().unwrap().to_matrix();
+        assert_eq!(positions.ncols(), 2 * 36);
+        assert_eq!(positions.column(36), Vector3::new(0.75, -0.25, -0.25));
+        assert_eq!(data.attributes.color.to_matrix().column(36), Vector3::y());
+        // Counterclockwise seen from outside
+        for triangle in 0..24 {
+            let [a, b, c] = [0, 1, 2].map(|i| positions.column(3 * triangle + i).into_owned());
+            let normal = (b - a).cross(&(c - a)).normalize();
+            assert_eq!(normal, normals.column(3 * triangle).into_owned());
+        }
+        assert!(voxels(&centers, 0.5, &Matrix3xX::zeros(1)).is_err());
+    }
+
+    #[test]
+    fn test_octree() {
+        let object = octree(
+            &[
+                (Vector3::zeros(), 0),
+                (Vector3::new(0.5, 0.5, 0.5), 1),
+                (Vector3::new(0.75, 0.75, 0.75), 2),
+            ],
+            2.0,
+        );
+        let GeometryType::Buffer { data } = &object.geometries[0].geometry else {
+            panic!("Expected a buffer geometry");
+        };
+        let points = &data.attributes.position.to_matrix();
+        let colors = &data.attributes.color.to_matrix();
+        assert_eq!(points.ncols(), 3 * 24);
+        assert_eq!(points.column(0).abs(), Vector3::repeat(1.0));
+        assert_eq!(
+            (points.column(24) - Vector3::repeat(0.5)).abs(),
+            Vector3::repeat(0.5)
+        );
+        assert_eq!(colors.column(0), Vector3::new(0.0, 0.0, 1.0));
+        assert_eq!(colors.column(48), Vector3::new(1.0, 0.0, 0.0));
+        assert!(octree(&[], 1.0).geometries.len() == 1);
+    }
+
+    #[test]
+    fn test_normals() {
+        let points = Matrix3xX::from_columns(&[Vector3::zeros(), Vector3::x()]);
+        let directions = Matrix3xX::from_columns(&[Vector3::new(0.0, 0.0, 2.0), Vector3::zeros()]);
+        let object = normals(&points, &directions, 0.1, 0xff8000).unwrap();
+        let GeometryType::Buffer { data } = &object.geometries[0].geometry else {
+            panic!("Expected a buffer geometry");
+        };
+        let segments = &data.attributes.position.to_matrix();
+        assert_eq!(segments.ncols(), 4);
+        assert!(segments
+            .column(1)
+            .relative_eq(&Vector3::new(0.0, 0.0, 0.1), 1e-6, 1e-6));
+        // Zero normals are degenerate segments
+        assert_eq!(segments.column(3), Vector3::x());
+        assert!(data.attributes.color.to_matrix().column(0).relative_eq(
+            &Vector3::new(1.0, 128.0 / 255.0, 0.0),
+            1e-6,
+            1e-6
+        ));
+        assert!(normals(&points, &Matrix3xX::zeros(1), 0.1, 0).is_err());
+    }
+
+    #[test]
+    fn test_arc() {
+        let (lines, label) = arc(
+            Vector3::zeros(),
+            Vector3::z(),
+            Vector3::new(1.0, 0.0, 1.0),
+            Vector3::new(0.0, -2.0, 0.0),
+            0.5,
+        )
+        .unwrap();
+        let GeometryType::Buffer { data } = &lines.geometries[0].geometry else {
+            panic!("Expected a buffer geometry");
+        };
+        let points = &data.attributes.position.to_matrix();
+        assert_eq!(points.ncols() % 2, 0);
+        assert!(points
+            .column(1)
+            .relative_eq(&Vector3::new(0.5, 0.0, 0.0), 1e-9, 1e-9));
+        assert!(points.column(points.ncols() - 1).relative_eq(
+            &Vector3::new(0.0, -0.5, 0.0),
+            1e-9,
+            1e-9
+        ));
+        let Some(Texture {
+            texture_type: TextureType::Text { text, .. },
+            ..
+        }) = label.texture
+        else {
+            panic!("Expected a text texture");
+        };
+        assert_eq!(text, "-90.0°");
+        assert!(arc(
+            Vector3::zeros(),
+            Vector3::z(),
+            Vector3::z(),
+            Vector3::x(),
+            1.0
+        )
+        .is_err());
+        assert!(arc(
+            Vector3::zeros(),
+            Vector3::zeros(),
+            Vector3::y(),
+            Vector3::x(),
+            1.0
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_screen() {
+        let image = Image::new("examples/data/HeadTextureMultisense.png").unwrap();
+        let image_uuid = image.uuid;
+        let screen = screen(image, 1.6, 0.9);
+        let texture = screen.texture.unwrap();
+        assert_eq!(screen.material.map, Some(texture.uuid));
+        if let TextureType::Image { image, .. } = texture.texture_type {
+            assert_eq!(image, Some(image_uuid));
+        } else {
+            panic!("Expected an image texture");
+        }
+    }
+
+    #[test]
+    fn test_sprite() {
+        let marker = sprite(None, 0xff0000, 0.1);
+        assert!(marker.object.children.is_empty());
+        assert!(marker.material.map.is_none());
+        let value = serde_json::to_value(&marker).unwrap();
+        assert_eq!(value["object"]["type"], "Sprite");
+        assert_eq!(value["object"]["matrix"][0], 0.1);
+        assert_eq!(value["materials"][0]["type"], "SpriteMaterial");
+        assert_eq!(value["materials"][0]["sizeAttenuation"], true);
+
+        let icon = sprite(
+            Some(Image::new("examples/data/HeadTextureMultisense.png").unwrap()),
+            0xffffff,
+            1.0,
+        );
+        assert_eq!(icon.material.map, Some(icon.texture.unwrap().uuid));
+    }
+
+    #[test]
+    fn test_geometry_registry() {
+        let mut registry = GeometryRegistry::new();
+        let wheels = (0..4)
+            .map(|index| {
+                registry
+                    .mesh_with_origin(
+                        "examples/data/mesh_0_convex_piece_0.obj",
+                        Isometry3::translation(index as f64, 0.0, 0.0),
+                    )
+                    .unwrap()
+            })
+            .collect::<Vec<_>>();
+        assert!(wheels.iter().all(|wheel| wheel.uuid == wheels[0].uuid));
+        let car = LumpedObject::builder().geometries(wheels).build();
+        assert_eq!(car.object.children.len(), 4);
+        assert_eq!(car.object.children[3].matrix[(0, 3)], 3.0);
+        let value = serde_json::to_value(&car).unwrap();
+        assert_eq!(value["geometries"].as_array().unwrap().len(), 1);
+        assert!(registry.mesh("missing.obj").is_err());
+    }
+
+    #[test]
+    fn test_obj_materials() {
+        let directory =
+            std::env::temp_dir().join(format!("meshcat-obj-{}", crate::types::new_uuid()));
+        std::fs::create_dir_all(directory.join("textures")).unwrap();
+        std::fs::write(directory.join("textures/wood.png"), [1, 2]).unwrap();
+        std::fs::write(
+            directory.join("box.mtl"),
+            "newmtl wood\nKd 1 0 0\nmap_Kd -s 2 2 1 textures/wood.png\n",
+        )
+        .unwrap();
+        let obj = directory.join("box.obj");
+        std::fs::write(
+            &obj,
+            "mtllib box.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\nusemtl wood\nf 1 2 3\n",
+        )
+        .unwrap();
+        let value = serde_json::to_value(mesh_file(obj.to_str().unwrap()).unwrap()).unwrap();
+        assert_eq!(value["object"]["format"], "obj");
+        assert!(value["object"]["mtl_library"]
+            .as_str()
+            .unwrap()
+            .starts_with("newmtl wood"));
+        assert_eq!(
+            value["object"]["resources"]["textures/wood.png"],
+            "data:image/png;base64,AQI="
+        );
+
+        // Without .mtl files the obj file is sent as is
+        let obj = directory.join("plain.obj");
+        std::fs::write(&obj, "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n").unwrap();
+        let value = serde_json::to_value(mesh_file(obj.to_str().unwrap()).unwrap()).unwrap();
+        assert!(value["object"].get("mtl_library").is_none());
+        assert!(value["object"].get("resources").is_none());
+        std::fs::write(&obj, "mtllib missing.mtl\n").unwrap();
+        assert!(mesh_file(obj.to_str().unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_gltf_meshes() {
+        let directory =
+            std::env::temp_dir().join(format!("meshcat-gltf-{}", crate::types::new_uuid()));
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(directory.join("mesh.bin"), [1, 2, 3]).unwrap();
+        std::fs::write(directory.join("texture.png"), [4, 5]).unwrap();
+        let json = r#"{"buffers": [{"uri": "mesh.bin"}], "images": [{"uri": "texture.png"}]}"#;
+        let gltf = directory.join("mesh.gltf");
+        std::fs::write(&gltf, json).unwrap();
+        let object = mesh_file(gltf.to_str().unwrap()).unwrap();
+        let value = serde_json::to_value(&object).unwrap();
+        assert_eq!(value["object"]["type"], "_meshfile_object");
+        assert_eq!(value["object"]["format"], "gltf");
+        let data: serde_json::Value =
+            serde_json::from_str(value["object"]["data"].as_str().unwrap()).unwrap();
+        assert_eq!(
+            data["buffers"][0]["uri"],
+            "data:application/octet-stream;base64,AQID"
+        );
+        assert_eq!(data["images"][0]["uri"], "data:image/png;base64,BAU=");
+
+        // 12 bytes header then the JSON and BIN chunks
+        let json = br#"{"buffers": [{"byteLength": 3}]} "#;
+        let mut glb = b"glTF".to_vec();
+        glb.extend(2u32.to_le_bytes());
+        glb.extend(((12 + 8 + json.len() + 8 + 4) as u32).to_le_bytes());
+        glb.extend((json.len() as u32).to_le_bytes());
+        glb.extend(b"JSON");
+        glb.extend(json);
+        glb.extend(4u32.to_le_bytes());
+        glb.extend(b"BIN\0");
+        glb.extend([1, 2, 3, 0]);
+        let path = directory.join("mesh.glb");
+        std::fs::write(&path, &glb).unwrap();
+        let object = mesh_file(path.to_str().unwrap()).unwrap();
+        let value = serde_json::to_value(&object).unwrap();
+        assert_eq!(value["object"]["type"], "_meshfile_object");
+        assert_eq!(value["object"]["format"], "gltf");
+        let data: serde_json::Value =
+            serde_json::from_str(value["object"]["data"].as_str().unwrap()).unwrap();
+        assert_eq!(
+            data["buffers"][0]["uri"],
+            "data:application/octet-stream;base64,AQIDAA=="
+        );
+        // The viewer can't load them as geometries, even when they don't exist
+        for path in [&gltf, &path, &directory.join("missing.glb")] {
+            assert!(matches!(
+                load_mesh(path.to_str().unwrap()),
+                Err(crate::Error::UnsupportedFormat(_))
+            ));
+        }
+        std::fs::write(&path, &glb[4..]).unwrap();
+        assert!(matches!(
+            mesh_file(path.to_str().unwrap()),
+            Err(crate::Error::Io { .. })
+        ));
+    }
 }
@@ -0,0 +1,157 @@
+use std::collections::{BTreeSet, HashMap};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver};
+use std::time::Duration;
+
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+
+use super::types::*;
+
+type Reload = Box<dyn Fn(&Meshcat) -> Result<(), Box<dyn Error>>>;
+
+// Re-publishes files when they change on disk, call poll (or run) to apply the changes.
+// The directories of the files are watched, since editors often save by replacing the file
+pub struct HotReloader {
+    watcher: RecommendedWatcher,
+    events: Receiver<notify::Result<notify::Event>>,
+    reloads: HashMap<PathBuf, Vec<Reload>>,
+    directories: BTreeSet<PathBuf>,
+}
+
+impl HotReloader {
+    pub fn new() -> Result<Self, Box<dyn Error>> {
+        let (sender, events) = channel();
+        Ok(Self {
+            watcher: notify::recommended_watcher(sender)?,
+            events,
+            reloads: HashMap::new(),
+            directories: BTreeSet::new(),
+        })
+    }
+
+    // Calls reload every time the file changes
+    pub fn watch(
+        &mut self,
+        file: &str,
+        reload: impl Fn(&Meshcat) -> Result<(), Box<dyn Error>> + 'static,
+    ) -> Result<(), Box<dyn Error>> {
+        let file = std::fs::canonicalize(file)?;
+        let directory = file
+            .parent()
+            .ok_or_else(|| format!("'{}' has no parent directory", file.display()))?
+            .to_path_buf();
+        if !self.directories.contains(&directory) {
+            self.watcher
+                .watch(&directory, RecursiveMode::NonRecursive)?;
+            self.directories.insert(directory);
+        }
+        self.reloads.entry(file).or_default().push(Box::new(reload));
+        Ok(())
+    }
+
+    // Publishes the mesh at path with the given material
+    pub fn watch_mesh(
+        &mut self,
+        file: &str,
+        path: &str,
+        material: Material,
+    ) -> Result<(), Box<dyn Error>> {
+        let (file, path) = (file.to_string(), path.to_string());
+        self.watch(&file.clone(), move |meshcat| {
+            meshcat.set_object(
+                &path,
+                LumpedObject::builder()
+                    .geometries(vec![Geometry::new(crate::utils::load_mesh(&file)?)])
+                    .material(material.clone())
+                    .build(),
+            )
+        })
+    }
+
+    // See UrdfVisualizer::load, the joints are back at their origins after a reload
+    pub fn watch_urdf(&mut self, file: &str) -> Result<(), Box<dyn Error>> {
+        let file = file.to_string();
+        self.watch(&file.clone(), move |meshcat| {
+            crate::urdf::UrdfVisualizer::from_file(&file)?.load(meshcat)
+        })
+    }
+
+    // See Meshcat::load_scene, only the objects that changed are re-sent
+    pub fn watch_scene(&mut self, file: &str) -> Result<(), Box<dyn Error>> {
+        let file = file.to_string();
+        self.watch(&file.clone(), move |meshcat| meshcat.load_scene(&file))
+    }
+
+    // Reloads the files changed since the last call, waiting up to timeout for a first change
+    pub fn poll(&self, meshcat: &Meshcat, timeout: Duration) -> Result<(), Box<dyn Error>> {
+        for file in self.changed_files(timeout)? {
+            for reload in &self.reloads[&file] {
+                reload(meshcat)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Reloads the files as they change, only returns on errors
+    pub fn run(&self, meshcat: &Meshcat) -> Result<(), Box<dyn Error>> {
+        loop {
+            self.poll(meshcat, Duration::from_secs(1))?;
+        }
+    }
+
+    // A save usually triggers several events, each file is only returned once
+    fn changed_files(&self, timeout: Duration) -> Result<BTreeSet<PathBuf>, Box<dyn Error>> {
+        let mut changed = BTreeSet::new();
+        let Ok(event) = self.events.recv_timeout(timeout) else {
+            return Ok(changed);
+        };
+        for event in std::iter::once(event).chain(self.events.try_iter()) {
+            let event = event?;
+            if event.kind.is_access() {
+                continue;
+            }
+            changed.extend(event.paths.into_iter().filter(|path| self.is_watched(path)));
+        }
+        Ok(changed)
+    }
+
+    fn is_watched(&self, path: &Path) -> bool {
+        self.reloads.contains_key(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_changed_files() {
+        let directory =
+            std::env::temp_dir().join(format!("meshcat-watch-{}", crate::types::new_uuid()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let watched = directory.join("scene.yaml");
+        let other = directory.join("other.yaml");
+        std::fs::write(&watched, "objects: {}").unwrap();
+
+        let mut reloader = HotReloader::new().unwrap();
+        reloader
+            .watch(watched.to_str().unwrap(), |_| Ok(()))
+            .unwrap();
+        assert!(reloader
+            .changed_files(Duration::from_millis(100))
+            .unwrap()
+            .is_empty());
+
+        std::fs::write(&other, "objects: {}").unwrap();
+        std::fs::write(&watched, "objects: {}").unwrap();
+        std::fs::write(&watched, "objects: {}").unwrap();
+        std::thread::sleep(Duration::from_millis(200));
+        let changed = reloader.changed_files(Duration::from_secs(5)).unwrap();
+        assert_eq!(
+            changed,
+            BTreeSet::from([std::fs::canonicalize(&watched).unwrap()])
+        );
+        assert!(reloader.watch("missing.yaml", |_| Ok(())).is_err());
+    }
+}